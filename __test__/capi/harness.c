@@ -0,0 +1,165 @@
+/* Drives the C ABI against the synthetic backend. Prints one `key value` line per check and exits
+ * with 1 on the first failure. */
+
+#define _POSIX_C_SOURCE 199309L
+
+#include <stdatomic.h>
+#include <stdio.h>
+#include <string.h>
+#include <time.h>
+
+#include "rs_capture.h"
+
+#define CHECK(cond)                                                   \
+  do {                                                                \
+    if (!(cond)) {                                                    \
+      const char *reason = rs_capture_last_error();                   \
+      fprintf(stderr, "%s:%d: %s (%s)\n", __FILE__, __LINE__, #cond,  \
+              reason ? reason : "no error");                          \
+      return 1;                                                       \
+    }                                                                 \
+  } while (0)
+
+typedef struct {
+  atomic_uint frames;
+  atomic_uint out_of_order;
+  atomic_uint skipped;
+  atomic_ullong dropped;
+  atomic_uint wrong_size;
+  atomic_uint wrong_display;
+  atomic_ullong next_sequence;
+} Counter;
+
+static void sleep_ms(long ms) {
+  struct timespec duration = {ms / 1000, (ms % 1000) * 1000000L};
+  nanosleep(&duration, NULL);
+}
+
+static void on_frame(RsCaptureFrame *frame, void *user_data) {
+  Counter *counter = user_data;
+  unsigned long long expected = atomic_load(&counter->next_sequence);
+  if (frame->sequence < expected) {
+    atomic_fetch_add(&counter->out_of_order, 1);
+  } else if (frame->sequence > expected) {
+    atomic_fetch_add(&counter->skipped, 1);
+  }
+  atomic_fetch_add(&counter->dropped, frame->dropped_since_last);
+  atomic_store(&counter->next_sequence, frame->sequence + 1);
+  if (frame->width != 64 || frame->height != 4 || frame->data_len != 64 * 4 * 4) {
+    atomic_fetch_add(&counter->wrong_size, 1);
+  }
+  /* The synthetic display has id 1. */
+  if (!frame->has_display_id || frame->display_id != 1) {
+    atomic_fetch_add(&counter->wrong_display, 1);
+  }
+  atomic_fetch_add(&counter->frames, 1);
+  rs_capture_frame_release(frame);
+}
+
+/* A callback slower than the 100 fps capture. */
+static void on_frame_slowly(RsCaptureFrame *frame, void *user_data) {
+  sleep_ms(20);
+  on_frame(frame, user_data);
+}
+
+/* Runs a session with `callback` for 300 ms and checks that it ends with `rs_capture_stop`. */
+static int run(const RsCaptureConfig *config, RsCaptureFrameCallback callback, Counter *counter) {
+  memset(counter, 0, sizeof(*counter));
+  RsCapture *capture = NULL;
+  CHECK(rs_capture_create(config, callback, counter, &capture) == RS_CAPTURE_STATUS_OK);
+  CHECK(rs_capture_start(capture) == RS_CAPTURE_STATUS_OK);
+  sleep_ms(300);
+  CHECK(rs_capture_stop(capture) == RS_CAPTURE_STATUS_OK);
+  unsigned frames = atomic_load(&counter->frames);
+  sleep_ms(100);
+  CHECK(atomic_load(&counter->frames) == frames);
+  CHECK(frames > 0);
+  CHECK(atomic_load(&counter->out_of_order) == 0);
+  CHECK(atomic_load(&counter->wrong_size) == 0);
+  rs_capture_destroy(capture);
+  return 0;
+}
+
+int main(void) {
+  CHECK(rs_capture_abi_version() == RS_CAPTURE_ABI_VERSION);
+
+  RsCaptureConfig config;
+  memset(&config, 0, sizeof(config));
+  config.backend = RS_CAPTURE_BACKEND_SYNTHETIC;
+  config.fps = 100;
+  config.synthetic.width = 64;
+  config.synthetic.height = 4;
+
+  Counter counter;
+  memset(&counter, 0, sizeof(counter));
+  RsCapture *capture = NULL;
+  CHECK(rs_capture_create(&config, on_frame, &counter, &capture) == RS_CAPTURE_STATUS_OK);
+  CHECK(capture != NULL);
+
+  CHECK(rs_capture_start(capture) == RS_CAPTURE_STATUS_OK);
+  sleep_ms(300);
+  CHECK(rs_capture_stop(capture) == RS_CAPTURE_STATUS_OK);
+  unsigned frames = atomic_load(&counter.frames);
+  sleep_ms(100);
+  CHECK(atomic_load(&counter.frames) == frames);
+  CHECK(frames > 0);
+  CHECK(atomic_load(&counter.out_of_order) == 0);
+  CHECK(atomic_load(&counter.wrong_size) == 0);
+  CHECK(atomic_load(&counter.wrong_display) == 0);
+  printf("frames %u\n", frames);
+
+  /* The default backpressure drops frames for a slow callback instead of slowing the capture. */
+  CHECK(run(&config, on_frame_slowly, &counter) == 0);
+  CHECK(atomic_load(&counter.skipped) > 0 && atomic_load(&counter.dropped) > 0);
+  printf("dropped %llu\n", (unsigned long long)atomic_load(&counter.dropped));
+
+  config.backpressure = RS_CAPTURE_BACKPRESSURE_BLOCK;
+  config.max_queued_frames = 1;
+  CHECK(run(&config, on_frame_slowly, &counter) == 0);
+  CHECK(atomic_load(&counter.skipped) == 0 && atomic_load(&counter.dropped) == 0);
+  printf("blocked %u\n", atomic_load(&counter.frames));
+  config.backpressure = RS_CAPTURE_BACKPRESSURE_DROP_OLDEST;
+  config.max_queued_frames = 0;
+
+  /* Display 0 is a display id like any other, not the primary display. */
+  config.has_display_id = true;
+  config.display_id = 0;
+  RsCapture *missing = NULL;
+  CHECK(rs_capture_create(&config, on_frame, &counter, &missing) == RS_CAPTURE_STATUS_OK);
+  CHECK(rs_capture_start(missing) == RS_CAPTURE_STATUS_INVALID_ARG);
+  printf("display %s\n", rs_capture_last_error());
+  rs_capture_destroy(missing);
+  config.display_id = 1;
+  CHECK(run(&config, on_frame, &counter) == 0);
+  CHECK(atomic_load(&counter.wrong_display) == 0);
+  config.has_display_id = false;
+
+  RsCaptureFrame *frame = NULL;
+  CHECK(rs_capture_screenshot(capture, &frame) == RS_CAPTURE_STATUS_OK);
+  CHECK(frame->width == 64 && frame->height == 4 && frame->stride == 64 * 4);
+  CHECK(frame->format == RS_CAPTURE_PIXEL_FORMAT_RGBA);
+  CHECK(frame->data != NULL && frame->data_len == 64 * 4 * 4);
+  printf("screenshot %ux%u\n", frame->width, frame->height);
+  rs_capture_destroy(capture);
+  /* Frames outlive their session. */
+  CHECK(frame->data[3] == 255);
+  rs_capture_frame_release(frame);
+
+  config.fps = 0;
+  config.pixel_format = RS_CAPTURE_PIXEL_FORMAT_I420;
+  config.output_width = 32;
+  CHECK(rs_capture_create(&config, NULL, NULL, &capture) == RS_CAPTURE_STATUS_OK);
+  CHECK(rs_capture_screenshot(capture, &frame) == RS_CAPTURE_STATUS_OK);
+  CHECK(frame->width == 32 && frame->height == 2);
+  CHECK(frame->format == RS_CAPTURE_PIXEL_FORMAT_I420 && frame->data_len == 32 * 2 * 3 / 2);
+  printf("i420 %ux%u\n", frame->width, frame->height);
+  rs_capture_frame_release(frame);
+  rs_capture_destroy(capture);
+
+  config.backend = 42;
+  CHECK(rs_capture_create(&config, NULL, NULL, &capture) == RS_CAPTURE_STATUS_INVALID_ARG);
+  CHECK(capture == NULL);
+  printf("error %s\n", rs_capture_last_error());
+
+  return 0;
+}
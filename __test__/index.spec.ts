@@ -1,5 +1,36 @@
+import { execFileSync, spawnSync } from 'node:child_process'
+import { copyFileSync, existsSync, mkdtempSync, readFileSync, readdirSync } from 'node:fs'
+import { tmpdir } from 'node:os'
+import { join } from 'node:path'
+import { fileURLToPath } from 'node:url'
+
 import test from 'ava'
-import { ScreenCapture } from '../index.mjs'
+import {
+  Backpressure,
+  CaptureBackend,
+  CaptureState,
+  ClipFormat,
+  ColorMatrix,
+  ColorRange,
+  DeltaDecoder,
+  DeltaEncoder,
+  DisplayChangeKind,
+  ErrorCode,
+  ImageFormat,
+  PixelFormat,
+  RecordingFormat,
+  ScaleFilter,
+  ScreenCapture,
+  TileCompression,
+  type CaptureError,
+  type DisplayChange,
+  type FrameData,
+} from '../index.mjs'
+
+const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms))
+
+// The synthetic backend stores the frame counter in the RGB channels of the top-left pixel.
+const frameCounter = (frame: FrameData) => (frame.rgba[0] << 16) | (frame.rgba[1] << 8) | frame.rgba[2]
 
 test('ScreenCapture: init', (t) => {
   const capturer = new ScreenCapture(() => {})
@@ -7,3 +38,1192 @@ test('ScreenCapture: init', (t) => {
   t.is(typeof capturer.start, 'function')
   t.is(typeof capturer.stop, 'function')
 })
+
+test('Synthetic: screenshot renders the test pattern', async (t) => {
+  const capturer = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    synthetic: { width: 64, height: 4 },
+  })
+  const frame = await capturer.screenshot()
+
+  t.is(frame.width, 64)
+  t.is(frame.height, 4)
+  t.is(frame.stride, 64 * 4)
+  t.is(frame.rgba.length, 64 * 4 * 4)
+  t.is(frameCounter(frame), 0)
+  // Bars are 8px wide: white, yellow, cyan, green, magenta, red, blue, black.
+  t.deepEqual([...frame.rgba.subarray(4, 8)], [255, 255, 255, 255])
+  t.deepEqual([...frame.rgba.subarray(8 * 4, 8 * 4 + 4)], [255, 255, 0, 255])
+  t.deepEqual([...frame.rgba.subarray(63 * 4, 64 * 4)], [0, 0, 0, 255])
+})
+
+test('Synthetic: frames are sequential and stop ends delivery', async (t) => {
+  const frames: FrameData[] = []
+  const capturer = new ScreenCapture((frame) => frames.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    synthetic: { width: 32, height: 2 },
+  })
+
+  await capturer.start()
+  await sleep(300)
+  capturer.stop()
+  await sleep(50)
+  const delivered = frames.length
+  await sleep(100)
+
+  t.true(delivered >= 5, `expected at least 5 frames, got ${delivered}`)
+  t.is(frames.length, delivered)
+  t.deepEqual(
+    frames.map(frameCounter),
+    frames.map((_, i) => i),
+  )
+})
+
+test('Synthetic: frames() applies backpressure and ends on stop', async (t) => {
+  const capturer = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    fps: 200,
+    backpressure: Backpressure.Block,
+    synthetic: { width: 16, height: 2 },
+  })
+  const iterator = capturer.frames()
+  await capturer.start()
+
+  // A slow consumer holds the capture loop back instead of losing frames.
+  const counters: number[] = []
+  for await (const frame of iterator) {
+    counters.push(frameCounter(frame))
+    await sleep(20)
+    if (counters.length === 5) break
+  }
+  t.deepEqual(
+    counters,
+    counters.map((_, i) => counters[0] + i),
+  )
+
+  const pending = (async () => {
+    let count = 0
+    for await (const _ of capturer.frames()) count++
+    return count
+  })()
+  await sleep(50)
+  capturer.stop()
+  t.true((await pending) > 0)
+})
+
+test('Synthetic: backpressure policies drop frames while JavaScript is busy', async (t) => {
+  const busy = (ms: number) => {
+    const end = Date.now() + ms
+    while (Date.now() < end) {}
+  }
+  const run = async (backpressure: Backpressure) => {
+    const frames: FrameData[] = []
+    const capturer = new ScreenCapture((frame) => frames.push(frame), {
+      backend: CaptureBackend.Synthetic,
+      fps: 100,
+      backpressure,
+      maxQueuedFrames: 2,
+      synthetic: { width: 8, height: 2 },
+    })
+    await capturer.start()
+    await sleep(50)
+    // Frames captured meanwhile have to wait in the queue.
+    busy(200)
+    await sleep(50)
+    capturer.stop()
+    await sleep(50)
+    const counters = frames.map(frameCounter)
+    const gaps = counters.slice(1).filter((counter, i) => counter !== counters[i] + 1)
+    return { stats: capturer.stats(), delivered: frames.length, gaps: gaps.length }
+  }
+
+  for (const backpressure of [Backpressure.DropOldest, Backpressure.DropNewest]) {
+    const { stats, delivered, gaps } = await run(backpressure)
+    t.true(stats.framesDropped > 0, backpressure)
+    t.is(stats.framesCaptured, delivered + stats.framesDropped)
+    t.true(gaps >= 1)
+  }
+
+  const { stats, delivered, gaps } = await run(Backpressure.Block)
+  t.is(stats.framesDropped, 0)
+  t.is(stats.framesCaptured, delivered)
+  t.is(gaps, 0)
+
+  t.throws(() => new ScreenCapture({ backend: CaptureBackend.Synthetic, maxQueuedFrames: 0 }), {
+    message: 'maxQueuedFrames must be greater than 0',
+  })
+})
+
+test('Synthetic: frame metadata', async (t) => {
+  const frames: FrameData[] = []
+  const capturer = new ScreenCapture((frame) => frames.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 100,
+    backpressure: Backpressure.DropOldest,
+    maxQueuedFrames: 2,
+    displayId: 2,
+    synthetic: { width: 8, height: 2, displays: 2 },
+  })
+  await capturer.start()
+  await sleep(50)
+  const end = Date.now() + 200
+  while (Date.now() < end) {}
+  await sleep(50)
+  capturer.stop()
+  await sleep(50)
+
+  t.is(frames[0].sequence, 0)
+  for (const [i, frame] of frames.entries()) {
+    t.is(frame.sequence, frameCounter(frame))
+    t.is(frame.displayId, 2)
+    t.true(frame.latency >= 0)
+    if (i > 0) {
+      t.is(frame.sequence, frames[i - 1].sequence + 1 + frame.droppedSinceLast)
+      t.true(frame.timestamp > frames[i - 1].timestamp)
+    }
+  }
+  const dropped = frames.reduce((sum, frame) => sum + frame.droppedSinceLast, 0)
+  t.true(dropped > 0)
+  t.is(dropped, capturer.stats().framesDropped)
+
+  // Sequence numbers restart with the session.
+  await capturer.start()
+  await sleep(50)
+  capturer.stop()
+  t.is(frames.filter((frame) => frame.sequence === 0).length, 2)
+
+  const window = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    windowId: 1,
+    synthetic: { width: 8, height: 2, windows: 1 },
+  })
+  const screenshot = await window.screenshot()
+  t.is(screenshot.displayId, undefined)
+  t.true(screenshot.timestamp > 0)
+})
+
+test('Synthetic: injected failures', async (t) => {
+  const failing = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    synthetic: { failOnStart: true, failScreenshot: true },
+  })
+  await t.throwsAsync(() => failing.start(), { message: 'Synthetic start failure' })
+  await t.throwsAsync(() => failing.screenshot(), { message: 'Synthetic screenshot failure' })
+
+  const frames: FrameData[] = []
+  const limited = new ScreenCapture((frame) => frames.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 100,
+    synthetic: { width: 8, height: 1, failAfterFrames: 3 },
+  })
+  await limited.start()
+  await sleep(200)
+  limited.stop()
+  t.is(frames.length, 3)
+})
+
+test('Synthetic: onError and onStateChange report the session lifecycle', async (t) => {
+  const capturer = new ScreenCapture(() => {}, {
+    backend: CaptureBackend.Synthetic,
+    fps: 100,
+    synthetic: { width: 8, height: 1, failAfterFrames: 3 },
+  })
+  const states: CaptureState[] = []
+  const errors: CaptureError[] = []
+  capturer.onStateChange((state) => states.push(state))
+  capturer.onError((error) => errors.push(error))
+  t.is(capturer.state, CaptureState.Stopped)
+
+  await capturer.start()
+  t.is(capturer.state, CaptureState.Running)
+  // The iterator ends when the session fails.
+  let count = 0
+  for await (const _ of capturer.frames()) count++
+  await sleep(20)
+  t.true(count <= 3)
+  t.is(capturer.state, CaptureState.Stopped)
+  t.deepEqual(states, [CaptureState.Starting, CaptureState.Running, CaptureState.Stopped])
+  t.deepEqual(errors, [
+    { code: ErrorCode.DisplayLost, message: 'Synthetic capture failed after 3 frames', fatal: true },
+  ])
+
+  // Errors raised before a listener exists are delivered to the first one.
+  const window = new ScreenCapture(() => {}, {
+    backend: CaptureBackend.Synthetic,
+    fps: 100,
+    windowId: 1,
+    synthetic: { width: 8, height: 2, windows: 1, failAfterFrames: 1 },
+  })
+  await window.start()
+  await sleep(50)
+  const late: CaptureError[] = []
+  window.onError((error) => late.push(error))
+  await sleep(20)
+  t.deepEqual(late, [{ code: ErrorCode.WindowClosed, message: 'Window 1 was closed', fatal: true }])
+
+  const failing = new ScreenCapture({ backend: CaptureBackend.Synthetic, synthetic: { failOnStart: true } })
+  const failingStates: CaptureState[] = []
+  failing.onStateChange((state) => failingStates.push(state))
+  await t.throwsAsync(() => failing.start())
+  await sleep(20)
+  t.deepEqual(failingStates, [CaptureState.Starting, CaptureState.Stopped])
+})
+
+test('Synthetic: listDisplays and displayId', async (t) => {
+  const config = { backend: CaptureBackend.Synthetic, synthetic: { width: 64, height: 4, displays: 2 } }
+  const displays = await ScreenCapture.listDisplays(config)
+
+  t.deepEqual(
+    displays.map((d) => [d.id, d.x, d.width, d.isPrimary]),
+    [
+      [1, 0, 64, true],
+      [2, 64, 64, false],
+    ],
+  )
+
+  // The second display starts one bar further along the pattern.
+  const frame = await new ScreenCapture({ ...config, displayId: 2 }).screenshot()
+  t.deepEqual([...frame.rgba.subarray(4, 8)], [255, 255, 0, 255])
+
+  await t.throwsAsync(() => new ScreenCapture({ ...config, displayId: 3 }).screenshot(), {
+    message: 'Display 3 not found',
+  })
+})
+
+test('Synthetic: region crops frames and setRegion moves it', async (t) => {
+  const config = {
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    synthetic: { width: 64, height: 4 },
+    region: { x: 8, y: 1, width: 16, height: 2 },
+  }
+
+  const shot = await new ScreenCapture(config).screenshot()
+  t.is(shot.width, 16)
+  t.is(shot.height, 2)
+  t.is(shot.rgba.length, 16 * 2 * 4)
+  // The region starts at the yellow bar and ends at the cyan one.
+  t.deepEqual([...shot.rgba.subarray(4, 8)], [255, 255, 0, 255])
+  t.deepEqual([...shot.rgba.subarray(8 * 4, 8 * 4 + 4)], [0, 255, 255, 255])
+
+  await t.throwsAsync(() => new ScreenCapture({ ...config, region: { x: 60, y: 0, width: 8, height: 1 } }).screenshot(), {
+    message: 'Region 8x1 at (60, 0) is outside the 64x4 display',
+  })
+
+  const frames: FrameData[] = []
+  const capturer = new ScreenCapture((frame) => frames.push(frame), config)
+  await capturer.start()
+  await sleep(100)
+  t.throws(() => capturer.setRegion({ x: 0, y: 0, width: 65, height: 1 }))
+  capturer.setRegion({ x: 0, y: 0, width: 4, height: 4 })
+  await sleep(100)
+  capturer.setRegion()
+  await sleep(100)
+  capturer.stop()
+
+  t.deepEqual([...new Set(frames.map((f) => `${f.width}x${f.height}`))], ['16x2', '4x4', '64x4'])
+})
+
+test('Synthetic: pause, resume and updateConfig change a running session', async (t) => {
+  const frames: FrameData[] = []
+  const capturer = new ScreenCapture((frame) => frames.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 100,
+    synthetic: { width: 64, height: 32 },
+  })
+  t.throws(() => capturer.pause(), { message: 'Capture is not running' })
+  const states: CaptureState[] = []
+  capturer.onStateChange((state) => states.push(state))
+
+  await capturer.start()
+  await sleep(100)
+  capturer.pause()
+  t.is(capturer.state, CaptureState.Paused)
+  await sleep(30)
+  const paused = frames.length
+  await sleep(150)
+  t.is(frames.length, paused)
+  capturer.resume()
+  t.is(capturer.state, CaptureState.Running)
+  await sleep(100)
+  t.true(frames.length > paused)
+  // The stream was not restarted: the pattern and the sequence numbers carry on without gaps.
+  t.deepEqual(
+    frames.map((f) => [frameCounter(f), f.sequence]),
+    frames.map((_, i) => [i, i]),
+  )
+
+  capturer.updateConfig({ fps: 10 })
+  await sleep(50)
+  const slowStart = frames.length
+  await sleep(500)
+  const slow = frames.length - slowStart
+  t.true(slow >= 3 && slow <= 7, `${slow} frames at 10 fps`)
+
+  capturer.updateConfig({
+    fps: 100,
+    region: { x: 0, y: 0, width: 16, height: 16 },
+    outputWidth: 8,
+    pixelFormat: PixelFormat.Bgra,
+  })
+  await sleep(50)
+  let last = frames[frames.length - 1]
+  t.deepEqual([last.width, last.height, last.format], [8, 8, PixelFormat.Bgra])
+
+  // `null` removes a setting, omitted fields keep theirs.
+  capturer.updateConfig({ region: null, outputWidth: null, encode: { format: ImageFormat.Png } })
+  await sleep(50)
+  last = frames[frames.length - 1]
+  t.deepEqual([last.width, last.height, last.encoding], [64, 32, ImageFormat.Png])
+
+  t.throws(() => capturer.updateConfig({ fps: 0 }), { message: 'fps must be greater than 0' })
+  t.throws(() => capturer.updateConfig({ pixelFormat: PixelFormat.Rgba, encode: { format: ImageFormat.Png } }), {
+    message: 'pixelFormat and encode cannot be used together',
+  })
+
+  capturer.pause()
+  capturer.stop()
+  t.throws(() => capturer.resume(), { message: 'Capture is not running' })
+  await sleep(20)
+  t.deepEqual(states, [
+    CaptureState.Starting,
+    CaptureState.Running,
+    CaptureState.Paused,
+    CaptureState.Running,
+    CaptureState.Paused,
+    CaptureState.Stopped,
+  ])
+})
+
+test('Synthetic: listWindows and windowId', async (t) => {
+  const config = { backend: CaptureBackend.Synthetic, fps: 100, synthetic: { width: 64, height: 8, windows: 2 } }
+  const windows = await ScreenCapture.listWindows(config)
+
+  t.deepEqual(
+    windows.map((w) => [w.id, w.title, w.width, w.height, w.isOnScreen]),
+    [
+      [1, 'Synthetic Window 1', 32, 4, true],
+      [2, 'Synthetic Window 2', 32, 4, true],
+    ],
+  )
+
+  const shot = await new ScreenCapture({ ...config, windowId: 2 }).screenshot()
+  t.is(shot.width, 32)
+  t.is(shot.height, 4)
+  t.deepEqual([...shot.rgba.subarray(4, 8)], [255, 255, 0, 255])
+
+  await t.throwsAsync(() => new ScreenCapture({ ...config, windowId: 3 }).start(), { message: 'Window 3 not found' })
+  t.throws(() => new ScreenCapture({ ...config, windowId: 1, displayId: 1 }))
+
+  // The window "closes" after three frames: delivery stops and the session ends.
+  const frames: FrameData[] = []
+  const closing = new ScreenCapture((frame) => frames.push(frame), {
+    ...config,
+    windowId: 1,
+    synthetic: { ...config.synthetic, failAfterFrames: 3 },
+  })
+  await closing.start()
+  await sleep(150)
+  t.is(frames.length, 3)
+})
+
+test('Synthetic: output scaling', async (t) => {
+  const config = { backend: CaptureBackend.Synthetic, fps: 50, synthetic: { width: 64, height: 8 } }
+  const size = (frame: FrameData) => `${frame.width}x${frame.height}`
+
+  t.is(size(await new ScreenCapture({ ...config, outputWidth: 32 }).screenshot()), '32x4')
+  t.is(size(await new ScreenCapture({ ...config, outputHeight: 2 }).screenshot()), '16x2')
+  t.is(size(await new ScreenCapture({ ...config, outputWidth: 16, outputHeight: 16 }).screenshot()), '16x16')
+  t.is(size(await new ScreenCapture({ ...config, maxWidth: 40 }).screenshot()), '40x5')
+  t.is(size(await new ScreenCapture({ ...config, maxWidth: 100 }).screenshot()), '64x8')
+  t.throws(() => new ScreenCapture({ ...config, maxWidth: 0 }))
+
+  // Halving with nearest keeps every other pixel, so each 8px bar becomes 4px wide.
+  const nearest = await new ScreenCapture({ ...config, outputWidth: 32, scaleFilter: ScaleFilter.Nearest }).screenshot()
+  t.is(nearest.stride, 32 * 4)
+  t.deepEqual([...nearest.rgba.subarray(4 * 4, 4 * 4 + 4)], [255, 255, 0, 255])
+  t.deepEqual([...nearest.rgba.subarray(31 * 4, 32 * 4)], [0, 0, 0, 255])
+
+  const frames: FrameData[] = []
+  const capturer = new ScreenCapture((frame) => frames.push(frame), {
+    ...config,
+    region: { x: 0, y: 0, width: 32, height: 8 },
+    outputWidth: 16,
+  })
+  await capturer.start()
+  await sleep(100)
+  capturer.stop()
+  t.true(frames.length > 0)
+  t.deepEqual([...new Set(frames.map(size))], ['16x4'])
+})
+
+test('Synthetic: pixel formats', async (t) => {
+  const config = { backend: CaptureBackend.Synthetic, synthetic: { width: 64, height: 4 } }
+  const shot = (extra: object) => new ScreenCapture({ ...config, ...extra }).screenshot()
+
+  t.is((await shot({})).format, PixelFormat.Rgba)
+
+  // Pixel 8 is the first yellow one.
+  const bgra = await shot({ pixelFormat: PixelFormat.Bgra })
+  t.is(bgra.format, PixelFormat.Bgra)
+  t.deepEqual([...bgra.rgba.subarray(8 * 4, 8 * 4 + 4)], [0, 255, 255, 255])
+
+  const rgb = await shot({ pixelFormat: PixelFormat.Rgb })
+  t.is(rgb.stride, 64 * 3)
+  t.is(rgb.rgba.length, 64 * 4 * 3)
+  t.deepEqual([...rgb.rgba.subarray(8 * 3, 8 * 3 + 3)], [255, 255, 0])
+
+  // BT.601 limited range: white is Y 235, yellow is Y 210, U 16, V 146.
+  const i420 = await shot({ pixelFormat: PixelFormat.I420 })
+  t.is(i420.stride, 64)
+  t.is(i420.rgba.length, 64 * 4 + 2 * 32 * 2)
+  t.is(i420.rgba[4], 235)
+  t.is(i420.rgba[8], 210)
+  t.is(i420.rgba[64 * 4 + 4], 16)
+  t.is(i420.rgba[64 * 4 + 32 * 2 + 4], 146)
+
+  const nv12 = await shot({ pixelFormat: PixelFormat.Nv12 })
+  t.is(nv12.rgba.length, i420.rgba.length)
+  t.deepEqual([...nv12.rgba.subarray(64 * 4 + 8, 64 * 4 + 10)], [16, 146])
+
+  t.is((await shot({ pixelFormat: PixelFormat.I420, colorMatrix: ColorMatrix.Bt709 })).rgba[8], 219)
+  t.is((await shot({ pixelFormat: PixelFormat.I420, colorRange: ColorRange.Full })).rgba[4], 255)
+
+  // Odd sizes round the chroma planes up.
+  const odd = await shot({ pixelFormat: PixelFormat.I420, outputWidth: 33 })
+  t.is(odd.rgba.length, 33 * 2 + 2 * 17 * 1)
+})
+
+test('Synthetic: encoded screenshots and frames', async (t) => {
+  const config = { backend: CaptureBackend.Synthetic, fps: 50, synthetic: { width: 64, height: 8 } }
+  const capturer = new ScreenCapture(config)
+  const magic = (image: Buffer, length: number) => [...image.subarray(0, length)]
+
+  const jpeg = await capturer.screenshot({ format: ImageFormat.Jpeg, quality: 90 })
+  t.deepEqual(magic(jpeg, 3), [0xff, 0xd8, 0xff])
+  const png = await capturer.screenshot({ format: ImageFormat.Png })
+  t.deepEqual(magic(png, 4), [0x89, 0x50, 0x4e, 0x47])
+  const webp = await capturer.screenshot({ format: ImageFormat.Webp })
+  t.is(webp.subarray(0, 4).toString(), 'RIFF')
+  t.is(webp.subarray(8, 12).toString(), 'WEBP')
+
+  await t.throwsAsync(() => capturer.screenshot({ format: ImageFormat.Jpeg, quality: 0 }), {
+    message: 'quality must be between 1 and 100',
+  })
+  t.throws(() => new ScreenCapture({ ...config, encode: { format: ImageFormat.Png }, pixelFormat: PixelFormat.Rgb }))
+
+  const frames: FrameData[] = []
+  const streaming = new ScreenCapture((frame) => frames.push(frame), {
+    ...config,
+    outputWidth: 32,
+    encode: { format: ImageFormat.Jpeg, quality: 50 },
+  })
+  await streaming.start()
+  await sleep(100)
+  streaming.stop()
+
+  t.true(frames.length > 0)
+  t.is(frames[0].encoding, ImageFormat.Jpeg)
+  t.is(frames[0].width, 32)
+  t.is(frames[0].stride, 0)
+  t.deepEqual(magic(frames[0].rgba, 3), [0xff, 0xd8, 0xff])
+})
+
+test('Synthetic: startRecording writes Y4M and raw files', async (t) => {
+  const dir = mkdtempSync(join(tmpdir(), 'rs-capture-'))
+  const capturer = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    pixelFormat: PixelFormat.I420,
+    synthetic: { width: 64, height: 30 },
+  })
+  const errors: CaptureError[] = []
+  capturer.onError((error) => errors.push(error))
+  await t.throwsAsync(() => capturer.stopRecording(), { message: 'No recording in progress' })
+
+  const y4mPath = join(dir, 'session.y4m')
+  capturer.startRecording(y4mPath, { fps: 25 })
+  t.throws(() => capturer.startRecording(join(dir, 'other.y4m')), {
+    message: 'A recording is already in progress',
+  })
+  await capturer.start()
+  await sleep(400)
+  const y4m = await capturer.stopRecording()
+
+  // Frames arrive at 50 fps and are written at 25 fps, so every other one is skipped.
+  t.true(y4m.frames >= 5 && y4m.frames <= 14, `${y4m.frames} frames`)
+  t.true(y4m.skippedFrames > 0)
+  t.true(Math.abs(y4m.duration - y4m.frames * 40) < 0.001)
+  const file = readFileSync(y4mPath)
+  const headerEnd = file.indexOf(10)
+  t.is(file.subarray(0, headerEnd).toString(), 'YUV4MPEG2 W64 H30 F25:1 Ip A1:1 C420jpeg XCOLORRANGE=LIMITED')
+  // Recordings ignore `pixelFormat`: frames are converted from RGBA to Y4M's own 4:2:0 layout.
+  const frameSize = 'FRAME\n'.length + 64 * 30 + 2 * 32 * 15
+  t.is(file.length - headerEnd - 1, y4m.frames * frameSize)
+  t.is(file.subarray(headerEnd + 1, headerEnd + 7).toString(), 'FRAME\n')
+
+  const rawPath = join(dir, 'session.raw')
+  capturer.startRecording(rawPath, { format: RecordingFormat.Raw })
+  await sleep(150)
+  capturer.updateConfig({ outputWidth: 32 })
+  await sleep(150)
+  capturer.stop()
+  const raw = await capturer.stopRecording()
+
+  const [version, columns, ...lines] = readFileSync(`${rawPath}.idx`, 'utf8').trim().split('\n')
+  t.is(version, '# rs-capture raw index v1')
+  t.is(columns, '# sequence timestamp_us offset length width height stride format')
+  const rows = lines.map((line) => line.split(' '))
+  t.is(rows.length, raw.frames)
+  t.is(rows[0][1], '0')
+  t.true(Math.abs(raw.duration - Number(rows[rows.length - 1][1]) / 1000) < 0.001)
+  t.deepEqual(rows[0].slice(3), [String(64 * 30 * 4), '64', '30', '256', 'RGBA'])
+  t.deepEqual(rows[rows.length - 1].slice(3), [String(32 * 15 * 4), '32', '15', '128', 'RGBA'])
+
+  const data = readFileSync(rawPath)
+  let offset = 0
+  for (const [, , start, length] of rows) {
+    t.is(Number(start), offset)
+    offset += Number(length)
+  }
+  t.is(data.length, offset)
+  t.is(frameCounter({ rgba: data.subarray(0, 4) } as FrameData), Number(rows[0][0]))
+  t.deepEqual(errors, [])
+})
+
+test('Synthetic: exportClip encodes looping GIF and APNG clips', async (t) => {
+  const capturer = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    fps: 30,
+    synthetic: { width: 160, height: 90 },
+  })
+  await t.throwsAsync(() => capturer.exportClip({ durationMs: 100 }), { message: 'Capture is not running' })
+  await capturer.start()
+  await t.throwsAsync(() => capturer.exportClip({ durationMs: 100, fps: 60 }), {
+    message: 'fps must be between 1 and 50',
+  })
+  await t.throwsAsync(() => capturer.exportClip({ durationMs: 100, scale: 2 }), {
+    message: 'scale must be greater than 0 and at most 1',
+  })
+
+  const [gif, apng] = await Promise.all([
+    capturer.exportClip({ durationMs: 500, scale: 0.5 }),
+    capturer.exportClip({ durationMs: 500, format: ClipFormat.Apng, fps: 20 }),
+  ])
+  capturer.stop()
+
+  t.is(gif.subarray(0, 6).toString(), 'GIF89a')
+  t.deepEqual([gif.readUInt16LE(6), gif.readUInt16LE(8)], [80, 45])
+  // NETSCAPE2.0 extension with a loop count of 0: loop forever.
+  const loop = gif.indexOf('NETSCAPE2.0')
+  t.true(loop > 0)
+  t.is(gif.readUInt16LE(loop + 13), 0)
+
+  t.deepEqual([...apng.subarray(1, 4)], [...Buffer.from('PNG')])
+  t.deepEqual([apng.readUInt32BE(16), apng.readUInt32BE(20)], [160, 90])
+  const animation = apng.indexOf('acTL')
+  const frames = apng.readUInt32BE(animation + 4)
+  t.true(frames >= 5 && frames <= 10, `${frames} frames`)
+  t.is(apng.readUInt32BE(animation + 8), 0)
+})
+
+test('Synthetic: exportClip takes the last frames of the replay buffer', async (t) => {
+  await t.throwsAsync(
+    () => new ScreenCapture({ backend: CaptureBackend.Synthetic }).exportClip({ durationMs: 100, fromReplay: true }),
+    { message: 'Replay is not enabled, set the replay option' },
+  )
+  const capturer = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    fps: 30,
+    synthetic: { width: 160, height: 90 },
+    replay: { durationMs: 2000, jpegQuality: 90 },
+  })
+  await t.throwsAsync(() => capturer.exportClip({ durationMs: 100, fromReplay: true }), {
+    message: 'The replay buffer is empty',
+  })
+  await capturer.start()
+  await sleep(1000)
+  capturer.stop()
+
+  // The session is stopped, so these frames can only come from the buffer.
+  const apng = await capturer.exportClip({ durationMs: 500, format: ClipFormat.Apng, fps: 20, fromReplay: true })
+  t.deepEqual([apng.readUInt32BE(16), apng.readUInt32BE(20)], [160, 90])
+  const frames = apng.readUInt32BE(apng.indexOf('acTL') + 4)
+  t.true(frames >= 5 && frames <= 10, `${frames} frames`)
+
+  const gif = await capturer.exportClip({ durationMs: 60_000, fromReplay: true, scale: 0.5 })
+  t.is(gif.subarray(0, 6).toString(), 'GIF89a')
+  t.deepEqual([gif.readUInt16LE(6), gif.readUInt16LE(8)], [80, 45])
+})
+
+test('Synthetic: saveReplay keeps the last frames of the session', async (t) => {
+  await t.throwsAsync(() => new ScreenCapture({ backend: CaptureBackend.Synthetic }).saveReplay(), {
+    message: 'Replay is not enabled, set the replay option',
+  })
+  t.throws(() => new ScreenCapture({ backend: CaptureBackend.Synthetic, replay: { durationMs: 0 } }), {
+    message: 'durationMs must be greater than 0',
+  })
+  t.throws(
+    () => new ScreenCapture({ backend: CaptureBackend.Synthetic, replay: { durationMs: 100, jpegQuality: 0 } }),
+    { message: 'jpegQuality must be between 1 and 100' },
+  )
+
+  const capturer = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    pixelFormat: PixelFormat.I420,
+    replay: { durationMs: 200 },
+    synthetic: { width: 32, height: 16 },
+  })
+  await t.throwsAsync(() => capturer.saveReplay(), { message: 'The replay buffer is empty' })
+  await capturer.start()
+  await sleep(600)
+  capturer.stop()
+
+  // The buffer outlives the session and holds about 200 ms of consecutive frames.
+  const frames = await capturer.saveReplay()
+  t.true(frames.length >= 8 && frames.length <= 12, `${frames.length} frames`)
+  t.true(frames[frames.length - 1].timestamp - frames[0].timestamp <= 200)
+  t.is(frames[0].format, PixelFormat.Rgba)
+  for (const frame of frames) {
+    t.is(frameCounter(frame), frame.sequence)
+  }
+  const dir = mkdtempSync(join(tmpdir(), 'rs-capture-'))
+  const summary = await capturer.saveReplay(join(dir, 'replay.y4m'))
+  t.true(summary.frames >= frames.length)
+  t.is(readFileSync(join(dir, 'replay.y4m')).subarray(0, 9).toString(), 'YUV4MPEG2')
+
+  // 32 * 16 RGBA frames take 2048 bytes each.
+  const capped = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    replay: { durationMs: 5000, maxBytes: 3 * 2048 },
+    synthetic: { width: 32, height: 16 },
+  })
+  await capped.start()
+  await sleep(300)
+  capped.stop()
+  t.is((await capped.saveReplay()).length, 3)
+
+  const jpeg = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    replay: { durationMs: 5000, jpegQuality: 70 },
+    synthetic: { width: 64, height: 32 },
+  })
+  await jpeg.start()
+  await sleep(200)
+  t.is((await jpeg.saveReplay())[0].encoding, ImageFormat.Jpeg)
+  // Saved frames are decoded back to pixels.
+  const rawPath = join(dir, 'replay.raw')
+  const raw = await jpeg.saveReplay(rawPath)
+  jpeg.stop()
+  const rows = readFileSync(`${rawPath}.idx`, 'utf8').trim().split('\n').slice(2)
+  t.is(rows.length, raw.frames)
+  t.deepEqual(rows[0].split(' ').slice(3), [String(64 * 32 * 4), '64', '32', '256', 'RGBA'])
+})
+
+test('Synthetic: dirtyRects cover every change since the previous frame', async (t) => {
+  const plain = new ScreenCapture({ backend: CaptureBackend.Synthetic, fps: 30, synthetic: { width: 64, height: 48 } })
+  await plain.start()
+  for await (const frame of plain.frames()) {
+    t.is(frame.dirtyRects, undefined)
+    break
+  }
+  plain.stop()
+
+  // 80 pixel wide bars moving 4 pixels per frame leave most 32 pixel tiles unchanged.
+  const capturer = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    fps: 60,
+    dirtyRects: true,
+    backpressure: Backpressure.DropNewest,
+    maxQueuedFrames: 2,
+    synthetic: { width: 640, height: 64 },
+  })
+  await capturer.start()
+  let previous: FrameData | undefined
+  let dropped = 0
+  for await (const frame of capturer.frames()) {
+    if (!previous) {
+      t.deepEqual(frame.dirtyRects, [{ x: 0, y: 0, width: 640, height: 64 }])
+    } else {
+      // Copying the dirty rectangles onto the previous frame, dropped frames included, rebuilds the frame.
+      const patched = Buffer.from(previous.rgba)
+      let area = 0
+      for (const rect of frame.dirtyRects!) {
+        t.true(rect.x + rect.width <= 640 && rect.y + rect.height <= 64)
+        area += rect.width * rect.height
+        for (let y = rect.y; y < rect.y + rect.height; y++) {
+          const start = y * frame.stride + rect.x * 4
+          frame.rgba.copy(patched, start, start, start + rect.width * 4)
+        }
+      }
+      t.true(patched.equals(frame.rgba))
+      if (frame.droppedSinceLast === 0) {
+        t.true(area < 640 * 64, `${area} dirty pixels`)
+      }
+    }
+    dropped += frame.droppedSinceLast
+    previous = frame
+    await sleep(30)
+    if (frame.sequence >= 15) break
+  }
+  t.true(dropped > 0)
+
+  // Scaled frames report their rectangles in output pixels, the first one dirty as a whole.
+  capturer.updateConfig({ outputWidth: 320 })
+  for await (const frame of capturer.frames()) {
+    if (frame.width === 320) {
+      t.deepEqual(frame.dirtyRects, [{ x: 0, y: 0, width: 320, height: 32 }])
+      break
+    }
+  }
+  capturer.stop()
+})
+
+test('Synthetic: emitOnlyOnChange skips unchanged frames', async (t) => {
+  t.throws(() => new ScreenCapture({ backend: CaptureBackend.Synthetic, keepaliveMs: 100 }), {
+    message: 'keepaliveMs can only be used with emitOnlyOnChange',
+  })
+
+  // The image changes every fifth frame; only those frames are delivered, numbered without gaps.
+  const frames: FrameData[] = []
+  const capturer = new ScreenCapture((frame) => frames.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    emitOnlyOnChange: true,
+    synthetic: { width: 64, height: 32, holdFrames: 5 },
+  })
+  await capturer.start()
+  await sleep(400)
+  capturer.stop()
+  t.true(frames.length >= 3 && frames.length <= 6, `${frames.length} frames`)
+  t.deepEqual(frames.map(frameCounter), frames.map((_, i) => i * 5))
+  t.deepEqual(frames.map((frame) => frame.sequence), frames.map((_, i) => i))
+
+  // A still screen is repeated every keepaliveMs, and right away after updateConfig.
+  const still: FrameData[] = []
+  const keepalive = new ScreenCapture((frame) => still.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    emitOnlyOnChange: true,
+    keepaliveMs: 100,
+    synthetic: { width: 64, height: 32, holdFrames: 1000 },
+  })
+  await keepalive.start()
+  await sleep(250)
+  keepalive.updateConfig({ outputWidth: 32 })
+  await sleep(30)
+  keepalive.stop()
+  t.deepEqual(still.map((frame) => frame.width), [64, 64, 64, 32])
+  t.true(still.slice(0, 3).every((frame) => frameCounter(frame) === 0))
+  t.true(still[1].timestamp - still[0].timestamp >= 99)
+})
+
+test('DeltaEncoder: packets rebuild frames with every compression', (t) => {
+  t.throws(() => new DeltaEncoder({ tileSize: 4 }), { message: 'tileSize must be between 8 and 1024' })
+
+  // 100 x 70 pixels make 4 x 3 tiles of 32 pixels, cut at the right and bottom edges.
+  const width = 100
+  const height = 70
+  const image = Buffer.alloc(width * height * 4)
+  for (let i = 0; i < width * height; i++) {
+    image.set([(i % width) * 2, Math.floor(i / width) * 3, 128, 255], i * 4)
+  }
+  const changed = Buffer.from(image)
+  for (let y = 40; y < 50; y++) changed.fill(255, (y * width + 40) * 4, (y * width + 50) * 4)
+
+  for (const compression of [TileCompression.Raw, TileCompression.Zlib, TileCompression.Jpeg]) {
+    const encoder = new DeltaEncoder({ tileSize: 32, compression })
+    const decoder = new DeltaDecoder()
+    const key = decoder.decode(encoder.encode({ width, height, rgba: image, sequence: 7, timestamp: 1.5 }))
+    t.true(key.keyframe)
+    t.is(key.dirtyRects?.length, 12)
+    t.is(key.sequence, 7)
+    t.is(key.timestamp, 1.5)
+    const delta = decoder.decode(encoder.encode({ width, height, rgba: changed }))
+    t.false(delta.keyframe)
+    t.deepEqual(delta.dirtyRects, [{ x: 32, y: 32, width: 32, height: 32 }])
+    if (compression === TileCompression.Jpeg) {
+      t.true(delta.rgba.every((value, i) => Math.abs(value - changed[i]) <= 24))
+    } else {
+      t.deepEqual(delta.rgba, changed)
+    }
+
+    // An unchanged frame is a bare header.
+    t.is(encoder.encode({ width, height, rgba: changed }).length, 40)
+  }
+
+  // Delta packets need the packet before them, or a keyframe after a gap.
+  const encoder = new DeltaEncoder({ tileSize: 32 })
+  const packets = [image, changed, image, changed].map((rgba) => encoder.encode({ width, height, rgba }))
+  t.throws(() => new DeltaDecoder().decode(packets[1]), { message: 'Delta packet 1 needs a keyframe first' })
+  const decoder = new DeltaDecoder()
+  decoder.decode(packets[0])
+  t.throws(() => decoder.decode(packets[2]), {
+    message: 'Delta packet 2 does not follow packet 0, wait for a keyframe',
+  })
+  t.throws(() => decoder.decode(packets[3]), { message: 'Delta packet 3 needs a keyframe first' })
+  t.deepEqual(decoder.decode(encoder.encode({ width, height, rgba: changed }, true)).rgba, changed)
+  t.throws(() => decoder.decode(Buffer.from('nope')), { message: 'Not a delta packet' })
+  t.throws(() => decoder.decode(packets[0].subarray(0, 60)), { message: 'Delta packet is truncated or corrupt' })
+
+  // Forged headers are rejected before the frame is allocated.
+  const forged = (width: number, height: number, tiles: number) => {
+    const header = Buffer.alloc(40)
+    header.write('RSDT')
+    header.writeUInt8(1, 4)
+    header.writeUInt8(1, 5)
+    header.writeUInt16LE(64, 6)
+    header.writeUInt32LE(width, 8)
+    header.writeUInt32LE(height, 12)
+    header.writeUInt32LE(tiles, 20)
+    return header
+  }
+  t.throws(() => new DeltaDecoder().decode(forged(65535, 65535, 1)), {
+    message: '65535x65535 frames are larger than the 256 MiB limit of delta packets',
+  })
+  t.throws(() => new DeltaDecoder().decode(forged(4096, 4096, 4096)), { message: 'Delta packet is truncated or corrupt' })
+  t.throws(() => new DeltaDecoder().decode(forged(4096, 4096, 1)), { message: 'Delta packet is truncated or corrupt' })
+})
+
+test('Synthetic: delta option streams packets of the changed tiles', async (t) => {
+  t.throws(
+    () => new ScreenCapture({ backend: CaptureBackend.Synthetic, delta: {}, pixelFormat: PixelFormat.I420 }),
+    { message: 'delta cannot be used with pixelFormat or encode' },
+  )
+  t.throws(() => new ScreenCapture({ backend: CaptureBackend.Synthetic }).requestKeyframe(), {
+    message: 'Delta output is not enabled, set the delta option',
+  })
+
+  // Decoded packets match the raw frames kept by the replay buffer.
+  const packets: FrameData[] = []
+  const capturer = new ScreenCapture((frame) => packets.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    delta: { tileSize: 32 },
+    replay: { durationMs: 5000 },
+    synthetic: { width: 640, height: 96 },
+  })
+  t.throws(() => capturer.updateConfig({ encode: { format: ImageFormat.Jpeg } }), {
+    message: 'delta cannot be used with pixelFormat or encode',
+  })
+  await capturer.start()
+  await sleep(200)
+  capturer.requestKeyframe()
+  await sleep(200)
+  capturer.stop()
+  const frames = await capturer.saveReplay()
+  t.true(packets.length >= 10, `${packets.length} packets`)
+  t.is(packets[0].stride, 0)
+  t.true(packets[0].keyframe)
+  t.is(packets.filter((packet) => packet.keyframe).length, 2)
+
+  const decoder = new DeltaDecoder()
+  for (const packet of packets) {
+    const frame = decoder.decode(packet.rgba)
+    t.is(frame.sequence, packet.sequence)
+    // The 8 moving bar edges and the frame counter touch at most 25 of the 60 tiles.
+    t.true(frame.dirtyRects!.length <= (packet.keyframe ? 60 : 25))
+    t.deepEqual(frame.rgba, frames.find((raw) => raw.sequence === packet.sequence)?.rgba)
+  }
+
+  // Frames dropped by the backpressure policy are followed by a keyframe.
+  const dropping = new ScreenCapture({
+    backend: CaptureBackend.Synthetic,
+    fps: 100,
+    delta: { tileSize: 32 },
+    backpressure: Backpressure.DropNewest,
+    maxQueuedFrames: 1,
+    synthetic: { width: 640, height: 96 },
+  })
+  const iterator = dropping.frames()
+  await dropping.start()
+  const slow = new DeltaDecoder()
+  let decoded = 0
+  for await (const packet of iterator) {
+    if (packet.droppedSinceLast > 0) t.true(packet.keyframe)
+    slow.decode(packet.rgba)
+    await sleep(30)
+    if (++decoded === 8) break
+  }
+  dropping.stop()
+  t.true(dropping.stats().framesDropped > 0)
+})
+
+test('Synthetic: showCursor and cursorMetadata report the pointer apart from the pixels', async (t) => {
+  const synthetic = { width: 64, height: 48, cursor: true }
+  // Screenshots show the pointer of frame 0: an arrow whose black outline starts at the top-left pixel.
+  const shot = (showCursor: boolean) =>
+    new ScreenCapture({ backend: CaptureBackend.Synthetic, showCursor, cursorMetadata: true, synthetic }).screenshot()
+  const drawn = await shot(true)
+  const hidden = await shot(false)
+  t.deepEqual([...drawn.rgba.subarray(64 * 4, 64 * 4 + 4)], [0, 0, 0, 255])
+  t.deepEqual([...hidden.rgba.subarray(64 * 4, 64 * 4 + 4)], [255, 255, 255, 255])
+  t.is(drawn.cursor, undefined)
+
+  // The pointer moves every frame, switches between the arrow and a 5x5 square every 10 frames
+  // and hides for the last 5 of every 30. Its shape is only sent when it changes: with frame 10n,
+  // or the first frame after it when the callback fell behind and frame 10n was dropped.
+  const frames: FrameData[] = []
+  const capturer = new ScreenCapture((frame) => frames.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    showCursor: false,
+    cursorMetadata: true,
+    synthetic,
+  })
+  await capturer.start()
+  await sleep(800)
+  capturer.stop()
+  t.true(frames.length >= 31, `${frames.length} frames`)
+  let previousSize: number | undefined
+  for (const frame of frames) {
+    const i = frameCounter(frame)
+    const arrow = Math.floor(i / 10) % 2 === 0
+    const { shape, ...cursor } = frame.cursor!
+    t.deepEqual(cursor, {
+      x: (i * 7) % 64,
+      y: (i * 5) % 48,
+      visible: i % 30 < 25,
+      hotspotX: arrow ? 0 : 2,
+      hotspotY: arrow ? 0 : 2,
+    })
+    const size = arrow ? 8 : 5
+    t.is(shape?.width, size === previousSize ? undefined : size)
+    previousSize = size
+  }
+  t.is(frames[0].cursor!.shape!.rgba.length, 8 * 8 * 4)
+
+  // A moving pointer is a change for emitOnlyOnChange, and its position follows the output size.
+  const still = async (cursorMetadata: boolean) => {
+    const delivered: FrameData[] = []
+    const capturer = new ScreenCapture((frame) => delivered.push(frame), {
+      backend: CaptureBackend.Synthetic,
+      fps: 50,
+      emitOnlyOnChange: true,
+      showCursor: false,
+      cursorMetadata,
+      outputWidth: 32,
+      synthetic: { ...synthetic, holdFrames: 1000 },
+    })
+    await capturer.start()
+    await sleep(200)
+    capturer.stop()
+    return delivered
+  }
+  const moving = await still(true)
+  t.true(moving.length >= 5, `${moving.length} frames`)
+  // The image is held, and scaled to 32 pixels its frame counter pixel is blended with the bars.
+  t.true(moving.every((frame) => frame.rgba.equals(moving[0].rgba)))
+  t.deepEqual(
+    moving.map((frame) => [frame.cursor!.x, frame.cursor!.y]),
+    moving.map((frame) => [Math.floor(((frame.sequence * 7) % 64) / 2), Math.floor(((frame.sequence * 5) % 48) / 2)]),
+  )
+  t.is((await still(false)).length, 1)
+})
+
+test('Synthetic: displayIds capture several displays, separately or stitched', async (t) => {
+  const synthetic = { width: 64, height: 32, displays: 2 }
+  t.throws(() => new ScreenCapture({ backend: CaptureBackend.Synthetic, displayId: 1, displayIds: [1, 2], synthetic }), {
+    message: 'displayIds cannot be used with displayId or windowId',
+  })
+  t.throws(() => new ScreenCapture({ backend: CaptureBackend.Synthetic, stitch: true, synthetic }), {
+    message: 'stitch can only be used with displayIds',
+  })
+  t.throws(() => new ScreenCapture({ backend: CaptureBackend.Synthetic, displayIds: [1, 2], dirtyRects: true, synthetic }), {
+    message: 'dirtyRects needs stitch when capturing several displays',
+  })
+
+  // Without stitch, every frame shows one display, named by displayId.
+  const frames: FrameData[] = []
+  const separate = new ScreenCapture((frame) => frames.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    displayIds: [1, 2],
+    synthetic,
+  })
+  await separate.start()
+  t.throws(() => separate.startRecording(join(mkdtempSync(join(tmpdir(), 'rs-capture-')), 'displays.y4m')), {
+    message: 'startRecording needs stitch when capturing several displays',
+  })
+  await sleep(200)
+  separate.stop()
+  t.deepEqual([...new Set(frames.map((frame) => frame.displayId))].sort(), [1, 2])
+  for (const id of [1, 2]) {
+    const shown = frames.filter((frame) => frame.displayId === id)
+    t.true(shown.length >= 5, `${shown.length} frames of display ${id}`)
+    t.deepEqual(shown.map(frameCounter), shown.map((_, i) => i))
+  }
+
+  // Stitched, display 2 sits right of display 1, where listDisplays places it.
+  const single = async (displayId: number) =>
+    new ScreenCapture({ backend: CaptureBackend.Synthetic, displayId, synthetic }).screenshot()
+  const columns = (frame: FrameData, x: number, width: number) =>
+    Buffer.concat(
+      Array.from({ length: frame.height }, (_, y) => frame.rgba.subarray(frame.stride * y + x * 4, frame.stride * y + (x + width) * 4)),
+    )
+  const stitched = new ScreenCapture({ backend: CaptureBackend.Synthetic, displayIds: [1, 2], stitch: true, synthetic })
+  const shot = await stitched.screenshot()
+  t.is(shot.width, 128)
+  t.is(shot.height, 32)
+  t.deepEqual(columns(shot, 0, 64), (await single(1)).rgba)
+  t.deepEqual(columns(shot, 64, 64), (await single(2)).rgba)
+  // The region crops the stitched frame.
+  stitched.setRegion({ x: 32, y: 0, width: 64, height: 32 })
+  t.deepEqual((await stitched.screenshot()).rgba, columns(shot, 32, 64))
+
+  // Both displays update every frame interval, yet one stitched frame is delivered per interval.
+  const delivered: FrameData[] = []
+  const capturer = new ScreenCapture((frame) => delivered.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    displayIds: [1, 2],
+    stitch: true,
+    synthetic,
+  })
+  await capturer.start()
+  await sleep(200)
+  capturer.stop()
+  t.true(delivered.length >= 5 && delivered.length <= 12, `${delivered.length} frames`)
+  t.true(delivered.every((frame) => frame.width === 128 && frame.displayId === undefined))
+  const counters = delivered.map((frame) => [frameCounter(frame), frameCounter({ ...frame, rgba: frame.rgba.subarray(64 * 4) })])
+  // Each half keeps showing the latest frame of its display.
+  t.true(counters.every(([left, right], i) => i === 0 || (left >= counters[i - 1][0] && right >= counters[i - 1][1])))
+  t.true(counters[counters.length - 1].every((counter, i) => counter > counters[0][i]))
+})
+
+test('Synthetic: onDisplayChange reports rotated and connected displays', async (t) => {
+  const frames: FrameData[] = []
+  const capturer = new ScreenCapture((frame) => frames.push(frame), {
+    backend: CaptureBackend.Synthetic,
+    fps: 50,
+    synthetic: { width: 64, height: 32, rotateAfterFrames: 5, connectAfterFrames: 10 },
+  })
+  const changes: DisplayChange[] = []
+  capturer.onDisplayChange((change) => changes.push(change))
+  await capturer.start()
+  await sleep(400)
+  capturer.stop()
+  await sleep(20)
+
+  t.deepEqual(
+    changes.map((change) => [change.kind, change.displayId, change.width, change.height]),
+    [
+      [DisplayChangeKind.ResolutionChanged, 1, 32, 64],
+      [DisplayChangeKind.DisplaysChanged, 1, 32, 64],
+    ],
+  )
+  t.deepEqual(
+    changes[1].displays.map((d) => [d.id, d.x, d.width, d.height]),
+    [
+      [1, 0, 32, 64],
+      [2, 32, 32, 64],
+    ],
+  )
+  // The session keeps running and frames take the new size from the frame the display rotated on.
+  t.true(frames.length > 10, `${frames.length} frames`)
+  t.true(frames.every((frame) => frame.width === (frameCounter(frame) < 5 ? 64 : 32)))
+})
+
+// Runs on Linux when an X server is reachable, e.g. `xvfb-run pnpm test`.
+const x11Test = process.platform === 'linux' && process.env.DISPLAY ? test : test.skip
+
+x11Test('X11: screenshot and streaming', async (t) => {
+  const capturer = new ScreenCapture({ backend: CaptureBackend.X11, fps: 30 })
+  const shot = await capturer.screenshot()
+  t.true(shot.width > 0 && shot.height > 0)
+  t.is(shot.rgba.length, shot.stride * shot.height)
+
+  const frames: FrameData[] = []
+  const streaming = new ScreenCapture((frame) => frames.push(frame), { backend: CaptureBackend.X11, fps: 30 })
+  await streaming.start()
+  await sleep(300)
+  streaming.stop()
+  t.true(frames.length > 0)
+  t.is(frames[0].width, shot.width)
+})
+
+x11Test('X11: start rejects a missing displayId', async (t) => {
+  const capturer = new ScreenCapture(() => {}, { displayId: 9 })
+  await t.throwsAsync(() => capturer.start(), { message: 'Display 9 not found' })
+  t.is(capturer.state, CaptureState.Stopped)
+})
+
+// Builds `__test__/capi/harness.c` against the addon built by `pnpm build`, which also exports the C ABI.
+const root = fileURLToPath(new URL('..', import.meta.url))
+const addon = readdirSync(root).find((file) => /^rs-capture\..+\.node$/.test(file))
+const cTest = process.platform !== 'win32' && addon && spawnSync('cc', ['--version']).status === 0 ? test : test.skip
+
+cTest('C ABI: synthetic capture from a C harness', (t) => {
+  const dir = mkdtempSync(join(tmpdir(), 'rs-capture-'))
+  copyFileSync(join(root, addon!), join(dir, process.platform === 'darwin' ? 'librs_capture.dylib' : 'librs_capture.so'))
+  const harness = join(dir, 'harness')
+  execFileSync('cc', [
+    '-std=c11',
+    '-Wall',
+    '-Werror',
+    '-I',
+    join(root, 'include'),
+    join(root, '__test__/capi/harness.c'),
+    '-L',
+    dir,
+    '-lrs_capture',
+    `-Wl,-rpath,${dir}`,
+    '-o',
+    harness,
+  ])
+  const output = execFileSync(harness, { encoding: 'utf8' })
+  const lines = Object.fromEntries(output.trim().split('\n').map((line) => line.split(/ (.*)/)))
+
+  t.true(Number(lines.frames) > 0, output)
+  t.true(Number(lines.dropped) > 0, output)
+  t.true(Number(lines.blocked) > 0, output)
+  t.is(lines.screenshot, '64x4')
+  t.is(lines.i420, '32x2')
+  t.is(lines.display, 'Display 0 not found')
+  t.is(lines.error, 'Unknown backend 42')
+})
+
+// Runs the binary built by `pnpm build:cli`, or by `cargo build` for a debug build.
+const cli = ['release', 'debug']
+  .map((profile) => join(root, 'target', profile, process.platform === 'win32' ? 'rs-capture.exe' : 'rs-capture'))
+  .find((path) => existsSync(path))
+const cliTest = cli ? test : test.skip
+
+cliTest('CLI: list-displays, screenshot, record and stream', (t) => {
+  const run = (...args: string[]) => spawnSync(cli!, [...args, '--backend', 'synthetic'], { maxBuffer: 1 << 28 })
+  const dir = mkdtempSync(join(tmpdir(), 'rs-capture-'))
+
+  const displays = JSON.parse(run('list-displays', '--json').stdout.toString())
+  t.deepEqual(
+    displays.map((d: { id: number; width: number; isPrimary: boolean }) => [d.id, d.width, d.isPrimary]),
+    [[1, 640, true]],
+  )
+
+  const pngPath = join(dir, 'shot.png')
+  const shot = run('screenshot', '-o', pngPath, '--region', '0,0,64,32', '--json')
+  t.like(JSON.parse(shot.stdout.toString()), { width: 64, height: 32 })
+  t.deepEqual([...readFileSync(pngPath).subarray(1, 4)], [0x50, 0x4e, 0x47])
+
+  const y4mPath = join(dir, 'session.y4m')
+  const recorded = JSON.parse(run('record', '-o', y4mPath, '--fps', '20', '--duration', '300ms', '--width', '64', '--json').stdout.toString())
+  t.true(recorded.frames > 0)
+  t.is(readFileSync(y4mPath).subarray(0, 23).toString(), 'YUV4MPEG2 W64 H48 F20:1')
+
+  // Frames go to stdout back to back; stderr describes them and ends with a summary.
+  const streamed = run('stream', '--stdout', '--format', 'i420', '--width', '64', '--duration', '300ms', '--json')
+  const [geometry, summary] = streamed.stderr.toString().trim().split('\n').map((line) => JSON.parse(line))
+  t.deepEqual(geometry, { width: 64, height: 48, stride: 64, format: 'i420', bytes: 64 * 48 * 1.5 })
+  t.true(summary.frames > 0)
+  t.is(streamed.stdout.length, summary.frames * geometry.bytes)
+
+  const unknown = run('bogus')
+  t.is(unknown.status, 2)
+  t.regex(unknown.stderr.toString(), /Unknown command bogus/)
+  const missing = run('screenshot', '--display', '9', '-o', pngPath)
+  t.is(missing.status, 1)
+  t.regex(missing.stderr.toString(), /Display 9 not found/)
+})
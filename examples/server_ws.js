@@ -2,10 +2,8 @@ const express = require('express')
 const WebSocket = require('ws')
 const http = require('http')
 const path = require('path')
-const os = require('os')
-const { spawn } = require('child_process')
 const { mouse, keyboard, Button, Key, Point } = require('@nut-tree/nut-js')
-const { ScreenCapture } = require('@vertfrag/rs-capture')
+const { Backpressure, ImageFormat, ScreenCapture } = require('@vertfrag/rs-capture')
 
 const app = express()
 const server = http.createServer(app)
@@ -14,40 +12,8 @@ const wss = new WebSocket.Server({ server, perMessageDeflate: false })
 const JPEG_QUALITY = Number.parseInt(process.env.CAP_JPEG_QUALITY ?? '60', 10)
 const MAX_WIDTH = Number.parseInt(process.env.CAP_MAX_WIDTH ?? (process.platform === 'win32' ? '1280' : '0'), 10)
 const CAP_FPS = Number.parseInt(process.env.CAP_FPS ?? '60', 10)
-const CAP_ENCODER = (process.env.CAP_ENCODER ?? (process.platform === 'win32' ? 'ffmpeg' : 'sharp')).toLowerCase()
-const CAP_FFMPEG_PATH = process.env.CAP_FFMPEG_PATH ?? null
-let DEFAULT_FFMPEG_PATH = null
-try {
-  DEFAULT_FFMPEG_PATH = require('@ffmpeg-installer/ffmpeg').path
-} catch {}
-const CAP_FFMPEG = CAP_FFMPEG_PATH || DEFAULT_FFMPEG_PATH || 'ffmpeg'
 
-let sharp = null
-let sharpInitTried = false
-let sharpUnavailableLogged = false
-
-function getSharp() {
-  if (sharp) return sharp
-  if (sharpInitTried) return null
-  sharpInitTried = true
-  try {
-    sharp = require('sharp')
-    sharp.cache(false)
-    sharp.concurrency(Math.max(1, Math.min(os.cpus().length, 4)))
-    return sharp
-  } catch (e) {
-    if (!sharpUnavailableLogged) {
-      sharpUnavailableLogged = true
-      console.error('sharp is not available (build scripts may be disabled). Set CAP_ENCODER=ffmpeg to avoid sharp.')
-    }
-    return null
-  }
-}
-
-console.log(`encoder config: encoder=${CAP_ENCODER} fps=${CAP_FPS} maxWidth=${MAX_WIDTH} jpegQuality=${JPEG_QUALITY}`)
-if (CAP_ENCODER === 'ffmpeg') {
-  console.log(`encoder config: ffmpeg=${CAP_FFMPEG}`)
-}
+console.log(`encoder config: fps=${CAP_FPS} maxWidth=${MAX_WIDTH} jpegQuality=${JPEG_QUALITY}`)
 
 // Configure nut-js
 mouse.config.autoDelayMs = 0
@@ -162,6 +128,7 @@ async function handleInputEvent(event) {
 
     if (
       (type === 'mousemove' || type === 'mousedown' || type === 'mouseup' || type === 'click' || type === 'dblclick') &&
+      currentScreenWidth > 0 &&
       currentOutputWidth > 0 &&
       currentOutputHeight > 0
     ) {
@@ -240,15 +207,10 @@ server.listen(PORT, () => {
 })
 
 let capture = null
-let isProcessing = false
 const clients = new Set()
 let encodedFrames = 0
 let broadcastFrames = 0
 let lastStatsAt = Date.now()
-let encoder = null
-let encoderW = 0
-let encoderH = 0
-let encoderBackpressure = false
 
 let currentScreenWidth = 0
 let currentScreenHeight = 0
@@ -263,7 +225,7 @@ let currentOutputHeight = 0
 //   const outFps = Math.round(broadcastFrames / dt)
 //   encodedFrames = 0
 //   broadcastFrames = 0
-//   console.log(`stats: clients=${clients.size} enc_fps=${encFps} out_fps=${outFps} encoder=${encoder ? CAP_ENCODER : 'none'}`)
+//   console.log(`stats: clients=${clients.size} enc_fps=${encFps} out_fps=${outFps}`)
 // }, 1000).unref()
 
 function attachClient(ws) {
@@ -288,102 +250,6 @@ function stopSharedCapture() {
     console.error('Capture stop error:', e)
   }
   capture = null
-  stopEncoder()
-}
-
-function stopEncoder() {
-  if (!encoder) return
-  try {
-    encoder.close()
-  } catch {}
-  encoder = null
-  encoderW = 0
-  encoderH = 0
-  encoderBackpressure = false
-}
-
-function qualityToQscale(quality) {
-  const q = Number.isFinite(quality) ? Math.round(31 - (quality / 100) * 29) : 8
-  return Math.max(2, Math.min(31, q))
-}
-
-function createFfmpegMjpegEncoder({ width, height, fps, maxWidth, quality, onFrame }) {
-  const args = ['-hide_banner', '-loglevel', 'error', '-nostdin']
-  args.push('-f', 'rawvideo', '-pix_fmt', 'rgba', '-s', `${width}x${height}`, '-r', String(fps), '-i', 'pipe:0')
-  if (maxWidth > 0 && width > maxWidth) {
-    const outH = Math.max(1, Math.round((height * maxWidth) / width))
-    args.push('-vf', `scale=${maxWidth}:${outH}:flags=fast_bilinear`)
-  }
-  args.push('-an', '-sn', '-dn')
-  args.push('-c:v', 'mjpeg', '-q:v', String(qualityToQscale(quality)))
-  args.push('-f', 'mjpeg', 'pipe:1')
-
-  const proc = spawn(CAP_FFMPEG, args, { windowsHide: true, stdio: ['pipe', 'pipe', 'pipe'] })
-  let buf = Buffer.alloc(0)
-  let closed = false
-  let lastStderr = ''
-
-  function tryParse() {
-    while (true) {
-      const start = buf.indexOf(Buffer.from([0xff, 0xd8]))
-      if (start < 0) {
-        if (buf.length > 2) buf = buf.subarray(buf.length - 2)
-        return
-      }
-      const end = buf.indexOf(Buffer.from([0xff, 0xd9]), start + 2)
-      if (end < 0) {
-        if (start > 0) buf = buf.subarray(start)
-        return
-      }
-      const frame = buf.subarray(start, end + 2)
-      buf = buf.subarray(end + 2)
-      onFrame(frame)
-    }
-  }
-
-  proc.stdout.on('data', (chunk) => {
-    if (closed) return
-    buf = buf.length === 0 ? chunk : Buffer.concat([buf, chunk], buf.length + chunk.length)
-    if (buf.length > 20 * 1024 * 1024) {
-      buf = buf.subarray(buf.length - 2)
-    }
-    tryParse()
-  })
-
-  proc.stderr.on('data', (chunk) => {
-    lastStderr = String(chunk)
-  })
-
-  proc.on('close', () => {
-    closed = true
-    if (lastStderr) {
-      console.error('ffmpeg closed:', lastStderr.trim())
-    }
-  })
-
-  proc.on('error', (e) => {
-    closed = true
-    console.error('ffmpeg spawn error:', e)
-  })
-
-  return {
-    writeFrame(rgba) {
-      if (closed || proc.stdin.destroyed) return null
-      return proc.stdin.write(rgba)
-    },
-    onDrain(fn) {
-      proc.stdin.on('drain', fn)
-    },
-    close() {
-      closed = true
-      try {
-        proc.stdin.end()
-      } catch {}
-      try {
-        proc.kill('SIGKILL')
-      } catch {}
-    },
-  }
 }
 
 function broadcastJpeg(jpegBuffer) {
@@ -396,103 +262,37 @@ function broadcastJpeg(jpegBuffer) {
   }
 }
 
+// Input events arrive in frame coordinates; the mouse moves in display coordinates.
+function updateScreenSize() {
+  ScreenCapture.listDisplays()
+    .then((displays) => {
+      const display = displays.find((d) => d.isPrimary) ?? displays[0]
+      if (!display) return
+      currentScreenWidth = display.width
+      currentScreenHeight = display.height
+    })
+    .catch((err) => console.error('Failed to list displays:', err))
+}
+
 function startSharedCapture() {
+  updateScreenSize()
   capture = new ScreenCapture(
-    async (frame) => {
+    (frame) => {
       if (clients.size === 0) return
 
-      currentScreenWidth = frame.width
-      currentScreenHeight = frame.height
-
-      let targetW = frame.width
-      let targetH = frame.height
-      if (MAX_WIDTH > 0 && frame.width > MAX_WIDTH) {
-        targetW = MAX_WIDTH
-        targetH = Math.round((frame.height * MAX_WIDTH) / frame.width)
-      }
-      currentOutputWidth = targetW
-      currentOutputHeight = targetH
-
-      if (CAP_ENCODER === 'ffmpeg') {
-        if (!encoder || encoderW !== frame.width || encoderH !== frame.height) {
-          stopEncoder()
-          try {
-            encoderW = frame.width
-            encoderH = frame.height
-            encoder = createFfmpegMjpegEncoder({
-              width: frame.width,
-              height: frame.height,
-              fps: CAP_FPS,
-              maxWidth: MAX_WIDTH,
-              quality: JPEG_QUALITY,
-              onFrame: broadcastJpeg,
-            })
-            encoder.onDrain(() => {
-              encoderBackpressure = false
-            })
-          } catch (e) {
-            console.error('ffmpeg encoder init failed, falling back to sharp:', e)
-            stopEncoder()
-          }
-        }
-
-        if (encoder && !encoderBackpressure) {
-          const ok = encoder.writeFrame(frame.rgba)
-          if (ok === true) return
-          if (ok === false) {
-            encoderBackpressure = true
-            return
-          }
-          stopEncoder()
-        }
-      }
-
-      if (isProcessing) return
-      isProcessing = true
-      try {
-        const sharpLib = getSharp()
-        if (!sharpLib) {
-          return
-        }
-
-        let pipeline = sharpLib(frame.rgba, {
-          raw: {
-            width: frame.width,
-            height: frame.height,
-            channels: 4,
-          },
-        })
-
-        if (MAX_WIDTH > 0 && frame.width > MAX_WIDTH) {
-          pipeline = pipeline.resize({
-            width: MAX_WIDTH,
-            height: Math.round((frame.height * MAX_WIDTH) / frame.width),
-            fit: 'fill',
-            kernel: 'nearest',
-            fastShrinkOnLoad: true,
-          })
-        }
-
-        const jpegBuffer = await pipeline
-          .jpeg({
-            quality: JPEG_QUALITY,
-            mozjpeg: process.platform !== 'win32',
-            progressive: false,
-            optimiseScans: false,
-            trellisQuantisation: false,
-            overshootDeringing: false,
-            optimiseCoding: false,
-          })
-          .toBuffer()
-
-        broadcastJpeg(jpegBuffer)
-      } catch (err) {
-        console.error('Frame processing error:', err)
-      } finally {
-        isProcessing = false
-      }
+      // Frames arrive already scaled to MAX_WIDTH and JPEG-encoded.
+      currentOutputWidth = frame.width
+      currentOutputHeight = frame.height
+      broadcastJpeg(frame.rgba)
+    },
+    {
+      fps: CAP_FPS,
+      maxWidth: MAX_WIDTH > 0 ? MAX_WIDTH : undefined,
+      encode: { format: ImageFormat.Jpeg, quality: JPEG_QUALITY },
+      // Viewers want the latest screen, not a backlog.
+      backpressure: Backpressure.DropOldest,
+      maxQueuedFrames: 1,
     },
-    { fps: CAP_FPS },
   )
 
   capture
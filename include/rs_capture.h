@@ -0,0 +1,255 @@
+/* Generated by cbindgen from src/capi.rs with `pnpm build:header`. Do not edit. */
+
+#ifndef RS_CAPTURE_H
+#define RS_CAPTURE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Version of the C ABI. Changes whenever a type or function changes incompatibly.
+ */
+#define RS_CAPTURE_ABI_VERSION 1
+
+#define RS_CAPTURE_BACKEND_AUTO 0
+
+#define RS_CAPTURE_BACKEND_SCREEN_CAPTURE_KIT 1
+
+#define RS_CAPTURE_BACKEND_XCAP 2
+
+#define RS_CAPTURE_BACKEND_SYNTHETIC 3
+
+#define RS_CAPTURE_BACKEND_X11 4
+
+#define RS_CAPTURE_PIXEL_FORMAT_RGBA 0
+
+#define RS_CAPTURE_PIXEL_FORMAT_BGRA 1
+
+#define RS_CAPTURE_PIXEL_FORMAT_RGB 2
+
+#define RS_CAPTURE_PIXEL_FORMAT_I420 3
+
+#define RS_CAPTURE_PIXEL_FORMAT_NV12 4
+
+#define RS_CAPTURE_BACKPRESSURE_DROP_OLDEST 0
+
+#define RS_CAPTURE_BACKPRESSURE_DROP_NEWEST 1
+
+#define RS_CAPTURE_BACKPRESSURE_BLOCK 2
+
+/**
+ * Result of the functions that can fail. `rs_capture_last_error` describes the failure.
+ */
+typedef enum {
+  RS_CAPTURE_STATUS_OK = 0,
+  RS_CAPTURE_STATUS_INVALID_ARG = 1,
+  RS_CAPTURE_STATUS_GENERIC_FAILURE = 2,
+  RS_CAPTURE_STATUS_CLOSING = 3,
+} RsCaptureStatus;
+
+/**
+ * A capture session.
+ */
+typedef struct RsCapture RsCapture;
+
+/**
+ * Rectangle relative to the top-left corner of the captured display or window.
+ */
+typedef struct {
+  uint32_t x;
+  uint32_t y;
+  uint32_t width;
+  uint32_t height;
+} RsCaptureRegion;
+
+/**
+ * What the synthetic backend generates. Fields left at 0 keep their default.
+ */
+typedef struct {
+  uint32_t width;
+  uint32_t height;
+  uint32_t displays;
+  uint32_t windows;
+} RsCaptureSyntheticConfig;
+
+/**
+ * Settings of a capture session. A zeroed config captures the primary display with the
+ * platform's default backend at 60 fps; fields left at 0 keep their default.
+ */
+typedef struct {
+  /**
+   * One of `RS_CAPTURE_BACKEND_*`.
+   */
+  uint32_t backend;
+  uint32_t fps;
+  /**
+   * Capture the display `display_id` instead of the primary one.
+   */
+  bool has_display_id;
+  uint32_t display_id;
+  /**
+   * Window to capture instead of a display.
+   */
+  uint32_t window_id;
+  /**
+   * Only capture this part of the display or window. A zero width and height capture all of it.
+   */
+  RsCaptureRegion region;
+  /**
+   * Scale frames to this size. When only one side is set the other follows the aspect ratio.
+   */
+  uint32_t output_width;
+  uint32_t output_height;
+  /**
+   * Scale frames down to at most this width, keeping the aspect ratio.
+   */
+  uint32_t max_width;
+  /**
+   * One of `RS_CAPTURE_PIXEL_FORMAT_*`.
+   */
+  uint32_t pixel_format;
+  /**
+   * One of `RS_CAPTURE_BACKPRESSURE_*`: what happens to new frames while `max_queued_frames`
+   * frames wait for the callback. The default drops the oldest one.
+   */
+  uint32_t backpressure;
+  /**
+   * Frames that may wait for the callback. 0 keeps the default of 4.
+   */
+  uint32_t max_queued_frames;
+  /**
+   * Leave the pointer out of the frames.
+   */
+  bool hide_cursor;
+  RsCaptureSyntheticConfig synthetic;
+} RsCaptureConfig;
+
+/**
+ * A captured frame. `data` holds `data_len` bytes laid out as `format`, rows `stride` bytes
+ * apart for packed formats.
+ */
+typedef struct {
+  uint32_t width;
+  uint32_t height;
+  uint32_t stride;
+  /**
+   * One of `RS_CAPTURE_PIXEL_FORMAT_*`.
+   */
+  uint32_t format;
+  const uint8_t *data;
+  size_t data_len;
+  /**
+   * When the frame was captured, in microseconds on a monotonic clock.
+   */
+  uint64_t timestamp_us;
+  /**
+   * Position in the capture session, starting at 0.
+   */
+  uint64_t sequence;
+  /**
+   * Whether the frame shows the single display `display_id`. False for window captures.
+   */
+  bool has_display_id;
+  uint32_t display_id;
+  /**
+   * Frames dropped by the backpressure policy just before this one.
+   */
+  uint64_t dropped_since_last;
+} RsCaptureFrame;
+
+/**
+ * Called with every frame, in order, on a delivery thread of the session. Frames wait for it
+ * in a queue handled by the `backpressure` setting, so a slow callback does not slow the
+ * capture down. The frame belongs to the callback, which must pass it to
+ * `rs_capture_frame_release` once done with it, possibly on another thread.
+ */
+typedef void (*RsCaptureFrameCallback)(RsCaptureFrame *frame, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Version of the C ABI the library implements, `RS_CAPTURE_ABI_VERSION` when it matches the
+ * header.
+ */
+uint32_t rs_capture_abi_version(void);
+
+/**
+ * Describes the last failure on the calling thread, or returns NULL. The string stays valid
+ * until the next failing call on the thread.
+ */
+const char *rs_capture_last_error(void);
+
+/**
+ * Sets up a capture session without starting it and stores it in `*out`. `config` may be NULL
+ * for the defaults. Without `callback` frames are only captured by `rs_capture_screenshot`.
+ *
+ * # Safety
+ *
+ * `config` must be NULL or point to a valid `RsCaptureConfig`, and `out` to writable memory.
+ * `user_data` is passed to `callback` from the delivery thread until the session is destroyed.
+ */
+RsCaptureStatus rs_capture_create(const RsCaptureConfig *config,
+                                  RsCaptureFrameCallback callback,
+                                  void *user_data,
+                                  RsCapture **out);
+
+/**
+ * Starts capturing, blocking until the backend delivers frames or failed.
+ *
+ * # Safety
+ *
+ * `capture` must come from `rs_capture_create` and not be destroyed.
+ */
+RsCaptureStatus rs_capture_start(RsCapture *capture);
+
+/**
+ * Stops capturing. Frames still queued for the callback are delivered first; once it returns
+ * the callback is not called again.
+ *
+ * # Safety
+ *
+ * `capture` must come from `rs_capture_create` and not be destroyed, and the function must not
+ * be called from the callback.
+ */
+RsCaptureStatus rs_capture_stop(RsCapture *capture);
+
+/**
+ * Captures a single frame with the output settings of the session and stores it in `*out`.
+ *
+ * # Safety
+ *
+ * `capture` must come from `rs_capture_create` and not be destroyed, and `out` must point to
+ * writable memory.
+ */
+RsCaptureStatus rs_capture_screenshot(RsCapture *capture, RsCaptureFrame **out);
+
+/**
+ * Frees a frame and its pixels. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `frame` must be NULL or a frame handed out by the library that was not released yet.
+ */
+void rs_capture_frame_release(RsCaptureFrame *frame);
+
+/**
+ * Stops the session if it is running and frees it. NULL is ignored. Frames handed out before
+ * stay valid until released.
+ *
+ * # Safety
+ *
+ * `capture` must be NULL or come from `rs_capture_create`, and not be used afterwards. The
+ * function must not be called from the callback.
+ */
+void rs_capture_destroy(RsCapture *capture);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RS_CAPTURE_H */
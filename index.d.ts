@@ -1,28 +1,546 @@
 /* auto-generated by NAPI-RS */
 /* eslint-disable */
+/** Rebuilds frames from delta packets of `DeltaEncoder` or the `delta` option. */
+export declare class DeltaDecoder {
+  constructor()
+  /**
+   * Applies a packet and returns the whole frame as RGBA, with the tiles it updated in
+   * `dirtyRects`. Throws for a packet that does not follow the previous one; after that only
+   * a keyframe is accepted.
+   */
+  decode(packet: Buffer): FrameData
+  /** Forgets the current frame, so the next packet must be a keyframe. */
+  reset(): void
+}
+
+/**
+ * Turns frames into delta packets without capturing, for example to stream frames from
+ * another source.
+ */
+export declare class DeltaEncoder {
+  constructor(config?: DeltaConfig | undefined | null)
+  /**
+   * Encodes the tiles that changed since the previous frame. The first packet, packets after a
+   * size change and packets with `keyframe` set hold every tile.
+   */
+  encode(frame: RawFrame, keyframe?: boolean | undefined | null): Buffer
+  /** Makes the next packet a keyframe. */
+  requestKeyframe(): void
+}
+
+/**
+ * Frames of a capture session, returned by `ScreenCapture.frames()`.
+ *
+ * This type implements JavaScript's async iterable protocol.
+ * It can be used with `for await...of` loops.
+ *
+ * @see https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Iteration_protocols#the_async_iterator_and_async_iterable_protocols
+ */
+export declare class FrameIterator {
+  [Symbol.asyncIterator](): AsyncGenerator<FrameData, void, undefined>
+}
+
 export declare class ScreenCapture {
   constructor(
     callbackOrConfig?: ((frame: FrameData) => void) | ScreenCaptureConfig,
     config?: ScreenCaptureConfig | null,
   )
+  /** Lists the displays available to the configured backend. */
+  static listDisplays(config?: ScreenCaptureConfig | undefined | null): Promise<Array<DisplayInfo>>
+  /** Lists the windows that can be captured with `windowId`. */
+  static listWindows(config?: ScreenCaptureConfig | undefined | null): Promise<Array<WindowInfo>>
   start(): Promise<void>
+  /** Stops capturing. Open `frames()` iterators finish after their queued frames. */
   stop(): void
+  /**
+   * Stops delivering frames while keeping the stream open, so `resume()` continues right away.
+   * Frames already waiting for the callback or an iterator are still delivered.
+   */
+  pause(): void
+  /** Delivers frames again after `pause()`. */
+  resume(): void
+  /**
+   * Changes the frame rate, region or output settings. A running session applies them from its
+   * next frame without restarting the stream.
+   */
+  updateConfig(config: CaptureConfigUpdate): void
+  /** Current state of the capture session. */
+  get state(): CaptureState
+  /**
+   * Adds a listener for errors on the capture threads. Errors reported before the first
+   * listener was added, such as a backend fallback in the constructor, are delivered to it.
+   */
+  onError(callback: (error: CaptureError) => void): void
+  /** Adds a listener called whenever the session moves to another `CaptureState`. */
+  onStateChange(callback: (state: CaptureState) => void): void
+  /**
+   * Adds a listener for display changes the session adapted to: displays connected,
+   * disconnected or moved, and mode changes of the captured display. Window captures report
+   * none, and stitched frames keep the layout the session started with.
+   */
+  onDisplayChange(callback: (change: DisplayChange) => void): void
+  /**
+   * Pulls frames with `for await`. Each iterator queues up to `maxQueuedFrames` frames and
+   * applies `backpressure` when it falls behind. Works with or without a frame callback.
+   */
+  frames(): FrameIterator
+  /** Frame counters, including frames dropped by the backpressure policy. */
+  stats(): CaptureStats
+  /**
+   * Writes the frames of the session to `path` until `stopRecording()` or `stop()`. Frames are
+   * recorded after scaling but before `pixelFormat` and `encode`, and timed by their timestamps.
+   */
+  startRecording(path: string, config?: RecordingConfig | undefined | null): void
+  /** Finishes the file of `startRecording()`, including the frames still queued for it. */
+  stopRecording(): Promise<RecordingSummary>
+  /**
+   * Captures the next `durationMs` of the session, or takes the last ones from the replay buffer
+   * with `fromReplay`, and resolves with a looping GIF or APNG. Frames are taken after scaling
+   * but before `pixelFormat` and `encode`.
+   */
+  exportClip(config: ClipConfig): Promise<Buffer>
+  /** Returns the frames in the replay buffer, oldest first. The buffer keeps its frames. */
+  saveReplay(): Promise<Array<FrameData>>
+  /** Writes the frames in the replay buffer to `path` like `startRecording()`. */
+  saveReplay(path: string): Promise<RecordingSummary>
+  /**
+   * Makes the next packet of the `delta` option a keyframe, for example when a viewer joins or
+   * lost a packet. Packets lost to `backpressure` trigger one on their own.
+   */
+  requestKeyframe(): void
+  /**
+   * Moves the capture region, or captures the whole display again when `region` is omitted.
+   * Takes effect on the next frame of a running session.
+   */
+  setRegion(region?: Region | undefined | null): void
+  /** Captures a single frame. */
   screenshot(): Promise<FrameData>
+  /** Captures a single frame and returns it compressed. */
+  screenshot(options: EncodeConfig): Promise<Buffer>
+}
+
+/** What happens to new frames while `maxQueuedFrames` are waiting for a consumer. */
+export declare const enum Backpressure {
+  /** Drop the oldest waiting frame, so consumers always get the latest ones. */
+  DropOldest = 'dropOldest',
+  /** Drop the new frame. */
+  DropNewest = 'dropNewest',
+  /** Pause the capture loop until the consumer catches up. */
+  Block = 'block',
 }
 
 export declare const enum CaptureBackend {
   ScreenCaptureKit = 'ScreenCaptureKit',
   XCap = 'XCap',
+  Synthetic = 'Synthetic',
+  X11 = 'X11',
+}
+
+/** Settings `updateConfig` changes on a running session. Omitted fields keep their value. */
+export interface CaptureConfigUpdate {
+  fps?: number
+  /** `null` captures the whole display or window again. */
+  region?: Region | null
+  /** `null` removes the limit, like the other output sizes. */
+  outputWidth?: number | null
+  outputHeight?: number | null
+  maxWidth?: number | null
+  scaleFilter?: ScaleFilter
+  /** Also switches back to raw frames when `encode` was set. */
+  pixelFormat?: PixelFormat
+  colorMatrix?: ColorMatrix
+  colorRange?: ColorRange
+  /** `null` switches back to raw frames. */
+  encode?: EncodeConfig | null
+}
+
+/** Error reported to `onError` listeners. */
+export interface CaptureError {
+  code: ErrorCode
+  message: string
+  /** The session ended because of this error. */
+  fatal: boolean
+}
+
+/** Lifecycle of a capture session, reported to `onStateChange` listeners. */
+export declare const enum CaptureState {
+  /** `start()` was called and the backend is being set up. */
+  Starting = 'starting',
+  Running = 'running',
+  /** `pause()` was called: the stream stays open but delivers no frames until `resume()`. */
+  Paused = 'paused',
+  /** Not capturing: never started, stopped, or ended by a fatal error. */
+  Stopped = 'stopped',
+}
+
+/** Frame counters of a `ScreenCapture`, across all of its sessions. */
+export interface CaptureStats {
+  /** Frames captured while a callback or `frames()` iterator was listening. */
+  framesCaptured: number
+  /** Frames discarded by the backpressure policy, summed over the callback and all iterators. */
+  framesDropped: number
+}
+
+export interface ClipConfig {
+  /** Length of the clip in milliseconds, from its first frame. */
+  durationMs: number
+  /** Defaults to `gif`. */
+  format?: ClipFormat
+  /** Frames per second kept from the capture, from 1 to 50. Defaults to 10. */
+  fps?: number
+  /** Size relative to the captured frames, greater than 0 and at most 1. Defaults to 1. */
+  scale?: number
+  /** Takes the last `durationMs` of the replay buffer instead of capturing the next ones. */
+  fromReplay?: boolean
+}
+
+/** Animation format of `exportClip()`. */
+export declare const enum ClipFormat {
+  /** One palette of up to 255 colors for the whole clip. */
+  Gif = 'gif',
+  /** Lossless. */
+  Apng = 'apng',
+}
+
+/** YUV matrix used for `I420` and `NV12` frames. */
+export declare const enum ColorMatrix {
+  Bt601 = 'BT601',
+  Bt709 = 'BT709',
+}
+
+/** Value range of `I420` and `NV12` frames. */
+export declare const enum ColorRange {
+  /** Y in 16..235, U and V in 16..240. */
+  Limited = 'Limited',
+  /** All channels in 0..255. */
+  Full = 'Full',
+}
+
+/** Pointer of the captured display or window at the time of a frame. */
+export interface CursorInfo {
+  /** Pointer position in frame pixels. Outside the frame while the pointer is elsewhere. */
+  x: number
+  y: number
+  /** False while the system hides the pointer. */
+  visible: boolean
+  /** Pixel of the shape that sits at the pointer position. */
+  hotspotX: number
+  hotspotY: number
+  /**
+   * Set on the first frame a consumer receives and whenever the shape changes. Keep the last
+   * one to draw the pointer.
+   */
+  shape?: CursorShape
+}
+
+/** Pointer image, at the resolution of the display. */
+export interface CursorShape {
+  width: number
+  height: number
+  /** Straight (not premultiplied) RGBA pixels, `width * 4` bytes per row. */
+  rgba: Buffer
+}
+
+/** Settings of delta packets, for the `delta` option and `DeltaEncoder`. */
+export interface DeltaConfig {
+  /** Side of the square tiles in pixels, from 8 to 1024 (default 64). */
+  tileSize?: number
+  /** Defaults to `zlib`. */
+  compression?: TileCompression
+  /** Quality of `jpeg` tiles from 1 to 100 (default 80). */
+  jpegQuality?: number
+}
+
+/** Display change reported to `onDisplayChange` listeners, once the session adapted to it. */
+export interface DisplayChange {
+  kind: DisplayChangeKind
+  /**
+   * Captured display after the change. Its id may differ from the one capture started with
+   * when the platform renumbered it.
+   */
+  displayId: number
+  /** Size of the captured display after the change, as `listDisplays()` reports it. */
+  width: number
+  height: number
+  /** Every display after the change. */
+  displays: Array<DisplayInfo>
+}
+
+/** What changed in the display setup of a running session, reported to `onDisplayChange`. */
+export declare const enum DisplayChangeKind {
+  /** Displays were connected, disconnected, moved or resized, other than the captured one. */
+  DisplaysChanged = 'displaysChanged',
+  /** The size of the captured display changed; frames have the new size from now on. */
+  ResolutionChanged = 'resolutionChanged',
+}
+
+export interface DisplayInfo {
+  id: number
+  name: string
+  x: number
+  y: number
+  width: number
+  height: number
+  scaleFactor: number
+  rotation: number
+  isPrimary: boolean
+}
+
+export interface EncodeConfig {
+  format: ImageFormat
+  /** JPEG quality from 1 to 100 (default 80). PNG and WebP are lossless and ignore it. */
+  quality?: number
+}
+
+/** Stable identifier of a capture error. Codes are never renamed or reused. */
+export declare const enum ErrorCode {
+  /** Screen recording was refused by the user or the OS. */
+  PermissionDenied = 'permissionDenied',
+  /** The captured display went away or can no longer be read. */
+  DisplayLost = 'displayLost',
+  /** The captured window was closed. */
+  WindowClosed = 'windowClosed',
+  /** The preferred backend is unavailable and another one is used instead. */
+  BackendFallback = 'backendFallback',
+  /** Reading a frame from the backend failed. */
+  CaptureFailed = 'captureFailed',
+  /** Scaling, converting or encoding a frame failed. */
+  ProcessingFailed = 'processingFailed',
+  /** The frame callback can no longer be called. */
+  StoppedByConsumer = 'stoppedByConsumer',
+  /** Writing a recording failed; the recording ended but capture goes on. */
+  RecordingFailed = 'recordingFailed',
 }
 
 export interface FrameData {
   width: number
   height: number
+  /** Bytes per row of packed formats, or of the Y plane of planar ones. */
   stride: number
+  format: PixelFormat
+  /** Set when the `encode` option is used. `rgba` then holds the compressed image and `stride` is 0. */
+  encoding?: ImageFormat
+  /** Pixel data laid out as described by `format`. Only RGBA by default. */
+  rgba: Buffer
+  /** Capture time in milliseconds on a monotonic clock shared by all captures of the process. */
+  timestamp: number
+  /** Position of the frame in the capture session, starting at 0 on every `start()`. */
+  sequence: number
+  /** Display the frame shows. Unset for window captures and with `stitch`. */
+  displayId?: number
+  /** Frames discarded by the backpressure policy since the previous frame this consumer received. */
+  droppedSinceLast: number
+  /** Milliseconds between capture and delivery to JavaScript. */
+  latency: number
+  /**
+   * Parts of the frame that changed since the previous frame this consumer received, when the
+   * `dirtyRects` option is set. Rectangles may overlap.
+   */
+  dirtyRects?: Array<Region>
+  /**
+   * Set with the `delta` option, where `rgba` holds a packet for `DeltaDecoder` and `stride` is
+   * 0: whether the packet is a keyframe. Frames returned by `DeltaDecoder` set it too.
+   */
+  keyframe?: boolean
+  /** Pointer position and shape, when the `cursorMetadata` option is set and the backend reports them. */
+  cursor?: CursorInfo
+}
+
+/** Compressed image format produced by `encode` and `screenshot(options)`. */
+export declare const enum ImageFormat {
+  Jpeg = 'jpeg',
+  Png = 'png',
+  /** Lossless WebP. */
+  Webp = 'webp',
+}
+
+/** Layout of `FrameData.rgba`. */
+export declare const enum PixelFormat {
+  Rgba = 'RGBA',
+  Bgra = 'BGRA',
+  Rgb = 'RGB',
+  /** Y plane, then U and V planes at half the width and height. */
+  I420 = 'I420',
+  /** Y plane, then one interleaved UV plane at half the width and height. */
+  Nv12 = 'NV12',
+}
+
+/**
+ * Packed pixels for `DeltaEncoder.encode()`. Frames delivered without `pixelFormat` or `encode`
+ * can be passed as they are.
+ */
+export interface RawFrame {
+  width: number
+  height: number
+  /** Bytes per row (default `width * 4`). */
+  stride?: number
+  /** `RGBA` or `BGRA` (default `RGBA`). */
+  format?: PixelFormat
   rgba: Buffer
+  /** Carried to the decoded frame (default 0). */
+  timestamp?: number
+  /** Carried to the decoded frame (default 0). */
+  sequence?: number
+}
+
+export interface RecordingConfig {
+  /** Defaults to `y4m` for `.y4m` paths and `raw` otherwise. */
+  format?: RecordingFormat
+  /** Frame rate of Y4M files. Defaults to the capture frame rate. */
+  fps?: number
+}
+
+/** File format of `startRecording()`. */
+export declare const enum RecordingFormat {
+  /** YUV4MPEG2 video with 4:2:0 frames at a constant frame rate. */
+  Y4m = 'y4m',
+  /** Frames as captured, plus a `.idx` text file with their timing and layout. */
+  Raw = 'raw',
+}
+
+/** What `stopRecording()` wrote. */
+export interface RecordingSummary {
+  /** Frames in the file, repeated frames included. */
+  frames: number
+  /** Frames written again to keep Y4M timing while the screen did not deliver new ones. */
+  repeatedFrames: number
+  /** Frames left out because they arrived faster than the Y4M frame rate. */
+  skippedFrames: number
+  /** Play time of Y4M files, or time from the first to the last frame of raw files, in milliseconds. */
+  duration: number
+}
+
+/** In-memory buffer of the last frames of a session, saved with `saveReplay()`. */
+export interface ReplayConfig {
+  /** How much of the session to keep, in milliseconds. */
+  durationMs: number
+  /**
+   * Memory the buffered frames may use, in bytes (default 256 MiB). The oldest frames are
+   * evicted first.
+   */
+  maxBytes?: number
+  /**
+   * Keep frames as JPEG at this quality, from 1 to 100, to hold more of them. Unset keeps raw
+   * pixels.
+   */
+  jpegQuality?: number
+}
+
+/** Rectangle relative to the top-left corner of the captured display or window. */
+export interface Region {
+  x: number
+  y: number
+  width: number
+  height: number
+}
+
+/** Resampling filter used when frames are scaled on the CPU. */
+export declare const enum ScaleFilter {
+  Nearest = 'Nearest',
+  Bilinear = 'Bilinear',
+  Lanczos = 'Lanczos',
 }
 
 export interface ScreenCaptureConfig {
   backend?: CaptureBackend
   fps?: number
+  /** Display to capture, as returned by `listDisplays`. Defaults to the primary display. */
+  displayId?: number
+  /** Window to capture instead of a display, as returned by `listWindows`. */
+  windowId?: number
+  /**
+   * Capture several displays in one session. Each frame shows one display, named by
+   * `FrameData.displayId`, unless `stitch` is set.
+   */
+  displayIds?: Array<number>
+  /** Deliver the displays of `displayIds` as one frame laid out like the virtual desktop. */
+  stitch?: boolean
+  /** Only capture this part of the display or window. */
+  region?: Region
+  /** Scale frames to this width. When only one of `outputWidth`/`outputHeight` is set the other follows the aspect ratio. */
+  outputWidth?: number
+  outputHeight?: number
+  /** Scale frames down to at most this width, keeping the aspect ratio. */
+  maxWidth?: number
+  /** Resampling filter for CPU scaling (default `Bilinear`). */
+  scaleFilter?: ScaleFilter
+  /** Layout of delivered frames (default `RGBA`). */
+  pixelFormat?: PixelFormat
+  /** YUV matrix for `I420`/`NV12` (default `BT601`). */
+  colorMatrix?: ColorMatrix
+  /** YUV range for `I420`/`NV12` (default `Limited`). */
+  colorRange?: ColorRange
+  /** Deliver compressed images instead of raw pixels. Cannot be combined with `pixelFormat`. */
+  encode?: EncodeConfig
+  /** What to do with new frames while the callback or an iterator is behind (default `dropOldest`). */
+  backpressure?: Backpressure
+  /** Frames that may wait for each consumer before `backpressure` applies (default 4). */
+  maxQueuedFrames?: number
+  /** Keep the last frames of the session in memory for `saveReplay()`. */
+  replay?: ReplayConfig
+  /** Report the parts of each frame that changed in `FrameData.dirtyRects`. */
+  dirtyRects?: boolean
+  /** Only deliver frames whose content changed. */
+  emitOnlyOnChange?: boolean
+  /** With `emitOnlyOnChange`, repeat the last frame when none was delivered for this many milliseconds. */
+  keepaliveMs?: number
+  /**
+   * Deliver delta packets of the changed tiles instead of frames, for `DeltaDecoder`. Cannot be
+   * combined with `pixelFormat` or `encode`.
+   */
+  delta?: DeltaConfig
+  /**
+   * Include the pointer in the frames (default true). Ignored by the `XCap` backend and GDI
+   * fallback, which capture whatever the system draws.
+   */
+  showCursor?: boolean
+  /**
+   * Report the pointer position and shape in `FrameData.cursor`, so viewers can draw it
+   * themselves. Combine with `showCursor: false` to keep it out of the pixels.
+   */
+  cursorMetadata?: boolean
+  synthetic?: SyntheticConfig
+}
+
+/** Test pattern settings used by the `Synthetic` backend. */
+export interface SyntheticConfig {
+  width?: number
+  height?: number
+  /** Number of synthetic displays reported by `listDisplays` (default 1). */
+  displays?: number
+  /** Number of synthetic windows reported by `listWindows` (default 0). */
+  windows?: number
+  failOnStart?: boolean
+  failScreenshot?: boolean
+  failAfterFrames?: number
+  /** Show every image for this many frames (default 1), like a mostly still screen. */
+  holdFrames?: number
+  /** Simulate a pointer moving over the test pattern (default false). */
+  cursor?: boolean
+  /** Swap the width and height of the displays from this frame on, as if they were rotated. */
+  rotateAfterFrames?: number
+  /** Report one more display from this frame on, as if it was connected. */
+  connectAfterFrames?: number
+}
+
+/** How `delta` packets compress their tiles. */
+export declare const enum TileCompression {
+  Raw = 'raw',
+  /** Tiles zlib does not shrink are sent raw. */
+  Zlib = 'zlib',
+  /** Lossy and without alpha, for photos and video on screen. */
+  Jpeg = 'jpeg',
+}
+
+export interface WindowInfo {
+  id: number
+  title: string
+  appName: string
+  pid: number
+  x: number
+  y: number
+  width: number
+  height: number
+  isMinimized: boolean
+  isOnScreen: boolean
 }
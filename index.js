@@ -576,5 +576,20 @@ if (!nativeBinding) {
 }
 
 module.exports = nativeBinding
+module.exports.DeltaDecoder = nativeBinding.DeltaDecoder
+module.exports.DeltaEncoder = nativeBinding.DeltaEncoder
+module.exports.FrameIterator = nativeBinding.FrameIterator
 module.exports.ScreenCapture = nativeBinding.ScreenCapture
+module.exports.Backpressure = nativeBinding.Backpressure
 module.exports.CaptureBackend = nativeBinding.CaptureBackend
+module.exports.CaptureState = nativeBinding.CaptureState
+module.exports.ClipFormat = nativeBinding.ClipFormat
+module.exports.ColorMatrix = nativeBinding.ColorMatrix
+module.exports.ColorRange = nativeBinding.ColorRange
+module.exports.DisplayChangeKind = nativeBinding.DisplayChangeKind
+module.exports.ErrorCode = nativeBinding.ErrorCode
+module.exports.ImageFormat = nativeBinding.ImageFormat
+module.exports.PixelFormat = nativeBinding.PixelFormat
+module.exports.RecordingFormat = nativeBinding.RecordingFormat
+module.exports.ScaleFilter = nativeBinding.ScaleFilter
+module.exports.TileCompression = nativeBinding.TileCompression
@@ -1,5 +1,41 @@
 import { createRequire } from 'module'
 const require = createRequire(import.meta.url)
-const { CaptureBackend, ScreenCapture } = require('./index.js')
+const {
+  Backpressure,
+  CaptureBackend,
+  CaptureState,
+  ClipFormat,
+  ColorMatrix,
+  ColorRange,
+  DeltaDecoder,
+  DeltaEncoder,
+  DisplayChangeKind,
+  ErrorCode,
+  FrameIterator,
+  ImageFormat,
+  PixelFormat,
+  RecordingFormat,
+  ScaleFilter,
+  ScreenCapture,
+  TileCompression,
+} = require('./index.js')
 
-export { CaptureBackend, ScreenCapture }
+export {
+  Backpressure,
+  CaptureBackend,
+  CaptureState,
+  ClipFormat,
+  ColorMatrix,
+  ColorRange,
+  DeltaDecoder,
+  DeltaEncoder,
+  DisplayChangeKind,
+  ErrorCode,
+  FrameIterator,
+  ImageFormat,
+  PixelFormat,
+  RecordingFormat,
+  ScaleFilter,
+  ScreenCapture,
+  TileCompression,
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use color_quant::NeuQuant;
+
+use super::output::{self, ScaleFilter};
+use super::pixel::{self, PixelFormat, YuvOptions};
+use super::FrameDataInternal;
+use crate::error::{Error, Result, Status};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClipFormat {
+  /// Looping GIF with one palette of up to 255 colors for the whole clip.
+  #[default]
+  Gif,
+  /// Looping APNG, lossless.
+  Apng,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ClipOptions {
+  pub format: ClipFormat,
+  /// Length of the clip, counted from its first frame.
+  pub duration: Duration,
+  /// Frames per second kept from the capture, at most `MAX_FPS`.
+  pub fps: u32,
+  /// Size of the clip relative to the captured frames, in `(0, 1]`.
+  pub scale: f64,
+  /// Take the last `duration` of the replay buffer instead of the next frames of the session.
+  pub from_replay: bool,
+}
+
+impl ClipOptions {
+  pub const DEFAULT_FPS: u32 = 10;
+  /// GIF delays are counted in hundredths of a second and players slow down anything faster.
+  pub const MAX_FPS: u32 = 50;
+
+  pub fn validate(&self) -> Result<()> {
+    if self.duration.is_zero() {
+      return Err(invalid("durationMs must be greater than 0"));
+    }
+    if !(1..=Self::MAX_FPS).contains(&self.fps) {
+      return Err(invalid(&format!(
+        "fps must be between 1 and {}",
+        Self::MAX_FPS
+      )));
+    }
+    if !(self.scale > 0.0 && self.scale <= 1.0) {
+      return Err(invalid("scale must be greater than 0 and at most 1"));
+    }
+    Ok(())
+  }
+}
+
+/// A clip frame: packed RGB pixels and when it starts, relative to the first frame.
+struct ClipFrame {
+  rgb: Vec<u8>,
+  start: Duration,
+}
+
+/// Collects frames for a clip, keeping at most `fps` frames per second, and encodes them.
+pub struct Clip {
+  options: ClipOptions,
+  /// Timestamp of the first frame.
+  origin: Option<Duration>,
+  /// Size of every frame, from the first captured frame and `scale`.
+  size: (u32, u32),
+  frames: Vec<ClipFrame>,
+}
+
+impl Clip {
+  pub fn new(options: ClipOptions) -> Self {
+    Self {
+      options,
+      origin: None,
+      size: (0, 0),
+      frames: Vec::new(),
+    }
+  }
+
+  /// Adds an `Rgba` or `Bgra` frame. Returns false, without adding it, once the frame is past
+  /// the end of the clip.
+  pub fn push(&mut self, frame: &FrameDataInternal) -> bool {
+    let origin = *self.origin.get_or_insert_with(|| {
+      let scaled = |side: u32| ((side as f64 * self.options.scale).round() as u32).max(1);
+      self.size = (scaled(frame.width), scaled(frame.height));
+      frame.timestamp
+    });
+    let start = frame.timestamp.saturating_sub(origin);
+    if start >= self.options.duration {
+      return false;
+    }
+    // One frame per 1/fps slot: the first one captured in it.
+    let slot = |start: Duration| (start.as_secs_f64() * self.options.fps as f64) as u64;
+    if self
+      .frames
+      .last()
+      .is_some_and(|last| slot(last.start) == slot(start))
+    {
+      return true;
+    }
+
+    let (width, height) = self.size;
+    let frame = if (frame.width, frame.height) == (width, height) {
+      frame.clone()
+    } else {
+      output::scale_frame(frame, width, height, ScaleFilter::Bilinear)
+    };
+    let rgb = pixel::convert(frame, PixelFormat::Rgb, YuvOptions::default()).data;
+    self.frames.push(ClipFrame { rgb, start });
+    true
+  }
+
+  /// Encodes the clip as a looping animation. Each frame is shown until the next one was
+  /// captured, and the last one until the end of the clip.
+  pub fn encode(mut self) -> Result<Vec<u8>> {
+    if self.frames.is_empty() {
+      return Err(Error::new(
+        Status::GenericFailure,
+        "No frames were captured for the clip".to_string(),
+      ));
+    }
+    // A still screen becomes one long frame.
+    self.frames.dedup_by(|next, prev| next.rgb == prev.rgb);
+    let mut ends: Vec<Duration> = self.frames.iter().skip(1).map(|f| f.start).collect();
+    ends.push(self.options.duration);
+
+    let result = match self.options.format {
+      ClipFormat::Gif => encode_gif(self.size, &self.frames, &ends),
+      ClipFormat::Apng => encode_apng(self.size, &self.frames, &ends),
+    };
+    result.map_err(|e| {
+      Error::new(
+        Status::GenericFailure,
+        format!("Failed to encode clip: {}", e),
+      )
+    })
+  }
+}
+
+fn invalid(message: &str) -> Error {
+  Error::new(Status::InvalidArg, message.to_string())
+}
+
+/// Delays between `starts` and `ends` in units of `1 / per_second`, rounded so that they add up
+/// to the clip length.
+fn delays(frames: &[ClipFrame], ends: &[Duration], per_second: f64) -> Vec<u16> {
+  let units = |time: Duration| (time.as_secs_f64() * per_second).round() as i64;
+  frames
+    .iter()
+    .zip(ends)
+    .map(|(frame, &end)| (units(end) - units(frame.start)).clamp(1, u16::MAX as i64) as u16)
+    .collect()
+}
+
+/// Smallest rectangle `(x, y, width, height)` containing every pixel of `(width, height)` frames
+/// for which `changed(index)` is true, or `None` when there is none.
+fn changed_rect(
+  (width, height): (u32, u32),
+  changed: impl Fn(usize) -> bool,
+) -> Option<(u32, u32, u32, u32)> {
+  let (width, height) = (width as usize, height as usize);
+  let row_changed = |y: usize| (0..width).any(|x| changed(y * width + x));
+  let top = (0..height).find(|&y| row_changed(y))?;
+  let bottom = (top..height).rev().find(|&y| row_changed(y))?;
+  let column_changed = |x: usize| (top..=bottom).any(|y| changed(y * width + x));
+  let left = (0..width).find(|&x| column_changed(x))?;
+  let right = (left..width).rev().find(|&x| column_changed(x))?;
+  Some((
+    left as u32,
+    top as u32,
+    (right - left + 1) as u32,
+    (bottom - top + 1) as u32,
+  ))
+}
+
+/// Palette index marking pixels that did not change since the previous frame.
+const TRANSPARENT: u8 = 255;
+
+/// Maps RGB pixels to indices of a palette of at most 255 colors.
+enum Palette {
+  /// The clip has few enough colors to keep all of them.
+  Exact(HashMap<[u8; 3], u8>),
+  Quantized(NeuQuant),
+}
+
+impl Palette {
+  /// Number of pixels the quantizer learns from.
+  const SAMPLE_PIXELS: usize = 1 << 19;
+
+  fn new(frames: &[ClipFrame]) -> Self {
+    let mut colors = HashMap::new();
+    'frames: for frame in frames {
+      for px in frame.rgb.chunks_exact(3) {
+        let next = colors.len();
+        colors.entry([px[0], px[1], px[2]]).or_insert(next as u8);
+        if colors.len() > TRANSPARENT as usize {
+          break 'frames;
+        }
+      }
+    }
+    if colors.len() <= TRANSPARENT as usize {
+      return Self::Exact(colors);
+    }
+
+    // Learn from pixels spread over every frame, so colors shown only briefly are kept too.
+    let pixels: usize = frames.iter().map(|f| f.rgb.len() / 3).sum();
+    let step = pixels.div_ceil(Self::SAMPLE_PIXELS);
+    let mut sample = Vec::with_capacity(pixels / step * 4 + 4);
+    for px in frames
+      .iter()
+      .flat_map(|f| f.rgb.chunks_exact(3))
+      .step_by(step)
+    {
+      sample.extend([px[0], px[1], px[2], 255]);
+    }
+    Self::Quantized(NeuQuant::new(10, TRANSPARENT as usize, &sample))
+  }
+
+  /// Colors of the indices, as GIF expects them: RGB triplets, with room for `TRANSPARENT`.
+  fn colors(&self) -> Vec<u8> {
+    let mut table = vec![0; 256 * 3];
+    match self {
+      Self::Exact(colors) => {
+        for (rgb, &index) in colors {
+          table[index as usize * 3..][..3].copy_from_slice(rgb);
+        }
+      }
+      Self::Quantized(quantizer) => {
+        let map = quantizer.color_map_rgb();
+        table[..map.len()].copy_from_slice(&map);
+      }
+    }
+    table
+  }
+
+  fn index(&self, rgb: &[u8]) -> Vec<u8> {
+    rgb
+      .chunks_exact(3)
+      .map(|px| match self {
+        Self::Exact(colors) => colors[&[px[0], px[1], px[2]]],
+        Self::Quantized(quantizer) => quantizer.index_of(&[px[0], px[1], px[2], 255]) as u8,
+      })
+      .collect()
+  }
+}
+
+/// Writes every frame after the first as the rectangle that changed, with unchanged pixels
+/// transparent so they compress well.
+fn encode_gif(
+  (width, height): (u32, u32),
+  frames: &[ClipFrame],
+  ends: &[Duration],
+) -> std::result::Result<Vec<u8>, String> {
+  let too_large = || format!("GIF frames are limited to 65535x65535, got {width}x{height}");
+  let gif_width = u16::try_from(width).map_err(|_| too_large())?;
+  let gif_height = u16::try_from(height).map_err(|_| too_large())?;
+  let palette = Palette::new(frames);
+
+  let mut data = Vec::new();
+  let mut encoder = gif::Encoder::new(&mut data, gif_width, gif_height, &palette.colors())
+    .map_err(|e| e.to_string())?;
+  encoder
+    .set_repeat(gif::Repeat::Infinite)
+    .map_err(|e| e.to_string())?;
+
+  // Frames that only differ in colors the palette merged are written as one.
+  let mut pending: Option<(gif::Frame, u16)> = None;
+  let mut previous: Option<Vec<u8>> = None;
+  for (frame, delay) in frames.iter().zip(delays(frames, ends, 100.0)) {
+    let indices = palette.index(&frame.rgb);
+    let mut gif_frame = match &previous {
+      None => gif::Frame {
+        width: gif_width,
+        height: gif_height,
+        buffer: indices.clone().into(),
+        ..gif::Frame::default()
+      },
+      Some(previous) => {
+        let Some((x, y, w, h)) = changed_rect((width, height), |i| indices[i] != previous[i])
+        else {
+          if let Some((_, pending_delay)) = &mut pending {
+            *pending_delay = pending_delay.saturating_add(delay);
+          }
+          continue;
+        };
+        let mut buffer = Vec::with_capacity((w * h) as usize);
+        for row in y..y + h {
+          let start = (row * width + x) as usize;
+          buffer.extend(
+            indices[start..start + w as usize]
+              .iter()
+              .zip(&previous[start..])
+              .map(|(&index, &old)| if index == old { TRANSPARENT } else { index }),
+          );
+        }
+        gif::Frame {
+          left: x as u16,
+          top: y as u16,
+          width: w as u16,
+          height: h as u16,
+          transparent: Some(TRANSPARENT),
+          buffer: buffer.into(),
+          ..gif::Frame::default()
+        }
+      }
+    };
+    gif_frame.dispose = gif::DisposalMethod::Keep;
+    if let Some((mut done, delay)) = pending.replace((gif_frame, delay)) {
+      done.delay = delay;
+      encoder.write_frame(&done).map_err(|e| e.to_string())?;
+    }
+    previous = Some(indices);
+  }
+  if let Some((mut last, delay)) = pending {
+    last.delay = delay;
+    encoder.write_frame(&last).map_err(|e| e.to_string())?;
+  }
+
+  drop(encoder);
+  Ok(data)
+}
+
+/// Writes every frame after the first as the rectangle that changed.
+fn encode_apng(
+  (width, height): (u32, u32),
+  frames: &[ClipFrame],
+  ends: &[Duration],
+) -> std::result::Result<Vec<u8>, String> {
+  let mut data = Vec::new();
+  let mut encoder = png::Encoder::new(&mut data, width, height);
+  encoder.set_color(png::ColorType::Rgb);
+  encoder.set_depth(png::BitDepth::Eight);
+  encoder.set_compression(png::Compression::Fast);
+  encoder
+    .set_animated(frames.len() as u32, 0)
+    .map_err(|e| e.to_string())?;
+  let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+
+  let row = width as usize * 3;
+  let mut previous: Option<&[u8]> = None;
+  for (frame, delay) in frames.iter().zip(delays(frames, ends, 1000.0)) {
+    let (x, y, w, h) = match previous {
+      None => (0, 0, width, height),
+      // Frames were deduplicated, so something changed.
+      Some(previous) => changed_rect((width, height), |i| {
+        frame.rgb[i * 3..i * 3 + 3] != previous[i * 3..i * 3 + 3]
+      })
+      .unwrap_or((0, 0, 1, 1)),
+    };
+    let mut rect = Vec::with_capacity((w * h * 3) as usize);
+    for line in frame
+      .rgb
+      .chunks_exact(row)
+      .skip(y as usize)
+      .take(h as usize)
+    {
+      rect.extend_from_slice(&line[x as usize * 3..(x + w) as usize * 3]);
+    }
+
+    let result = writer
+      .set_frame_delay(delay, 1000)
+      .and_then(|()| writer.reset_frame_position())
+      .and_then(|()| writer.set_frame_dimension(w, h))
+      .and_then(|()| writer.set_frame_position(x, y))
+      .and_then(|()| writer.set_blend_op(png::BlendOp::Source))
+      .and_then(|()| writer.set_dispose_op(png::DisposeOp::None))
+      .and_then(|()| writer.write_image_data(&rect));
+    result.map_err(|e| e.to_string())?;
+    previous = Some(&frame.rgb);
+  }
+  writer.finish().map_err(|e| e.to_string())?;
+  Ok(data)
+}
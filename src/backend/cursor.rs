@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use super::pixel::PixelFormat;
+use super::{FrameDataInternal, RegionInternal};
+
+/// Pointer image, in straight (not premultiplied) RGBA.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CursorShape {
+  /// Changes whenever the image does, so consumers only need the pixels of ids they have not seen.
+  pub id: u64,
+  pub width: u32,
+  pub height: u32,
+  /// Pixel of the image that sits at the pointer position.
+  pub hotspot_x: u32,
+  pub hotspot_y: u32,
+  pub data: Vec<u8>,
+}
+
+/// Pointer of the captured display or window at the time of a frame.
+#[derive(Clone, Debug)]
+pub struct CursorInternal {
+  /// Pointer position in frame pixels. Outside the frame while the pointer is elsewhere.
+  pub x: i32,
+  pub y: i32,
+  /// False while the system hides the pointer.
+  pub visible: bool,
+  /// Current image, when the platform reports it.
+  pub shape: Option<Arc<CursorShape>>,
+  /// Whether the consumer has not received `shape` yet. `FrameQueue` clears it when it hands
+  /// out a frame whose shape the consumer already got.
+  pub shape_changed: bool,
+}
+
+impl CursorInternal {
+  pub fn new(x: i32, y: i32, visible: bool, shape: Option<Arc<CursorShape>>) -> Self {
+    Self {
+      x,
+      y,
+      visible,
+      shape,
+      shape_changed: true,
+    }
+  }
+
+  pub fn shape_id(&self) -> Option<u64> {
+    self.shape.as_ref().map(|shape| shape.id)
+  }
+
+  /// Whether the pointer looks different from `other`: it moved, appeared, disappeared or
+  /// changed shape.
+  pub fn differs(&self, other: &Self) -> bool {
+    (self.x, self.y, self.visible, self.shape_id())
+      != (other.x, other.y, other.visible, other.shape_id())
+  }
+
+  /// The pointer of a `from` sized frame scaled to `to`. The shape keeps its size.
+  pub fn scale(&self, from: (u32, u32), to: (u32, u32)) -> Self {
+    let scale = |v: i32, from: u32, to: u32| (v as i64 * to as i64).div_euclid(from.max(1) as i64);
+    Self {
+      x: scale(self.x, from.0, to.0) as i32,
+      y: scale(self.y, from.1, to.1) as i32,
+      ..self.clone()
+    }
+  }
+
+  /// Part of a `width` x `height` frame the pointer image covers, or `None` when it is hidden,
+  /// has no known shape or lies outside the frame.
+  pub fn bounds(&self, width: u32, height: u32) -> Option<RegionInternal> {
+    let shape = self.shape.as_deref().filter(|_| self.visible)?;
+    let left = self.x as i64 - shape.hotspot_x as i64;
+    let top = self.y as i64 - shape.hotspot_y as i64;
+    let right = (left + shape.width as i64).min(width as i64);
+    let bottom = (top + shape.height as i64).min(height as i64);
+    let (left, top) = (left.max(0), top.max(0));
+    (left < right && top < bottom).then(|| RegionInternal {
+      x: left as u32,
+      y: top as u32,
+      width: (right - left) as u32,
+      height: (bottom - top) as u32,
+    })
+  }
+
+  /// Blends the pointer image onto an `Rgba` or `Bgra` frame.
+  pub fn draw(&self, frame: &mut FrameDataInternal) {
+    let (Some(shape), Some(rect)) = (&self.shape, self.bounds(frame.width, frame.height)) else {
+      return;
+    };
+    let bgra = frame.format == PixelFormat::Bgra;
+    let left = self.x as i64 - shape.hotspot_x as i64;
+    let top = self.y as i64 - shape.hotspot_y as i64;
+    for y in rect.y..rect.y + rect.height {
+      let row = (y as i64 - top) as usize * shape.width as usize;
+      for x in rect.x..rect.x + rect.width {
+        let src = (row + (x as i64 - left) as usize) * 4;
+        let src = &shape.data[src..src + 4];
+        let alpha = src[3] as u32;
+        if alpha == 0 {
+          continue;
+        }
+        let dst = y as usize * frame.stride as usize + x as usize * 4;
+        let dst = &mut frame.data[dst..dst + 3];
+        for (c, dst) in dst.iter_mut().enumerate() {
+          let src = src[if bgra { 2 - c } else { c }] as u32;
+          *dst = ((src * alpha + *dst as u32 * (255 - alpha) + 127) / 255) as u8;
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,499 @@
+//! Delta packets: the tiles of a frame that changed since the previous packet, for streaming a
+//! screen to a remote viewer. All integers are little endian.
+//!
+//! | Offset | Size | Field                                                    |
+//! | ------ | ---- | -------------------------------------------------------- |
+//! | 0      | 4    | Magic `RSDT`                                             |
+//! | 4      | 1    | Version, 1                                               |
+//! | 5      | 1    | Flags; bit 0 marks a keyframe, which holds every tile    |
+//! | 6      | 2    | Tile size in pixels                                      |
+//! | 8      | 4    | Frame width                                              |
+//! | 12     | 4    | Frame height                                             |
+//! | 16     | 4    | Packet index, counted from the start of the encoder      |
+//! | 20     | 4    | Number of tiles                                          |
+//! | 24     | 8    | Frame sequence                                           |
+//! | 32     | 8    | Frame timestamp in microseconds                          |
+//!
+//! Each tile follows as its column and row (2 bytes each), its compression (1 byte: 0 raw,
+//! 1 zlib, 2 JPEG), the length of its data (4 bytes) and the data. Raw data is the RGBA rows
+//! of the tile; tiles on the right and bottom edges are cut to the frame.
+//!
+//! Frames are limited to `MAX_FRAME_BYTES` of RGBA pixels, so a forged header cannot make the
+//! decoder allocate more.
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+
+use super::pixel::{self, PixelFormat, YuvOptions};
+use super::{FrameDataInternal, RegionInternal};
+use crate::error::{Error, Result, Status};
+
+const MAGIC: &[u8; 4] = b"RSDT";
+const VERSION: u8 = 1;
+const KEYFRAME: u8 = 1;
+const HEADER_SIZE: usize = 40;
+/// Column, row, compression and length of a tile.
+const TILE_HEADER_SIZE: usize = 9;
+/// Largest RGBA frame a packet may describe, enough for 8K by 8K.
+pub const MAX_FRAME_BYTES: usize = 256 << 20;
+
+/// Size of a `width` by `height` RGBA frame, or `None` above `MAX_FRAME_BYTES`.
+fn frame_bytes(width: u32, height: u32) -> Option<usize> {
+  (width as usize)
+    .checked_mul(height as usize)
+    .and_then(|pixels| pixels.checked_mul(4))
+    .filter(|&bytes| bytes <= MAX_FRAME_BYTES)
+}
+
+fn too_large(width: u32, height: u32) -> Error {
+  invalid(&format!(
+    "{}x{} frames are larger than the {} MiB limit of delta packets",
+    width,
+    height,
+    MAX_FRAME_BYTES >> 20
+  ))
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileCompression {
+  Raw,
+  /// Falls back to raw for tiles zlib does not shrink.
+  #[default]
+  Zlib,
+  /// Lossy; alpha is dropped and decodes as opaque.
+  Jpeg,
+}
+
+impl TileCompression {
+  fn code(self) -> u8 {
+    match self {
+      Self::Raw => 0,
+      Self::Zlib => 1,
+      Self::Jpeg => 2,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DeltaOptions {
+  /// Side of the square tiles, from `MIN_TILE_SIZE` to `MAX_TILE_SIZE`.
+  pub tile_size: u32,
+  pub compression: TileCompression,
+  /// JPEG quality, 1 to 100. Only used with `TileCompression::Jpeg`.
+  pub jpeg_quality: u8,
+}
+
+impl DeltaOptions {
+  pub const DEFAULT_TILE_SIZE: u32 = 64;
+  pub const MIN_TILE_SIZE: u32 = 8;
+  pub const MAX_TILE_SIZE: u32 = 1024;
+  pub const DEFAULT_JPEG_QUALITY: u8 = 80;
+
+  pub fn validate(&self) -> Result<()> {
+    if !(Self::MIN_TILE_SIZE..=Self::MAX_TILE_SIZE).contains(&self.tile_size) {
+      return Err(invalid(&format!(
+        "tileSize must be between {} and {}",
+        Self::MIN_TILE_SIZE,
+        Self::MAX_TILE_SIZE
+      )));
+    }
+    if !(1..=100).contains(&self.jpeg_quality) {
+      return Err(invalid("jpegQuality must be between 1 and 100"));
+    }
+    Ok(())
+  }
+}
+
+impl Default for DeltaOptions {
+  fn default() -> Self {
+    Self {
+      tile_size: Self::DEFAULT_TILE_SIZE,
+      compression: TileCompression::default(),
+      jpeg_quality: Self::DEFAULT_JPEG_QUALITY,
+    }
+  }
+}
+
+/// Turns frames into delta packets, each holding the tiles that differ from the frame before.
+pub struct DeltaEncoder {
+  options: DeltaOptions,
+  /// Last frame encoded, as `Rgba`, that the next one is compared with.
+  previous: Option<FrameDataInternal>,
+  /// Index of the next packet.
+  index: u32,
+  keyframe_requested: bool,
+}
+
+impl DeltaEncoder {
+  pub fn new(options: DeltaOptions) -> Self {
+    Self {
+      options,
+      previous: None,
+      index: 0,
+      keyframe_requested: false,
+    }
+  }
+
+  /// Makes the next packet a keyframe, for a viewer that joins or lost a packet.
+  pub fn request_keyframe(&mut self) {
+    self.keyframe_requested = true;
+  }
+
+  /// Starts over at packet 0 with a keyframe, at the start of a session.
+  pub fn reset(&mut self) {
+    *self = Self::new(self.options);
+  }
+
+  /// Encodes a packed 4-byte frame. The result keeps the frame size and timing, has a `stride`
+  /// of 0, `data` holding the packet and `keyframe` set.
+  pub fn encode(&mut self, frame: FrameDataInternal) -> Result<FrameDataInternal> {
+    let frame = pixel::convert(frame, PixelFormat::Rgba, YuvOptions::default());
+    if !matches!(frame.format, PixelFormat::Rgba) {
+      return Err(invalid("Delta encoding needs RGBA or BGRA frames"));
+    }
+    let (width, height) = (frame.width, frame.height);
+    let row = width as usize * 4;
+    if width == 0
+      || height == 0
+      || (frame.stride as usize) < row
+      || frame.data.len() < (height as usize - 1) * frame.stride as usize + row
+    {
+      return Err(invalid(&format!(
+        "Frame buffer does not hold {}x{} pixels",
+        width, height
+      )));
+    }
+    if frame_bytes(width, height).is_none() {
+      return Err(too_large(width, height));
+    }
+    let keyframe = self.keyframe_requested
+      || self
+        .previous
+        .as_ref()
+        .is_none_or(|previous| (previous.width, previous.height) != (width, height));
+
+    let tile_size = self.options.tile_size;
+    let mut packet = Vec::with_capacity(HEADER_SIZE);
+    packet.extend_from_slice(MAGIC);
+    packet.push(VERSION);
+    packet.push(if keyframe { KEYFRAME } else { 0 });
+    packet.extend_from_slice(&(tile_size as u16).to_le_bytes());
+    packet.extend_from_slice(&width.to_le_bytes());
+    packet.extend_from_slice(&height.to_le_bytes());
+    packet.extend_from_slice(&self.index.to_le_bytes());
+    packet.extend_from_slice(&0u32.to_le_bytes());
+    packet.extend_from_slice(&frame.sequence.to_le_bytes());
+    packet.extend_from_slice(&(frame.timestamp.as_micros() as u64).to_le_bytes());
+
+    let mut tiles = 0u32;
+    for row in 0..height.div_ceil(tile_size) {
+      for column in 0..width.div_ceil(tile_size) {
+        let tile = RegionInternal {
+          x: column * tile_size,
+          y: row * tile_size,
+          width: tile_size.min(width - column * tile_size),
+          height: tile_size.min(height - row * tile_size),
+        };
+        if !keyframe && !self.changed(&frame, tile) {
+          continue;
+        }
+        let (compression, data) = self.compress(pixels(&frame, tile), tile)?;
+        packet.extend_from_slice(&(column as u16).to_le_bytes());
+        packet.extend_from_slice(&(row as u16).to_le_bytes());
+        packet.push(compression.code());
+        packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&data);
+        tiles += 1;
+      }
+    }
+    packet[20..24].copy_from_slice(&tiles.to_le_bytes());
+
+    self.index = self.index.wrapping_add(1);
+    self.keyframe_requested = false;
+    let stride = frame.stride;
+    let mut encoded = FrameDataInternal {
+      stride: 0,
+      keyframe: Some(keyframe),
+      ..frame
+    };
+    let pixels = std::mem::replace(&mut encoded.data, packet);
+    self.previous = Some(FrameDataInternal {
+      width,
+      height,
+      stride,
+      data: pixels,
+      ..Default::default()
+    });
+    Ok(encoded)
+  }
+
+  fn changed(&self, frame: &FrameDataInternal, tile: RegionInternal) -> bool {
+    let Some(previous) = &self.previous else {
+      return true;
+    };
+    let bytes = tile.width as usize * 4;
+    (tile.y..tile.y + tile.height).any(|y| {
+      let a = y as usize * previous.stride as usize + tile.x as usize * 4;
+      let b = y as usize * frame.stride as usize + tile.x as usize * 4;
+      previous.data[a..a + bytes] != frame.data[b..b + bytes]
+    })
+  }
+
+  fn compress(&self, pixels: Vec<u8>, tile: RegionInternal) -> Result<(TileCompression, Vec<u8>)> {
+    match self.options.compression {
+      TileCompression::Raw => Ok((TileCompression::Raw, pixels)),
+      TileCompression::Zlib => {
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+        let data = encoder
+          .write_all(&pixels)
+          .and_then(|()| encoder.finish())
+          .map_err(|e| failed("compress", e))?;
+        Ok(if data.len() < pixels.len() {
+          (TileCompression::Zlib, data)
+        } else {
+          (TileCompression::Raw, pixels)
+        })
+      }
+      TileCompression::Jpeg => {
+        let rgb: Vec<u8> = pixels
+          .chunks_exact(4)
+          .flat_map(|px| [px[0], px[1], px[2]])
+          .collect();
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, self.options.jpeg_quality)
+          .write_image(&rgb, tile.width, tile.height, ExtendedColorType::Rgb8)
+          .map_err(|e| failed("compress", e))?;
+        Ok((TileCompression::Jpeg, data))
+      }
+    }
+  }
+}
+
+/// Rebuilds frames from the packets of a `DeltaEncoder`.
+#[derive(Default)]
+pub struct DeltaDecoder {
+  /// Frame the next delta packet applies to.
+  frame: Option<FrameDataInternal>,
+  /// Index of the last packet applied; unset until a keyframe arrives or after a bad packet.
+  index: Option<u32>,
+}
+
+impl DeltaDecoder {
+  /// Applies a packet and returns the whole `Rgba` frame, with the decoded tiles as its
+  /// `dirty_rects`. Delta packets must follow the previous packet; after a missing or bad one,
+  /// only a keyframe is accepted.
+  pub fn decode(&mut self, packet: &[u8]) -> Result<FrameDataInternal> {
+    let result = self.apply(packet);
+    if result.is_err() {
+      self.index = None;
+    }
+    result
+  }
+
+  /// Forgets the current frame, so only a keyframe is accepted next.
+  pub fn reset(&mut self) {
+    *self = Self::default();
+  }
+
+  fn apply(&mut self, packet: &[u8]) -> Result<FrameDataInternal> {
+    let mut reader = Reader(packet);
+    if reader.take(4)? != MAGIC {
+      return Err(invalid("Not a delta packet"));
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+      return Err(invalid(&format!(
+        "Unsupported delta packet version {}",
+        version
+      )));
+    }
+    let keyframe = reader.u8()? & KEYFRAME != 0;
+    let tile_size = reader.u16()? as u32;
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    let index = reader.u32()?;
+    let tiles = reader.u32()?;
+    let sequence = reader.u64()?;
+    let timestamp = Duration::from_micros(reader.u64()?);
+    if tile_size == 0 || width == 0 || height == 0 {
+      return Err(truncated());
+    }
+    let Some(bytes) = frame_bytes(width, height) else {
+      return Err(too_large(width, height));
+    };
+    // A keyframe holds every tile, and each tile takes at least its header.
+    let grid = width.div_ceil(tile_size) as u64 * height.div_ceil(tile_size) as u64;
+    if tiles as u64 > grid
+      || (keyframe && tiles as u64 != grid)
+      || tiles as usize > reader.0.len() / TILE_HEADER_SIZE
+    {
+      return Err(truncated());
+    }
+
+    if !keyframe {
+      match self.index {
+        Some(last) if last.wrapping_add(1) == index => {}
+        Some(last) => {
+          return Err(Error::new(
+            Status::GenericFailure,
+            format!(
+              "Delta packet {} does not follow packet {}, wait for a keyframe",
+              index, last
+            ),
+          ))
+        }
+        None => {
+          return Err(Error::new(
+            Status::GenericFailure,
+            format!("Delta packet {} needs a keyframe first", index),
+          ))
+        }
+      }
+    }
+    let mut frame = match self.frame.take() {
+      Some(frame) if !keyframe => frame,
+      _ => FrameDataInternal {
+        width,
+        height,
+        stride: width * 4,
+        format: PixelFormat::Rgba,
+        data: vec![0; bytes],
+        ..Default::default()
+      },
+    };
+    if (frame.width, frame.height) != (width, height) {
+      return Err(invalid(&format!(
+        "Delta packet {} is {}x{} but the frame it applies to is {}x{}",
+        index, width, height, frame.width, frame.height
+      )));
+    }
+
+    let mut rects = Vec::with_capacity(tiles as usize);
+    for _ in 0..tiles {
+      let column = reader.u16()? as u32;
+      let row = reader.u16()? as u32;
+      let compression = reader.u8()?;
+      let length = reader.u32()? as usize;
+      let data = reader.take(length)?;
+      let tile = RegionInternal {
+        x: column * tile_size,
+        y: row * tile_size,
+        width: tile_size,
+        height: tile_size,
+      }
+      .clamp(width, height)
+      .ok_or_else(|| invalid("Delta packet has a tile outside the frame"))?;
+      let pixels = decompress(compression, data, tile)?;
+      let bytes = tile.width as usize * 4;
+      for (y, source) in (tile.y..tile.y + tile.height).zip(pixels.chunks_exact(bytes)) {
+        let start = y as usize * frame.stride as usize + tile.x as usize * 4;
+        frame.data[start..start + bytes].copy_from_slice(source);
+      }
+      rects.push(tile);
+    }
+
+    self.index = Some(index);
+    let decoded = FrameDataInternal {
+      sequence,
+      timestamp,
+      keyframe: Some(keyframe),
+      dirty_rects: Some(rects),
+      ..frame.clone()
+    };
+    self.frame = Some(frame);
+    Ok(decoded)
+  }
+}
+
+/// The RGBA rows of `tile`, without stride padding.
+fn pixels(frame: &FrameDataInternal, tile: RegionInternal) -> Vec<u8> {
+  let bytes = tile.width as usize * 4;
+  let mut data = Vec::with_capacity(bytes * tile.height as usize);
+  for y in tile.y..tile.y + tile.height {
+    let start = y as usize * frame.stride as usize + tile.x as usize * 4;
+    data.extend_from_slice(&frame.data[start..start + bytes]);
+  }
+  data
+}
+
+fn decompress(compression: u8, data: &[u8], tile: RegionInternal) -> Result<Vec<u8>> {
+  let size = tile.width as usize * tile.height as usize * 4;
+  let pixels = match compression {
+    0 => data.to_vec(),
+    1 => {
+      let mut pixels = Vec::with_capacity(size);
+      ZlibDecoder::new(data)
+        .take(size as u64 + 1)
+        .read_to_end(&mut pixels)
+        .map_err(|e| failed("decompress", e))?;
+      pixels
+    }
+    2 => image::load_from_memory_with_format(data, image::ImageFormat::Jpeg)
+      .map_err(|e| failed("decompress", e))?
+      .into_rgba8()
+      .into_raw(),
+    _ => {
+      return Err(invalid(&format!(
+        "Unknown tile compression {}",
+        compression
+      )))
+    }
+  };
+  if pixels.len() != size {
+    return Err(invalid(&format!(
+      "Tile at ({}, {}) does not hold {}x{} pixels",
+      tile.x, tile.y, tile.width, tile.height
+    )));
+  }
+  Ok(pixels)
+}
+
+/// Reads the fields of a packet, failing once it runs out of bytes.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+    if self.0.len() < len {
+      return Err(truncated());
+    }
+    let (head, rest) = self.0.split_at(len);
+    self.0 = rest;
+    Ok(head)
+  }
+
+  fn u8(&mut self) -> Result<u8> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn u16(&mut self) -> Result<u16> {
+    Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+  }
+
+  fn u32(&mut self) -> Result<u32> {
+    Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+  }
+
+  fn u64(&mut self) -> Result<u64> {
+    Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+  }
+}
+
+fn invalid(message: &str) -> Error {
+  Error::new(Status::InvalidArg, message.to_string())
+}
+
+fn truncated() -> Error {
+  invalid("Delta packet is truncated or corrupt")
+}
+
+fn failed(action: &str, error: impl std::fmt::Display) -> Error {
+  Error::new(
+    Status::GenericFailure,
+    format!("Failed to {} tile: {}", action, error),
+  )
+}
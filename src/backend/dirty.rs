@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::output::ScaleFilter;
+use super::{FrameDataInternal, RegionInternal};
+
+/// Side of the square tiles `diff` compares.
+pub const TILE_SIZE: u32 = 32;
+
+/// Rectangles reported for one frame; more are merged into their bounding box.
+const MAX_RECTS: usize = 256;
+
+/// Fills in the `dirty_rects` of every frame of a session: the rectangles the backend
+/// reported, or the tiles that differ from the previous frame when it reported none.
+#[derive(Default)]
+pub struct DirtyTracker {
+  /// Last frame, kept to compare the next one when the backend reports no rectangles.
+  previous: Option<FrameDataInternal>,
+  /// Size of the last frame. Unset after `reset`, making the next frame dirty as a whole.
+  size: Option<(u32, u32)>,
+}
+
+impl DirtyTracker {
+  pub fn track(&mut self, frame: &mut FrameDataInternal) {
+    let size = (frame.width, frame.height);
+    let native = frame.dirty_rects.take();
+    let rects = match (&native, &self.previous) {
+      _ if self.size != Some(size) => vec![RegionInternal::full(frame.width, frame.height)],
+      (Some(rects), _) => rects.clone(),
+      (None, Some(previous)) => diff(previous, frame),
+      (None, None) => vec![RegionInternal::full(frame.width, frame.height)],
+    };
+    self.size = Some(size);
+    self.previous = native.is_none().then(|| frame.clone());
+    frame.dirty_rects = Some(limit(rects));
+  }
+
+  /// Forgets the previous frame, at the start of a session.
+  pub fn reset(&mut self) {
+    *self = Self::default();
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChangeOptions {
+  /// Let an unchanged frame through when no frame was delivered for this long.
+  pub keepalive: Option<Duration>,
+}
+
+/// Holds back frames identical to the last frame let through for the same display, so the
+/// displays of a multi-display session are compared with themselves.
+pub struct ChangeFilter {
+  options: ChangeOptions,
+  /// Last frame let through per display, before scaling, and when it was let through.
+  last: HashMap<Option<u32>, (FrameDataInternal, Duration)>,
+}
+
+impl ChangeFilter {
+  pub fn new(options: ChangeOptions) -> Self {
+    Self {
+      options,
+      last: HashMap::new(),
+    }
+  }
+
+  /// Whether `frame` differs from the last frame let through, or the keepalive is due.
+  /// Empty `dirty_rects` from the backend count as unchanged without comparing pixels. A pointer
+  /// reported with the frame that moved or changed shape counts as a change.
+  pub fn accept(&mut self, frame: &FrameDataInternal) -> bool {
+    if let Some((last, sent)) = self.last.get(&frame.display_id) {
+      let unchanged = !pointer_changed(last, frame)
+        && (frame.dirty_rects.as_ref().is_some_and(Vec::is_empty) || same(last, frame));
+      let keepalive_due = self
+        .options
+        .keepalive
+        .is_some_and(|keepalive| frame.timestamp >= *sent + keepalive);
+      if unchanged && !keepalive_due {
+        return false;
+      }
+    }
+    self
+      .last
+      .insert(frame.display_id, (frame.clone(), frame.timestamp));
+    true
+  }
+
+  /// The last frame let through, stamped `now`, when the keepalive is due. With several
+  /// displays, the one waiting longest.
+  pub fn keepalive(&mut self, now: Duration) -> Option<FrameDataInternal> {
+    let keepalive = self.options.keepalive?;
+    let (last, sent) = self.last.values_mut().min_by_key(|(_, sent)| *sent)?;
+    if now < *sent + keepalive {
+      return None;
+    }
+    *sent = now;
+    Some(FrameDataInternal {
+      timestamp: now,
+      dirty_rects: Some(Vec::new()),
+      ..last.clone()
+    })
+  }
+
+  /// Lets the next frame through even if it is unchanged.
+  pub fn reset(&mut self) {
+    self.last.clear();
+  }
+}
+
+/// Whether two frames hold the same pixels.
+fn same(a: &FrameDataInternal, b: &FrameDataInternal) -> bool {
+  (a.width, a.height, a.stride, a.format, a.encoding)
+    == (b.width, b.height, b.stride, b.format, b.encoding)
+    && a.data == b.data
+}
+
+/// Whether the pointers reported with two frames look different.
+fn pointer_changed(a: &FrameDataInternal, b: &FrameDataInternal) -> bool {
+  match (&a.cursor, &b.cursor) {
+    (Some(a), Some(b)) => a.differs(b),
+    (a, b) => a.is_some() != b.is_some(),
+  }
+}
+
+/// Tiles of `frame` that differ from `previous`, merged into rectangles.
+/// Both frames must be packed 4-byte frames of the same size.
+pub fn diff(previous: &FrameDataInternal, frame: &FrameDataInternal) -> Vec<RegionInternal> {
+  let (width, height) = (frame.width, frame.height);
+  if (previous.width, previous.height) != (width, height) || previous.format != frame.format {
+    return vec![RegionInternal::full(width, height)];
+  }
+  fn row(frame: &FrameDataInternal, y: u32) -> &[u8] {
+    let start = y as usize * frame.stride as usize;
+    &frame.data[start..start + frame.width as usize * 4]
+  }
+  let tile_bytes = TILE_SIZE as usize * 4;
+
+  let mut rects: Vec<RegionInternal> = Vec::new();
+  // Rectangles reaching down to the current band of tiles, which it may extend.
+  let mut above: Vec<usize> = Vec::new();
+  let mut changed = vec![false; width.div_ceil(TILE_SIZE) as usize];
+  for top in (0..height).step_by(TILE_SIZE as usize) {
+    let bottom = (top + TILE_SIZE).min(height);
+    changed.fill(false);
+    for y in top..bottom {
+      let (a, b) = (row(previous, y), row(frame, y));
+      for (column, changed) in changed.iter_mut().enumerate() {
+        let start = column * tile_bytes;
+        let end = (start + tile_bytes).min(a.len());
+        *changed = *changed || a[start..end] != b[start..end];
+      }
+    }
+
+    let mut band = Vec::new();
+    let mut column = 0;
+    while column < changed.len() {
+      if !changed[column] {
+        column += 1;
+        continue;
+      }
+      let first = column;
+      while column < changed.len() && changed[column] {
+        column += 1;
+      }
+      let x = first as u32 * TILE_SIZE;
+      let rect_width = (column as u32 * TILE_SIZE).min(width) - x;
+      let index = match above
+        .iter()
+        .find(|&&i| rects[i].x == x && rects[i].width == rect_width)
+      {
+        Some(&i) => {
+          rects[i].height = bottom - rects[i].y;
+          i
+        }
+        None => {
+          rects.push(RegionInternal {
+            x,
+            y: top,
+            width: rect_width,
+            height: bottom - top,
+          });
+          rects.len() - 1
+        }
+      };
+      band.push(index);
+    }
+    above = band;
+  }
+  rects
+}
+
+/// Adds the dirty rectangles of a dropped frame to the next frame, so consumers that skip
+/// a frame still learn everything that changed.
+pub fn merge(frame: &mut FrameDataInternal, dropped: Vec<RegionInternal>) {
+  let (width, height) = (frame.width, frame.height);
+  if let Some(rects) = frame.dirty_rects.take() {
+    let clipped = dropped.iter().filter_map(|rect| rect.clamp(width, height));
+    frame.dirty_rects = Some(limit(rects.into_iter().chain(clipped).collect()));
+  }
+}
+
+/// Dirty rectangles of a `from` sized frame scaled to `to`, grown by the reach of `filter`
+/// so they cover every output pixel a changed source pixel contributes to.
+pub fn scale_rects(
+  rects: &[RegionInternal],
+  from: (u32, u32),
+  to: (u32, u32),
+  filter: ScaleFilter,
+) -> Vec<RegionInternal> {
+  let margin = match filter {
+    ScaleFilter::Nearest => 0,
+    ScaleFilter::Bilinear => 1,
+    ScaleFilter::Lanczos => 3,
+  };
+  let scale = |start: u32, end: u32, from: u32, to: u32| {
+    let start = (start as u64 * to as u64 / from.max(1) as u64) as u32;
+    let end = (end as u64 * to as u64).div_ceil(from.max(1) as u64) as u32;
+    (start.saturating_sub(margin), (end + margin).min(to))
+  };
+  rects
+    .iter()
+    .filter_map(|rect| {
+      let (x0, x1) = scale(rect.x, rect.x + rect.width, from.0, to.0);
+      let (y0, y1) = scale(rect.y, rect.y + rect.height, from.1, to.1);
+      (x0 < x1 && y0 < y1).then_some(RegionInternal {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+      })
+    })
+    .collect()
+}
+
+/// Replaces too many rectangles by their bounding box.
+fn limit(rects: Vec<RegionInternal>) -> Vec<RegionInternal> {
+  if rects.len() <= MAX_RECTS {
+    return rects;
+  }
+  let left = rects.iter().map(|r| r.x).min().unwrap_or(0);
+  let top = rects.iter().map(|r| r.y).min().unwrap_or(0);
+  let right = rects.iter().map(|r| r.x + r.width).max().unwrap_or(0);
+  let bottom = rects.iter().map(|r| r.y + r.height).max().unwrap_or(0);
+  vec![RegionInternal {
+    x: left,
+    y: top,
+    width: right - left,
+    height: bottom - top,
+  }]
+}
@@ -7,30 +7,46 @@ use std::thread;
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
-use napi::threadsafe_function::ThreadsafeFunctionCallMode;
-use napi::Status;
 use windows::core::Interface;
-use windows::Win32::Foundation::{HANDLE, HWND};
+use windows::Win32::Foundation::{HANDLE, HWND, POINT, RECT};
 use windows::Win32::Graphics::Direct3D::{D3D_DRIVER_TYPE_UNKNOWN, D3D_FEATURE_LEVEL_11_0};
 use windows::Win32::Graphics::Direct3D11::{
-  D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, D3D11_CREATE_DEVICE_FLAG,
-  D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ, D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC,
-  D3D11_USAGE_STAGING,
+  D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, D3D11_BOX,
+  D3D11_CREATE_DEVICE_FLAG, D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ, D3D11_SDK_VERSION,
+  D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING,
+};
+use windows::Win32::Graphics::Dxgi::Common::{
+  DXGI_MODE_ROTATION_ROTATE180, DXGI_MODE_ROTATION_ROTATE270, DXGI_MODE_ROTATION_ROTATE90,
 };
 use windows::Win32::Graphics::Dxgi::{
-  CreateDXGIFactory1, IDXGIAdapter1, IDXGIFactory1, IDXGIOutput1, IDXGIOutputDuplication,
-  IDXGIResource, DXGI_ERROR_ACCESS_LOST, DXGI_ERROR_WAIT_TIMEOUT, DXGI_OUTDUPL_FRAME_INFO,
+  CreateDXGIFactory1, IDXGIAdapter1, IDXGIFactory1, IDXGIOutput, IDXGIOutput1,
+  IDXGIOutputDuplication, IDXGIResource, DXGI_ERROR_ACCESS_LOST, DXGI_ERROR_WAIT_TIMEOUT,
+  DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTDUPL_MOVE_RECT, DXGI_OUTDUPL_POINTER_SHAPE_INFO,
+  DXGI_OUTDUPL_POINTER_SHAPE_TYPE_COLOR, DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MASKED_COLOR,
+  DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MONOCHROME, DXGI_OUTPUT_DESC,
 };
 use windows::Win32::Graphics::Gdi::{
-  BitBlt, CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, GetDC, ReleaseDC,
-  SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, CAPTUREBLT, DIB_RGB_COLORS, HBITMAP, HDC,
-  HGDIOBJ, ROP_CODE, SRCCOPY,
+  BitBlt, CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, GetDC, GetMonitorInfoW,
+  MonitorFromPoint, ReleaseDC, SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, CAPTUREBLT,
+  DIB_RGB_COLORS, HBITMAP, HDC, HGDIOBJ, HMONITOR, MONITORINFO, MONITOR_DEFAULTTOPRIMARY, ROP_CODE,
+  SRCCOPY,
 };
+use windows::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+use windows::Win32::UI::HiDpi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI};
 use windows::Win32::UI::WindowsAndMessaging::{GetSystemMetrics, SM_CXSCREEN, SM_CYSCREEN};
 
-use super::{CaptureBackendImpl, FrameDataInternal, FrameTsfnType};
+use super::cursor::{CursorInternal, CursorShape};
+use super::events::ErrorCode;
+use super::pixel::PixelFormat;
+use super::topology::{Change, DisplayWatch};
+use super::{
+  host_time, host_time_before, CaptureBackendImpl, CaptureOptions, DisplayInfoInternal,
+  FrameDataInternal, FrameSink, RegionInternal, SharedRegion, WindowInfoInternal,
+};
+use crate::error::{self, Error, Status};
 
 pub struct DxgiBackend {
+  options: CaptureOptions,
   running: Arc<AtomicBool>,
   handle: Option<thread::JoinHandle<()>>,
 }
@@ -42,10 +58,23 @@ struct DxgiState {
   device: ID3D11Device,
   context: ID3D11DeviceContext,
   duplication: IDXGIOutputDuplication,
+  display_id: u32,
   fastlane: bool,
   width: u32,
   height: u32,
   staging_texture: Option<ID3D11Texture2D>,
+  /// Part of the desktop copied into the previous frame.
+  last_rect: Option<RegionInternal>,
+  hide_cursor: bool,
+  /// Top-left corner of the pointer image on the output, once DXGI reported it. DXGI only
+  /// reports pointer changes, so the last known state is kept.
+  pointer_position: Option<POINT>,
+  pointer_visible: bool,
+  pointer_shape: Option<Arc<CursorShape>>,
+  /// Id given to the last pointer shape.
+  pointer_shape_id: u64,
+  /// Part of the previous frame the drawn pointer covered.
+  pointer_rect: Option<RegionInternal>,
 }
 
 enum DxgiCaptureError {
@@ -59,6 +88,9 @@ struct GdiState {
   dib: HBITMAP,
   old_obj: HGDIOBJ,
   bits: *mut c_void,
+  display_id: u32,
+  left: i32,
+  top: i32,
   width: i32,
   height: i32,
 }
@@ -83,9 +115,30 @@ impl Drop for GdiState {
 }
 
 impl GdiState {
-  unsafe fn new() -> Result<Self> {
-    let width = GetSystemMetrics(SM_CXSCREEN);
-    let height = GetSystemMetrics(SM_CYSCREEN);
+  unsafe fn new(display_id: Option<u32>) -> Result<Self> {
+    let monitor = match display_id {
+      Some(id) => hmonitor_from_id(id),
+      None => MonitorFromPoint(POINT { x: 0, y: 0 }, MONITOR_DEFAULTTOPRIMARY),
+    };
+    let (left, top, width, height) = match display_id {
+      Some(id) => {
+        let mut info = MONITORINFO {
+          cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+          ..Default::default()
+        };
+        if !GetMonitorInfoW(monitor, &mut info).as_bool() {
+          return Err(anyhow!("Display {} not found", id));
+        }
+        let rc = info.rcMonitor;
+        (rc.left, rc.top, rc.right - rc.left, rc.bottom - rc.top)
+      }
+      None => (
+        0,
+        0,
+        GetSystemMetrics(SM_CXSCREEN),
+        GetSystemMetrics(SM_CYSCREEN),
+      ),
+    };
     if width <= 0 || height <= 0 {
       return Err(anyhow!("Invalid screen size"));
     }
@@ -145,37 +198,47 @@ impl GdiState {
       dib,
       old_obj,
       bits,
+      display_id: monitor_id(monitor),
+      left,
+      top,
       width,
       height,
     })
   }
 
-  unsafe fn capture_frame(&mut self) -> Result<FrameDataInternal> {
+  unsafe fn capture_frame(&mut self, region: &SharedRegion) -> Result<FrameDataInternal> {
+    let rect = region.rect_within(self.width as u32, self.height as u32);
+    let timestamp = host_time();
     let rop = ROP_CODE(SRCCOPY.0 | CAPTUREBLT.0);
     BitBlt(
       self.mem_dc,
       0,
       0,
-      self.width,
-      self.height,
+      rect.width as i32,
+      rect.height as i32,
       self.screen_dc,
-      0,
-      0,
+      self.left + rect.x as i32,
+      self.top + rect.y as i32,
       rop,
     )?;
 
-    let data = bgra_to_rgba_compact_opaque(
+    let data = compact_bgra_opaque(
       self.bits as *const u8,
       (self.width as usize) * 4,
-      self.width as u32,
-      self.height as u32,
+      rect.width,
+      rect.height,
     );
 
     Ok(FrameDataInternal {
-      width: self.width as u32,
-      height: self.height as u32,
-      stride: (self.width as u32) * 4,
+      width: rect.width,
+      height: rect.height,
+      stride: rect.width * 4,
+      format: PixelFormat::Bgra,
+      encoding: None,
       data,
+      timestamp,
+      display_id: Some(self.display_id),
+      ..Default::default()
     })
   }
 }
@@ -185,10 +248,19 @@ enum CaptureMode {
   Gdi(GdiState),
 }
 
-unsafe fn init_capture_mode() -> Result<CaptureMode> {
-  match DxgiState::new() {
+impl CaptureMode {
+  fn display_id(&self) -> u32 {
+    match self {
+      Self::Dxgi(state) => state.display_id,
+      Self::Gdi(state) => state.display_id,
+    }
+  }
+}
+
+unsafe fn init_capture_mode(display_id: Option<u32>, hide_cursor: bool) -> Result<CaptureMode> {
+  match DxgiState::new(display_id, hide_cursor) {
     Ok(dxgi) => Ok(CaptureMode::Dxgi(dxgi)),
-    Err(dxgi_err) => match GdiState::new() {
+    Err(dxgi_err) => match GdiState::new(display_id) {
       Ok(gdi) => Ok(CaptureMode::Gdi(gdi)),
       Err(gdi_err) => Err(anyhow!(
         "DXGI init failed: {:?}; GDI init failed: {:?}",
@@ -200,11 +272,9 @@ unsafe fn init_capture_mode() -> Result<CaptureMode> {
 }
 
 impl DxgiState {
-  unsafe fn new() -> Result<Self> {
+  unsafe fn new(display_id: Option<u32>, hide_cursor: bool) -> Result<Self> {
     let factory: IDXGIFactory1 = CreateDXGIFactory1()?;
-    let adapter = get_adapter(&factory)?;
-    let output = get_output(&adapter)?;
-    let output1: IDXGIOutput1 = output.cast()?;
+    let (adapter, output1, display_id) = find_output(&factory, display_id)?;
 
     let mut device: Option<ID3D11Device> = None;
     let mut context: Option<ID3D11DeviceContext> = None;
@@ -234,17 +304,28 @@ impl DxgiState {
       device,
       context,
       duplication,
+      display_id,
       fastlane,
       width,
       height,
       staging_texture: None,
+      last_rect: None,
+      hide_cursor,
+      pointer_position: None,
+      pointer_visible: false,
+      pointer_shape: None,
+      pointer_shape_id: 0,
+      pointer_rect: None,
     })
   }
 
   unsafe fn capture_frame(
     &mut self,
     timeout_ms: u32,
+    region: &SharedRegion,
   ) -> std::result::Result<Option<FrameDataInternal>, DxgiCaptureError> {
+    let rect = region.rect_within(self.width, self.height);
+
     let mut frame_info = DXGI_OUTDUPL_FRAME_INFO::default();
     let mut resource: Option<IDXGIResource> = None;
 
@@ -278,6 +359,8 @@ impl DxgiState {
     }
 
     let _guard = ReleaseGuard(self.duplication.clone());
+    let timestamp = qpc_to_host_time(frame_info.LastPresentTime);
+    self.update_pointer(&frame_info);
 
     if self.fastlane {
       struct SurfaceUnmapGuard(IDXGIOutputDuplication);
@@ -304,19 +387,29 @@ impl DxgiState {
         }
       };
 
-      let src_ptr = mapped.pBits as *const u8;
       let src_stride = mapped.Pitch as usize;
-      let data = bgra_to_rgba_compact(src_ptr, src_stride, self.width, self.height);
-
-      return Ok(Some(FrameDataInternal {
-        width: self.width,
-        height: self.height,
-        stride: self.width * 4,
+      let src_ptr =
+        (mapped.pBits as *const u8).add(rect.y as usize * src_stride + rect.x as usize * 4);
+      let data = compact_bgra(src_ptr, src_stride, rect.width, rect.height);
+
+      let frame = FrameDataInternal {
+        width: rect.width,
+        height: rect.height,
+        stride: rect.width * 4,
+        format: PixelFormat::Bgra,
+        encoding: None,
         data,
-      }));
+        timestamp,
+        display_id: Some(self.display_id),
+        dirty_rects: self.dirty_rects(&frame_info, rect),
+        ..Default::default()
+      };
+      return Ok(Some(self.with_pointer(frame, rect)));
     }
 
     let Some(res) = resource else {
+      // The changes of this frame are not delivered, so the next one cannot report them.
+      self.last_rect = None;
       return Ok(None);
     };
     let texture: ID3D11Texture2D = match res.cast() {
@@ -331,6 +424,10 @@ impl DxgiState {
 
     let mut desc = D3D11_TEXTURE2D_DESC::default();
     texture.GetDesc(&mut desc);
+    // Only the region is copied out of the desktop texture.
+    let rect = rect.clamp(desc.Width, desc.Height).unwrap_or(rect);
+    desc.Width = rect.width;
+    desc.Height = rect.height;
 
     let needs_new_staging = match self.staging_texture.as_ref() {
       None => true,
@@ -361,10 +458,28 @@ impl DxgiState {
     }
 
     let Some(staging) = &self.staging_texture else {
+      self.last_rect = None;
       return Ok(None);
     };
 
-    self.context.CopyResource(staging, &texture);
+    let src_box = D3D11_BOX {
+      left: rect.x,
+      top: rect.y,
+      front: 0,
+      right: rect.x + rect.width,
+      bottom: rect.y + rect.height,
+      back: 1,
+    };
+    self.context.CopySubresourceRegion(
+      staging,
+      0,
+      0,
+      0,
+      0,
+      &texture,
+      0,
+      Some(&src_box as *const _),
+    );
 
     // Wait for copy to complete? Actually CopyResource is executed by GPU but we Map immediately.
     // D3D11 Map on Staging texture should sync automatically.
@@ -390,99 +505,383 @@ impl DxgiState {
     // let sum: u64 = slice.iter().map(|&x| x as u64).sum();
     // eprintln!("Captured frame sum: {}", sum);
 
-    let data = bgra_to_rgba_compact_opaque(src_ptr, src_stride, width, height);
+    let data = compact_bgra_opaque(src_ptr, src_stride, width, height);
 
     self.context.Unmap(staging, 0);
 
-    Ok(Some(FrameDataInternal {
+    let frame = FrameDataInternal {
       width,
       height,
       stride: width * 4,
+      format: PixelFormat::Bgra,
+      encoding: None,
       data,
-    }))
+      timestamp,
+      display_id: Some(self.display_id),
+      dirty_rects: self.dirty_rects(&frame_info, rect),
+      ..Default::default()
+    };
+    Ok(Some(self.with_pointer(frame, rect)))
+  }
+
+  /// Records the pointer changes reported with the acquired frame, before it is released.
+  unsafe fn update_pointer(&mut self, info: &DXGI_OUTDUPL_FRAME_INFO) {
+    if info.LastMouseUpdateTime != 0 {
+      self.pointer_position = Some(info.PointerPosition.Position);
+      self.pointer_visible = info.PointerPosition.Visible.as_bool();
+    }
+    if info.PointerShapeBufferSize == 0 {
+      return;
+    }
+    let mut buffer = vec![0u8; info.PointerShapeBufferSize as usize];
+    let mut required = 0;
+    let mut shape_info = DXGI_OUTDUPL_POINTER_SHAPE_INFO::default();
+    let fetched = self.duplication.GetFramePointerShape(
+      buffer.len() as u32,
+      buffer.as_mut_ptr() as *mut c_void,
+      &mut required,
+      &mut shape_info,
+    );
+    if fetched.is_err() {
+      return;
+    }
+    if let Some((width, height, data)) = pointer_shape_rgba(&buffer, &shape_info) {
+      self.pointer_shape_id += 1;
+      self.pointer_shape = Some(Arc::new(CursorShape {
+        id: self.pointer_shape_id,
+        width,
+        height,
+        hotspot_x: shape_info.HotSpot.x.max(0) as u32,
+        hotspot_y: shape_info.HotSpot.y.max(0) as u32,
+        data,
+      }));
+    }
+  }
+
+  /// Adds the pointer to a frame showing `rect` of the output, and draws it unless
+  /// `hide_cursor` is set: desktop duplication leaves it out of the desktop image.
+  fn with_pointer(
+    &mut self,
+    mut frame: FrameDataInternal,
+    rect: RegionInternal,
+  ) -> FrameDataInternal {
+    let (hotspot_x, hotspot_y) = self
+      .pointer_shape
+      .as_ref()
+      .map_or((0, 0), |shape| (shape.hotspot_x, shape.hotspot_y));
+    let cursor = self.pointer_position.map(|position| {
+      CursorInternal::new(
+        position.x + hotspot_x as i32 - rect.x as i32,
+        position.y + hotspot_y as i32 - rect.y as i32,
+        self.pointer_visible,
+        self.pointer_shape.clone(),
+      )
+    });
+    if !self.hide_cursor {
+      let drawn = cursor
+        .as_ref()
+        .and_then(|cursor| cursor.bounds(frame.width, frame.height));
+      // The pointer may have moved over an unchanged desktop.
+      if let Some(rects) = &mut frame.dirty_rects {
+        rects.extend(self.pointer_rect.iter().chain(&drawn));
+      }
+      self.pointer_rect = drawn;
+      if let Some(cursor) = &cursor {
+        cursor.draw(&mut frame);
+      }
+    }
+    frame.cursor = cursor;
+    frame
+  }
+
+  /// Parts of `rect` that changed since the previous frame, relative to `rect`: the dirty
+  /// rectangles of the desktop and the destinations of moved ones. `None` when DXGI reported
+  /// nothing or the region moved; `FrameSink` then compares the frames itself.
+  unsafe fn dirty_rects(
+    &mut self,
+    info: &DXGI_OUTDUPL_FRAME_INFO,
+    rect: RegionInternal,
+  ) -> Option<Vec<RegionInternal>> {
+    if self.last_rect.replace(rect) != Some(rect) {
+      return None;
+    }
+    // Only the pointer moved; the desktop image is unchanged.
+    if info.AccumulatedFrames == 0 {
+      return Some(Vec::new());
+    }
+    let capacity = info.TotalMetadataBufferSize as usize;
+    if capacity == 0 {
+      return None;
+    }
+
+    let move_size = std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>();
+    let mut moves = vec![DXGI_OUTDUPL_MOVE_RECT::default(); capacity / move_size + 1];
+    let mut required = 0;
+    self
+      .duplication
+      .GetFrameMoveRects(
+        (moves.len() * move_size) as u32,
+        moves.as_mut_ptr(),
+        &mut required,
+      )
+      .ok()?;
+    moves.truncate(required as usize / move_size);
+
+    let rect_size = std::mem::size_of::<RECT>();
+    let mut dirty = vec![RECT::default(); capacity / rect_size + 1];
+    self
+      .duplication
+      .GetFrameDirtyRects(
+        (dirty.len() * rect_size) as u32,
+        dirty.as_mut_ptr(),
+        &mut required,
+      )
+      .ok()?;
+    dirty.truncate(required as usize / rect_size);
+
+    let within = |r: &RECT| {
+      let left = (r.left as i64).max(rect.x as i64);
+      let top = (r.top as i64).max(rect.y as i64);
+      let right = (r.right as i64).min(rect.x as i64 + rect.width as i64);
+      let bottom = (r.bottom as i64).min(rect.y as i64 + rect.height as i64);
+      (left < right && top < bottom).then(|| RegionInternal {
+        x: (left - rect.x as i64) as u32,
+        y: (top - rect.y as i64) as u32,
+        width: (right - left) as u32,
+        height: (bottom - top) as u32,
+      })
+    };
+    Some(
+      dirty
+        .iter()
+        .chain(moves.iter().map(|m| &m.DestinationRect))
+        .filter_map(within)
+        .collect(),
+    )
   }
 }
 
 impl DxgiBackend {
-  pub fn new() -> Result<Self> {
+  pub fn new(options: CaptureOptions) -> Result<Self> {
     unsafe {
-      if DxgiState::new().is_err() && GdiState::new().is_err() {
+      if DxgiState::new(options.display_id, options.hide_cursor).is_err()
+        && GdiState::new(options.display_id).is_err()
+      {
         return Err(anyhow!("Neither DXGI nor GDI capture is available"));
       }
     }
 
     Ok(Self {
+      options,
       running: Arc::new(AtomicBool::new(false)),
       handle: None,
     })
   }
 }
 
-unsafe fn get_adapter(factory: &IDXGIFactory1) -> Result<IDXGIAdapter1> {
-  factory
-    .EnumAdapters1(0)
-    .map_err(|_| anyhow!("No DXGI adapter found"))
+/// Display ids are `HMONITOR` values, matching the monitor ids used by XCap.
+fn monitor_id(monitor: HMONITOR) -> u32 {
+  monitor.0 as usize as u32
 }
 
-unsafe fn get_output(adapter: &IDXGIAdapter1) -> Result<IDXGIOutput1> {
-  let output = adapter
-    .EnumOutputs(0)
-    .map_err(|_| anyhow!("No DXGI output found"))?;
-  let output1: IDXGIOutput1 = output.cast()?;
-  Ok(output1)
+fn hmonitor_from_id(id: u32) -> HMONITOR {
+  HMONITOR(id as usize as *mut c_void)
+}
+
+/// Converts a `QueryPerformanceCounter` reading to `host_time`.
+/// `LastPresentTime` is 0 when only the pointer moved; those frames are stamped now.
+unsafe fn qpc_to_host_time(ticks: i64) -> Duration {
+  let (mut now, mut frequency) = (0, 0);
+  if ticks <= 0
+    || QueryPerformanceCounter(&mut now).is_err()
+    || QueryPerformanceFrequency(&mut frequency).is_err()
+    || frequency <= 0
+  {
+    return host_time();
+  }
+  let age = now.saturating_sub(ticks).max(0) as u128;
+  host_time_before(Duration::from_nanos(
+    (age * 1_000_000_000 / frequency as u128) as u64,
+  ))
 }
 
-fn bgra_to_rgba_compact(src_ptr: *const u8, src_stride: usize, width: u32, height: u32) -> Vec<u8> {
-  let w = width as usize;
-  let h = height as usize;
-  let mut dst = vec![0u8; w * h * 4];
-
-  for y in 0..h {
-    let src_row = unsafe { src_ptr.add(y * src_stride) };
-    for x in 0..w {
-      let src_px = unsafe { src_row.add(x * 4) };
-      let dst_i = (y * w + x) * 4;
-      dst[dst_i] = unsafe { *src_px.add(2) };
-      dst[dst_i + 1] = unsafe { *src_px.add(1) };
-      dst[dst_i + 2] = unsafe { *src_px.add(0) };
-      dst[dst_i + 3] = unsafe { *src_px.add(3) };
+/// Outputs attached to the desktop, across every adapter.
+unsafe fn enum_outputs(
+  factory: &IDXGIFactory1,
+) -> Result<Vec<(IDXGIAdapter1, IDXGIOutput, DXGI_OUTPUT_DESC)>> {
+  let mut outputs = Vec::new();
+  let mut adapter_index = 0;
+  while let Ok(adapter) = factory.EnumAdapters1(adapter_index) {
+    let mut output_index = 0;
+    while let Ok(output) = adapter.EnumOutputs(output_index) {
+      let desc = output.GetDesc()?;
+      if desc.AttachedToDesktop.as_bool() {
+        outputs.push((adapter.clone(), output, desc));
+      }
+      output_index += 1;
     }
+    adapter_index += 1;
+  }
+
+  if outputs.is_empty() {
+    return Err(anyhow!("No DXGI output found"));
+  }
+  Ok(outputs)
+}
+
+/// Displays attached to the desktop, read from a new factory: the outputs of an existing one
+/// never change.
+unsafe fn list_outputs() -> Result<Vec<DisplayInfoInternal>> {
+  let factory: IDXGIFactory1 = CreateDXGIFactory1()?;
+  let outputs = enum_outputs(&factory)?;
+  Ok(
+    outputs
+      .iter()
+      .map(|(_, _, desc)| display_info(desc))
+      .collect(),
+  )
+}
+
+fn is_primary_output(desc: &DXGI_OUTPUT_DESC) -> bool {
+  desc.DesktopCoordinates.left == 0 && desc.DesktopCoordinates.top == 0
+}
+
+/// Picks the output showing `display_id`, or the primary output when no id is given.
+/// Also returns the id of the picked display.
+unsafe fn find_output(
+  factory: &IDXGIFactory1,
+  display_id: Option<u32>,
+) -> Result<(IDXGIAdapter1, IDXGIOutput1, u32)> {
+  let outputs = enum_outputs(factory)?;
+  let index = match display_id {
+    Some(id) => outputs
+      .iter()
+      .position(|(_, _, desc)| monitor_id(desc.Monitor) == id)
+      .ok_or_else(|| anyhow!("Display {} not found", id))?,
+    None => outputs
+      .iter()
+      .position(|(_, _, desc)| is_primary_output(desc))
+      .unwrap_or(0),
+  };
+
+  let (adapter, output, desc) = outputs.into_iter().nth(index).unwrap();
+  let output1: IDXGIOutput1 = output.cast()?;
+  Ok((adapter, output1, monitor_id(desc.Monitor)))
+}
+
+unsafe fn display_info(desc: &DXGI_OUTPUT_DESC) -> DisplayInfoInternal {
+  let name_len = desc
+    .DeviceName
+    .iter()
+    .position(|&c| c == 0)
+    .unwrap_or(desc.DeviceName.len());
+  let rect = desc.DesktopCoordinates;
+
+  let mut dpi_x = 0;
+  let mut dpi_y = 0;
+  let scale_factor = match GetDpiForMonitor(desc.Monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y)
+  {
+    Ok(()) if dpi_x > 0 => dpi_x as f64 / 96.0,
+    _ => 1.0,
+  };
+
+  let rotation = match desc.Rotation {
+    DXGI_MODE_ROTATION_ROTATE90 => 90.0,
+    DXGI_MODE_ROTATION_ROTATE180 => 180.0,
+    DXGI_MODE_ROTATION_ROTATE270 => 270.0,
+    _ => 0.0,
+  };
+
+  DisplayInfoInternal {
+    id: monitor_id(desc.Monitor),
+    name: String::from_utf16_lossy(&desc.DeviceName[..name_len]),
+    x: rect.left,
+    y: rect.top,
+    width: (rect.right - rect.left) as u32,
+    height: (rect.bottom - rect.top) as u32,
+    scale_factor,
+    rotation,
+    is_primary: is_primary_output(desc),
+  }
+}
+
+fn compact_bgra(src_ptr: *const u8, src_stride: usize, width: u32, height: u32) -> Vec<u8> {
+  let row = width as usize * 4;
+  let mut dst = Vec::with_capacity(row * height as usize);
+
+  for y in 0..height as usize {
+    let src_row = unsafe { std::slice::from_raw_parts(src_ptr.add(y * src_stride), row) };
+    dst.extend_from_slice(src_row);
   }
 
   dst
 }
 
-fn bgra_to_rgba_compact_opaque(
-  src_ptr: *const u8,
-  src_stride: usize,
-  width: u32,
-  height: u32,
-) -> Vec<u8> {
-  let w = width as usize;
-  let h = height as usize;
-  let mut dst = vec![0u8; w * h * 4];
-
-  for y in 0..h {
-    let src_row = unsafe { src_ptr.add(y * src_stride) };
-    for x in 0..w {
-      let src_px = unsafe { src_row.add(x * 4) };
-      let dst_i = (y * w + x) * 4;
-      dst[dst_i] = unsafe { *src_px.add(2) };
-      dst[dst_i + 1] = unsafe { *src_px.add(1) };
-      dst[dst_i + 2] = unsafe { *src_px.add(0) };
-      dst[dst_i + 3] = 255;
+/// Converts a pointer shape from `GetFramePointerShape` to straight RGBA and returns it with its
+/// size. Pixels that invert the screen behind them cannot be expressed and become black.
+fn pointer_shape_rgba(
+  buffer: &[u8],
+  info: &DXGI_OUTDUPL_POINTER_SHAPE_INFO,
+) -> Option<(u32, u32, Vec<u8>)> {
+  let (width, pitch) = (info.Width as usize, info.Pitch as usize);
+  let shape_type = info.Type as i32;
+  if shape_type == DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MONOCHROME.0 {
+    // An AND mask followed by an XOR mask, one bit per pixel.
+    let height = info.Height as usize / 2;
+    let bit = |row: usize, x: usize| {
+      let byte = *buffer.get(row * pitch + x / 8)?;
+      Some(byte & (0x80 >> (x % 8)) != 0)
+    };
+    let mut data = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+      for x in 0..width {
+        data.extend_from_slice(match (bit(y, x)?, bit(y + height, x)?) {
+          (true, false) => &[0, 0, 0, 0],
+          (false, true) => &[255, 255, 255, 255],
+          _ => &[0, 0, 0, 255],
+        });
+      }
+    }
+    return Some((width as u32, height as u32, data));
+  }
+
+  let masked = shape_type == DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MASKED_COLOR.0;
+  if !masked && shape_type != DXGI_OUTDUPL_POINTER_SHAPE_TYPE_COLOR.0 {
+    return None;
+  }
+  let height = info.Height as usize;
+  let mut data = Vec::with_capacity(width * height * 4);
+  for y in 0..height {
+    let row = buffer.get(y * pitch..y * pitch + width * 4)?;
+    for px in row.chunks_exact(4) {
+      let (b, g, r, a) = (px[0], px[1], px[2], px[3]);
+      // Masked color pixels replace the screen when the mask (alpha) byte is 0, and are XORed
+      // with it otherwise.
+      data.extend_from_slice(&match (masked, a) {
+        (false, _) => [r, g, b, a],
+        (true, 0) => [r, g, b, 255],
+        (true, _) if (r, g, b) == (0, 0, 0) => [0, 0, 0, 0],
+        (true, _) => [0, 0, 0, 255],
+      });
     }
   }
+  Some((width as u32, height as u32, data))
+}
 
+/// Like `compact_bgra`, for sources whose alpha byte is undefined.
+fn compact_bgra_opaque(src_ptr: *const u8, src_stride: usize, width: u32, height: u32) -> Vec<u8> {
+  let mut dst = compact_bgra(src_ptr, src_stride, width, height);
+  for px in dst.chunks_exact_mut(4) {
+    px[3] = 255;
+  }
   dst
 }
 
 impl CaptureBackendImpl for DxgiBackend {
   fn start<'a>(
     &'a mut self,
-    tsfn: Option<FrameTsfnType>,
-    fps: u32,
-  ) -> Pin<Box<dyn Future<Output = napi::Result<()>> + Send + 'a>> {
+    sink: Option<FrameSink>,
+  ) -> Pin<Box<dyn Future<Output = error::Result<()>> + Send + 'a>> {
     Box::pin(async move {
       if self.running.load(Ordering::SeqCst) {
         return Ok(());
@@ -490,11 +889,15 @@ impl CaptureBackendImpl for DxgiBackend {
 
       self.running.store(true, Ordering::SeqCst);
       let running = self.running.clone();
+      let options = self.options.clone();
 
       let handle = thread::spawn(move || {
-        let result = unsafe { run_capture_loop(running.clone(), tsfn, fps) };
+        let result = unsafe { run_capture_loop(running.clone(), sink, &options) };
         if let Err(e) = result {
-          eprintln!("DXGI Capture Loop Error: {:?}", e);
+          options.events.fail(
+            ErrorCode::CaptureFailed,
+            format!("DXGI capture failed: {:?}", e),
+          );
           running.store(false, Ordering::SeqCst);
         }
       });
@@ -504,7 +907,7 @@ impl CaptureBackendImpl for DxgiBackend {
     })
   }
 
-  fn stop(&mut self) -> napi::Result<()> {
+  fn stop(&mut self) -> error::Result<()> {
     self.running.store(false, Ordering::SeqCst);
     if let Some(handle) = self.handle.take() {
       let _ = handle.join();
@@ -514,15 +917,17 @@ impl CaptureBackendImpl for DxgiBackend {
 
   fn screenshot<'a>(
     &'a mut self,
-  ) -> Pin<Box<dyn Future<Output = napi::Result<FrameDataInternal>> + Send + 'a>> {
+  ) -> Pin<Box<dyn Future<Output = error::Result<FrameDataInternal>> + Send + 'a>> {
     Box::pin(async move {
-      let mut mode = unsafe { init_capture_mode() }
-        .map_err(|e| napi::Error::new(Status::GenericFailure, format!("Init failed: {:?}", e)))?;
+      let display_id = self.options.display_id;
+      let region = &self.options.region;
+      let mut mode = unsafe { init_capture_mode(display_id, self.options.hide_cursor) }
+        .map_err(|e| Error::new(Status::GenericFailure, format!("Init failed: {:?}", e)))?;
 
       // Try a few times in case of timeout
       for _ in 0..10 {
         match &mut mode {
-          CaptureMode::Dxgi(state) => match unsafe { state.capture_frame(100) } {
+          CaptureMode::Dxgi(state) => match unsafe { state.capture_frame(100, region) } {
             Ok(Some(frame)) => {
               // Check if frame is empty (all zeros), which can happen on first capture
               let sum: u64 = frame.data.iter().step_by(100).map(|&x| x as u64).sum();
@@ -536,10 +941,10 @@ impl CaptureBackendImpl for DxgiBackend {
             Ok(None) => continue, // Timeout, retry
             Err(DxgiCaptureError::AccessLost(_)) | Err(DxgiCaptureError::Other(_)) => {
               // Fallback to GDI
-              match unsafe { GdiState::new() } {
+              match unsafe { GdiState::new(display_id) } {
                 Ok(gdi) => mode = CaptureMode::Gdi(gdi),
                 Err(e) => {
-                  return Err(napi::Error::new(
+                  return Err(Error::new(
                     Status::GenericFailure,
                     format!("GDI Fallback failed: {:?}", e),
                   ))
@@ -548,8 +953,8 @@ impl CaptureBackendImpl for DxgiBackend {
             }
           },
           CaptureMode::Gdi(gdi) => {
-            let frame = unsafe { gdi.capture_frame() }.map_err(|e| {
-              napi::Error::new(
+            let frame = unsafe { gdi.capture_frame(region) }.map_err(|e| {
+              Error::new(
                 Status::GenericFailure,
                 format!("GDI capture failed: {:?}", e),
               )
@@ -559,55 +964,154 @@ impl CaptureBackendImpl for DxgiBackend {
         }
       }
 
-      Err(napi::Error::new(
+      Err(Error::new(
         Status::GenericFailure,
         "Screenshot timed out".to_string(),
       ))
     })
   }
+
+  fn list_displays<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = error::Result<Vec<DisplayInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move {
+      unsafe { list_outputs() }.map_err(|e| {
+        Error::new(
+          Status::GenericFailure,
+          format!("Failed to list displays: {:?}", e),
+        )
+      })
+    })
+  }
+
+  fn list_windows<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = error::Result<Vec<WindowInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move { super::xcap::list_windows() })
+  }
 }
 
 unsafe fn run_capture_loop(
   running: Arc<AtomicBool>,
-  tsfn: Option<FrameTsfnType>,
-  fps: u32,
+  sink: Option<FrameSink>,
+  options: &CaptureOptions,
 ) -> Result<()> {
-  let mut mode = init_capture_mode()?;
-  let target_interval = Duration::from_secs_f64(1.0 / fps as f64);
+  let (region, events) = (&options.region, &options.events);
+  let hide_cursor = options.hide_cursor;
+  let mut mode = init_capture_mode(options.display_id, hide_cursor)?;
+  if let CaptureMode::Gdi(_) = mode {
+    events.warn(
+      ErrorCode::BackendFallback,
+      "DXGI desktop duplication is unavailable. Falling back to GDI.",
+    );
+  }
+  // Windows does not notify display changes without a window to receive them, and GDI keeps
+  // capturing the old rectangle, so the outputs are listed again every `POLL_INTERVAL`.
+  let mut watch = list_outputs().ok().and_then(|displays| {
+    let target = DisplayWatch::find(&displays, Some(mode.display_id()))?;
+    Some(DisplayWatch::new(options, displays, target))
+  });
+  // Set when DXGI lost access, as mode changes and unplugged displays make it do, until the
+  // duplication is recreated.
+  let mut access_lost = false;
 
   while running.load(Ordering::SeqCst) {
     let start_time = Instant::now();
+    let target_interval = options.settings.frame_interval();
+    // Duplication stays open while paused, so resuming needs no setup.
+    if options.settings.is_paused() {
+      options.settings.sleep(target_interval);
+      continue;
+    }
 
+    if let Some(watch) = watch.as_mut().filter(|w| access_lost || w.due()) {
+      let change = match list_outputs() {
+        Ok(displays) => watch.update(displays),
+        // Every output is gone for now; GDI keeps capturing until one is back.
+        Err(_) => Change::None,
+      };
+      match change {
+        Change::None => {}
+        Change::Reinit(display) => {
+          mode = init_capture_mode(Some(display.id), hide_cursor)?;
+          access_lost = false;
+        }
+        Change::Lost => {
+          let id = watch.target().id;
+          events.fail(
+            ErrorCode::DisplayLost,
+            format!("Display {} was disconnected", id),
+          );
+          running.store(false, Ordering::SeqCst);
+          return Ok(());
+        }
+      }
+    }
+
+    let display_id = Some(mode.display_id());
     match &mut mode {
-      CaptureMode::Dxgi(state) => match state.capture_frame(100) {
+      CaptureMode::Dxgi(state) => match state.capture_frame(100, region) {
         Ok(Some(frame)) => {
-          if let Some(tsfn) = &tsfn {
-            let status = tsfn.call(frame, ThreadsafeFunctionCallMode::NonBlocking);
+          if let Some(sink) = &sink {
+            let status = sink.send(frame);
             if status != Status::Ok {
               running.store(false, Ordering::SeqCst);
             }
           }
         }
-        Ok(None) => {}
-        Err(DxgiCaptureError::AccessLost(e)) => {
-          eprintln!("DXGI access lost: {:?}", e);
-          match DxgiState::new() {
-            Ok(new_state) => mode = CaptureMode::Dxgi(new_state),
-            Err(_) => match GdiState::new() {
-              Ok(gdi) => mode = CaptureMode::Gdi(gdi),
-              Err(e) => return Err(e),
-            },
+        // Nothing changed on screen before the timeout.
+        Ok(None) => {
+          if let Some(sink) = &sink {
+            if sink.idle() != Status::Ok {
+              running.store(false, Ordering::SeqCst);
+            }
           }
         }
-        Err(DxgiCaptureError::Other(e)) => match GdiState::new() {
-          Ok(gdi) => mode = CaptureMode::Gdi(gdi),
+        // Mode changes, desktop switches and unplugged displays. The displays are checked
+        // first, then the duplication is recreated.
+        Err(DxgiCaptureError::AccessLost(_)) if watch.is_some() && !access_lost => {
+          access_lost = true;
+          continue;
+        }
+        Err(DxgiCaptureError::AccessLost(e)) => match DxgiState::new(display_id, hide_cursor) {
+          Ok(new_state) => {
+            mode = CaptureMode::Dxgi(new_state);
+            access_lost = false;
+          }
+          Err(_) => match GdiState::new(display_id) {
+            Ok(gdi) => {
+              events.warn(
+                ErrorCode::BackendFallback,
+                format!("DXGI access lost ({:?}). Falling back to GDI.", e),
+              );
+              mode = CaptureMode::Gdi(gdi);
+              access_lost = false;
+            }
+            Err(gdi_err) => {
+              events.fail(
+                ErrorCode::DisplayLost,
+                format!("DXGI access lost ({:?}) and GDI failed: {:?}", e, gdi_err),
+              );
+              running.store(false, Ordering::SeqCst);
+              return Ok(());
+            }
+          },
+        },
+        Err(DxgiCaptureError::Other(e)) => match GdiState::new(display_id) {
+          Ok(gdi) => {
+            events.warn(
+              ErrorCode::BackendFallback,
+              format!("DXGI capture failed ({:?}). Falling back to GDI.", e),
+            );
+            mode = CaptureMode::Gdi(gdi);
+          }
           Err(_) => return Err(e),
         },
       },
       CaptureMode::Gdi(gdi) => {
-        let frame = gdi.capture_frame()?;
-        if let Some(tsfn) = &tsfn {
-          let status = tsfn.call(frame, ThreadsafeFunctionCallMode::NonBlocking);
+        let frame = gdi.capture_frame(region)?;
+        if let Some(sink) = &sink {
+          let status = sink.send(frame);
           if status != Status::Ok {
             running.store(false, Ordering::SeqCst);
           }
@@ -617,7 +1121,7 @@ unsafe fn run_capture_loop(
 
     let elapsed = start_time.elapsed();
     if elapsed < target_interval {
-      thread::sleep(target_interval - elapsed);
+      options.settings.sleep(target_interval - elapsed);
     }
   }
 
@@ -0,0 +1,114 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+
+use super::pixel::{self, PixelFormat, YuvOptions};
+use super::FrameDataInternal;
+use crate::error::{Error, Result, Status};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+  Jpeg,
+  Png,
+  /// Lossless VP8L; `image` has no lossy WebP encoder.
+  Webp,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EncodeOptions {
+  pub format: ImageFormat,
+  /// JPEG quality, 1 to 100. PNG and WebP are lossless and ignore it.
+  pub quality: u8,
+}
+
+impl EncodeOptions {
+  pub const DEFAULT_QUALITY: u8 = 80;
+
+  pub fn validate(&self) -> Result<()> {
+    if !(1..=100).contains(&self.quality) {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "quality must be between 1 and 100".to_string(),
+      ));
+    }
+    Ok(())
+  }
+}
+
+/// Compresses a packed frame. The result keeps the frame size, has a `stride` of 0
+/// and its `encoding` set to `options.format`.
+pub fn encode(frame: FrameDataInternal, options: EncodeOptions) -> Result<FrameDataInternal> {
+  // Captured alpha is always opaque, so RGB keeps the files smaller.
+  let frame = pixel::convert(frame, PixelFormat::Rgb, YuvOptions::default());
+  let (width, height) = (frame.width, frame.height);
+  let mut data = Vec::new();
+
+  let result = match options.format {
+    ImageFormat::Jpeg => JpegEncoder::new_with_quality(&mut data, options.quality).write_image(
+      &frame.data,
+      width,
+      height,
+      ExtendedColorType::Rgb8,
+    ),
+    // Frames are encoded while capturing, so favour speed over size.
+    ImageFormat::Png => {
+      PngEncoder::new_with_quality(&mut data, CompressionType::Fast, FilterType::Adaptive)
+        .write_image(&frame.data, width, height, ExtendedColorType::Rgb8)
+    }
+    ImageFormat::Webp => WebPEncoder::new_lossless(&mut data).write_image(
+      &frame.data,
+      width,
+      height,
+      ExtendedColorType::Rgb8,
+    ),
+  };
+  result.map_err(|e| {
+    Error::new(
+      Status::GenericFailure,
+      format!("Failed to encode frame: {}", e),
+    )
+  })?;
+
+  Ok(FrameDataInternal {
+    width,
+    height,
+    stride: 0,
+    format: PixelFormat::Rgb,
+    encoding: Some(options.format),
+    data,
+    ..frame
+  })
+}
+
+/// Decodes a frame compressed by `encode` back to `Rgba` pixels.
+pub fn decode(frame: &FrameDataInternal) -> Result<FrameDataInternal> {
+  let Some(format) = frame.encoding else {
+    return Ok(frame.clone());
+  };
+  let format = match format {
+    ImageFormat::Jpeg => image::ImageFormat::Jpeg,
+    ImageFormat::Png => image::ImageFormat::Png,
+    ImageFormat::Webp => image::ImageFormat::WebP,
+  };
+  let image = image::load_from_memory_with_format(&frame.data, format)
+    .map_err(|e| {
+      Error::new(
+        Status::GenericFailure,
+        format!("Failed to decode frame: {}", e),
+      )
+    })?
+    .into_rgba8();
+
+  Ok(FrameDataInternal {
+    width: image.width(),
+    height: image.height(),
+    stride: image.width() * 4,
+    format: PixelFormat::Rgba,
+    encoding: None,
+    data: image.into_raw(),
+    dirty_rects: frame.dirty_rects.clone(),
+    cursor: frame.cursor.clone(),
+    ..*frame
+  })
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+use std::sync::{Arc, Mutex as StdMutex};
+
+use super::DisplayInfoInternal;
+
+/// Stable identifiers of capture errors, shared by the Rust and JavaScript APIs.
+/// Codes are never renamed or reused; new ones may be added.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+  /// Screen recording was refused by the user or the OS.
+  PermissionDenied,
+  /// The captured display went away or can no longer be read.
+  DisplayLost,
+  /// The captured window was closed.
+  WindowClosed,
+  /// The preferred backend is unavailable and another one is used instead.
+  BackendFallback,
+  /// Reading a frame from the backend failed.
+  CaptureFailed,
+  /// Scaling, converting or encoding a frame failed.
+  ProcessingFailed,
+  /// The frame callback can no longer be called, e.g. because JavaScript is shutting down.
+  StoppedByConsumer,
+  /// Writing a recording failed; the recording ended but capture goes on.
+  RecordingFailed,
+}
+
+#[derive(Clone, Debug)]
+pub struct CaptureErrorInternal {
+  pub code: ErrorCode,
+  pub message: String,
+  /// The session ended because of this error.
+  pub fatal: bool,
+}
+
+impl fmt::Display for CaptureErrorInternal {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}: {}", self.code, self.message)
+  }
+}
+
+impl std::error::Error for CaptureErrorInternal {}
+
+/// Lifecycle of a capture session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaptureState {
+  /// `start` was called and the backend is being set up.
+  Starting,
+  Running,
+  /// `pause` was called: the stream stays open but delivers no frames until `resume`.
+  Paused,
+  /// Not capturing: never started, stopped by the caller or ended by a fatal error.
+  #[default]
+  Stopped,
+}
+
+/// What changed in the display setup of a running session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayChangeKind {
+  /// Displays were connected, disconnected, moved or resized, other than the captured one.
+  DisplaysChanged,
+  /// The size of the captured display changed; frames have the new size from now on.
+  ResolutionChanged,
+}
+
+#[derive(Clone, Debug)]
+pub struct DisplayChangeInternal {
+  pub kind: DisplayChangeKind,
+  /// Captured display after the change.
+  pub display_id: u32,
+  pub width: u32,
+  pub height: u32,
+  /// Every display after the change.
+  pub displays: Vec<DisplayInfoInternal>,
+}
+
+type ErrorListener = Box<dyn Fn(&CaptureErrorInternal) + Send + Sync>;
+type StateListener = Box<dyn Fn(CaptureState) + Send + Sync>;
+type DisplayListener = Box<dyn Fn(&DisplayChangeInternal) + Send + Sync>;
+
+/// Errors reported while nobody listens are kept for the first error listener, up to this many.
+const MAX_PENDING_ERRORS: usize = 16;
+
+#[derive(Default)]
+struct Listeners {
+  state: CaptureState,
+  errors: Vec<ErrorListener>,
+  states: Vec<StateListener>,
+  displays: Vec<DisplayListener>,
+  pending: Vec<CaptureErrorInternal>,
+  /// Displays of the last display change reported in this session.
+  reported: Option<Vec<DisplayInfoInternal>>,
+}
+
+/// Error and state change channel shared between `ScreenCapture` and the backend,
+/// so failures on capture threads reach the caller instead of ending the session silently.
+///
+/// Listeners run on the thread reporting the event, with the channel locked: they must
+/// return quickly and must not report events themselves.
+#[derive(Clone, Default)]
+pub struct EventSink(Arc<StdMutex<Listeners>>);
+
+impl fmt::Debug for EventSink {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("EventSink").field(&self.state()).finish()
+  }
+}
+
+impl EventSink {
+  /// Adds an error listener. The first one also receives the errors reported before it,
+  /// such as a backend fallback while the capturer was created.
+  pub fn on_error(&self, listener: impl Fn(&CaptureErrorInternal) + Send + Sync + 'static) {
+    let mut listeners = self.0.lock().unwrap();
+    for error in listeners.pending.drain(..) {
+      listener(&error);
+    }
+    listeners.errors.push(Box::new(listener));
+  }
+
+  pub fn on_state_change(&self, listener: impl Fn(CaptureState) + Send + Sync + 'static) {
+    self.0.lock().unwrap().states.push(Box::new(listener));
+  }
+
+  pub fn on_display_change(
+    &self,
+    listener: impl Fn(&DisplayChangeInternal) + Send + Sync + 'static,
+  ) {
+    self.0.lock().unwrap().displays.push(Box::new(listener));
+  }
+
+  pub fn state(&self) -> CaptureState {
+    self.0.lock().unwrap().state
+  }
+
+  /// Moves to `state`, notifying the listeners if it changed.
+  pub fn set_state(&self, state: CaptureState) {
+    let mut listeners = self.0.lock().unwrap();
+    Self::transition(&mut listeners, state);
+  }
+
+  /// Moves from `Starting` to `Running`, unless the session already failed.
+  pub fn started(&self) {
+    self.transition_from(CaptureState::Starting, CaptureState::Running);
+  }
+
+  /// Moves from `from` to `to`. Returns false, without notifying, when the state is not `from`.
+  pub fn transition_from(&self, from: CaptureState, to: CaptureState) -> bool {
+    let mut listeners = self.0.lock().unwrap();
+    if listeners.state != from {
+      return false;
+    }
+    Self::transition(&mut listeners, to);
+    true
+  }
+
+  /// Reports a problem the session recovered from.
+  pub fn warn(&self, code: ErrorCode, message: impl Into<String>) {
+    let mut listeners = self.0.lock().unwrap();
+    Self::emit(&mut listeners, code, message.into(), false);
+  }
+
+  /// Reports a change of the display setup the session adapted to. The backends of a
+  /// multi-display session all notice the same change; `DisplaysChanged` is only reported when
+  /// the displays differ from the last change reported.
+  pub fn display_changed(&self, change: DisplayChangeInternal) {
+    let mut listeners = self.0.lock().unwrap();
+    let seen = listeners.reported.as_ref() == Some(&change.displays);
+    if seen && change.kind == DisplayChangeKind::DisplaysChanged {
+      return;
+    }
+    listeners.reported = Some(change.displays.clone());
+    for listener in &listeners.displays {
+      listener(&change);
+    }
+  }
+
+  /// Reports the error that ended the session and moves to `Stopped`.
+  pub fn fail(&self, code: ErrorCode, message: impl Into<String>) {
+    let mut listeners = self.0.lock().unwrap();
+    Self::emit(&mut listeners, code, message.into(), true);
+    Self::transition(&mut listeners, CaptureState::Stopped);
+  }
+
+  fn emit(listeners: &mut Listeners, code: ErrorCode, message: String, fatal: bool) {
+    let error = CaptureErrorInternal {
+      code,
+      message,
+      fatal,
+    };
+    if listeners.errors.is_empty() {
+      if listeners.pending.len() < MAX_PENDING_ERRORS {
+        listeners.pending.push(error);
+      }
+      return;
+    }
+    for listener in &listeners.errors {
+      listener(&error);
+    }
+  }
+
+  fn transition(listeners: &mut Listeners, state: CaptureState) {
+    if listeners.state == state {
+      return;
+    }
+    listeners.state = state;
+    if state == CaptureState::Starting {
+      listeners.reported = None;
+    }
+    for listener in &listeners.states {
+      listener(state);
+    }
+  }
+}
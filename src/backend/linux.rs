@@ -0,0 +1,78 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use super::events::ErrorCode;
+use super::x11::{is_x11_session, X11Backend};
+use super::xcap::XCapBackend;
+use super::{
+  CaptureBackendImpl, CaptureOptions, DisplayInfoInternal, FrameDataInternal, FrameSink,
+  WindowInfoInternal,
+};
+use crate::error::Result;
+
+pub struct LinuxBackend {
+  inner: Box<dyn CaptureBackendImpl>,
+}
+
+impl LinuxBackend {
+  pub fn new(options: CaptureOptions) -> Self {
+    // Window targets are captured through XCap's `Window`.
+    if options.window_id.is_some() || !is_x11_session() {
+      return Self {
+        inner: Box::new(XCapBackend::new(options)),
+      };
+    }
+
+    match X11Backend::new(options.clone()) {
+      Ok(x11) => Self {
+        inner: Box::new(x11),
+      },
+      Err(e) => {
+        options.events.warn(
+          ErrorCode::BackendFallback,
+          format!("X11 capture init failed: {:#}. Falling back to XCap.", e),
+        );
+        Self {
+          inner: Box::new(XCapBackend::new(options)),
+        }
+      }
+    }
+  }
+}
+
+impl Default for LinuxBackend {
+  fn default() -> Self {
+    Self::new(CaptureOptions::default())
+  }
+}
+
+impl CaptureBackendImpl for LinuxBackend {
+  fn start<'a>(
+    &'a mut self,
+    sink: Option<FrameSink>,
+  ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+    self.inner.start(sink)
+  }
+
+  fn stop(&mut self) -> Result<()> {
+    self.inner.stop()
+  }
+
+  fn screenshot<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<FrameDataInternal>> + Send + 'a>> {
+    self.inner.screenshot()
+  }
+
+  fn list_displays<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<DisplayInfoInternal>>> + Send + 'a>> {
+    self.inner.list_displays()
+  }
+
+  fn list_windows<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<WindowInfoInternal>>> + Send + 'a>> {
+    self.inner.list_windows()
+  }
+}
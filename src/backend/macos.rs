@@ -2,11 +2,13 @@ use std::ffi::c_void;
 use std::future::Future;
 use std::pin::Pin;
 use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
 
 use block2::RcBlock;
-use napi::bindgen_prelude::*;
-use napi::threadsafe_function::ThreadsafeFunctionCallMode;
+use core_graphics::display::CGDisplay;
+use objc2::runtime::ProtocolObject;
 use objc2::AnyThread;
 use objc2::{
   define_class, msg_send,
@@ -14,14 +16,27 @@ use objc2::{
   ClassType, DeclaredClass,
 };
 use objc2_core_media::{CMSampleBuffer, CMTime, CMTimeFlags};
-use objc2_foundation::{NSArray, NSError, NSObject, NSObjectProtocol};
+use objc2_foundation::{CGPoint, CGRect, CGSize, NSArray, NSError, NSObject, NSObjectProtocol};
 use objc2_screen_capture_kit::*;
 
-use super::{CaptureBackendImpl, FrameDataInternal, FrameTsfnType};
+use super::events::{ErrorCode, EventSink};
+use super::output::ScaleOptions;
+use super::pixel::PixelFormat;
+use super::topology::{Change, DisplayWatch};
+use super::{
+  display_not_found, host_time, host_time_before, window_not_found, CaptureBackendImpl,
+  CaptureOptions, DisplayInfoInternal, FrameDataInternal, FrameSink, RegionInternal, SharedRegion,
+  SharedSettings, WindowInfoInternal,
+};
+use crate::error::{Error, Result, Status};
 
 #[link(name = "CoreMedia", kind = "framework")]
 extern "C" {
   fn CMSampleBufferGetImageBuffer(sbuf: *mut c_void) -> *mut c_void;
+  fn CMSampleBufferGetPresentationTimeStamp(sbuf: *mut c_void) -> CMTime;
+  fn CMSampleBufferGetSampleAttachmentsArray(sbuf: *mut c_void, create: u8) -> *const c_void;
+  fn CMClockGetHostTimeClock() -> *mut c_void;
+  fn CMClockGetTime(clock: *mut c_void) -> CMTime;
 }
 
 #[link(name = "CoreVideo", kind = "framework")]
@@ -34,11 +49,78 @@ extern "C" {
   fn CVPixelBufferUnlockBaseAddress(pbuf: *mut c_void, flags: u64) -> i32;
 }
 
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+  fn CFArrayGetCount(array: *const c_void) -> isize;
+  fn CFArrayGetValueAtIndex(array: *const c_void, index: isize) -> *const c_void;
+  fn CFDictionaryGetValue(dict: *const c_void, key: *const c_void) -> *const c_void;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+  fn CGRectMakeWithDictionaryRepresentation(dict: *const c_void, rect: *mut CGRect) -> bool;
+}
+
 #[link(name = "System", kind = "dylib")]
 extern "C" {
   fn dispatch_queue_create(label: *const i8, attr: *mut c_void) -> *mut c_void;
 }
 
+/// `host_time` at which the sample was presented. Screen samples are timed on the host clock.
+unsafe fn presentation_time(sbuf: *mut c_void) -> Duration {
+  let seconds = |time: CMTime| {
+    (time.flags.0 & 1 != 0 && time.timescale > 0).then(|| time.value as f64 / time.timescale as f64)
+  };
+  let presented = seconds(CMSampleBufferGetPresentationTimeStamp(sbuf));
+  let now = seconds(CMClockGetTime(CMClockGetHostTimeClock()));
+  match (presented, now) {
+    (Some(presented), Some(now)) => {
+      host_time_before(Duration::from_secs_f64((now - presented).max(0.0)))
+    }
+    _ => host_time(),
+  }
+}
+
+/// Dirty rectangles ScreenCaptureKit attached to the sample, in pixels of its
+/// `width` x `height` image.
+unsafe fn sample_dirty_rects(
+  sbuf: *mut c_void,
+  width: u32,
+  height: u32,
+) -> Option<Vec<RegionInternal>> {
+  let attachments = CMSampleBufferGetSampleAttachmentsArray(sbuf, 0);
+  if attachments.is_null() || CFArrayGetCount(attachments) == 0 {
+    return None;
+  }
+  let info = CFArrayGetValueAtIndex(attachments, 0);
+  let key = SCStreamFrameInfoDirtyRects as *const SCStreamFrameInfo as *const c_void;
+  let rects = CFDictionaryGetValue(info, key);
+  if rects.is_null() {
+    return None;
+  }
+
+  let mut dirty = Vec::new();
+  for i in 0..CFArrayGetCount(rects) {
+    let mut rect = CGRect::new(CGPoint::new(0.0, 0.0), CGSize::new(0.0, 0.0));
+    if !CGRectMakeWithDictionaryRepresentation(CFArrayGetValueAtIndex(rects, i), &mut rect) {
+      return None;
+    }
+    let left = rect.origin.x.floor().max(0.0) as u32;
+    let top = rect.origin.y.floor().max(0.0) as u32;
+    let right = (rect.origin.x + rect.size.width).ceil().min(width as f64) as u32;
+    let bottom = (rect.origin.y + rect.size.height).ceil().min(height as f64) as u32;
+    if left < right && top < bottom {
+      dirty.push(RegionInternal {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+      });
+    }
+  }
+  Some(dirty)
+}
+
 unsafe fn extract_frame(sample: &CMSampleBuffer) -> Option<FrameDataInternal> {
   let sbuf_ptr = sample as *const CMSampleBuffer as *mut c_void;
   let pixel_buffer = CMSampleBufferGetImageBuffer(sbuf_ptr);
@@ -53,17 +135,10 @@ unsafe fn extract_frame(sample: &CMSampleBuffer) -> Option<FrameDataInternal> {
       let base_ptr = base as *const u8;
       let mut data = Vec::with_capacity(width * height * 4);
 
-      // Compact and Swap RB (BGRA -> RGBA)
+      // Compact rows; the pixels stay BGRA
       for y in 0..height {
         let row_start = base_ptr.add(y * stride);
-        let row_slice = std::slice::from_raw_parts(row_start, width * 4);
-
-        for chunk in row_slice.chunks_exact(4) {
-          data.push(chunk[2]); // R
-          data.push(chunk[1]); // G
-          data.push(chunk[0]); // B
-          data.push(chunk[3]); // A
-        }
+        data.extend_from_slice(std::slice::from_raw_parts(row_start, width * 4));
       }
 
       CVPixelBufferUnlockBaseAddress(pixel_buffer, 1);
@@ -71,7 +146,12 @@ unsafe fn extract_frame(sample: &CMSampleBuffer) -> Option<FrameDataInternal> {
         width: width as u32,
         height: height as u32,
         stride: (width * 4) as u32,
+        format: PixelFormat::Bgra,
+        encoding: None,
         data,
+        timestamp: presentation_time(sbuf_ptr),
+        dirty_rects: sample_dirty_rects(sbuf_ptr, width as u32, height as u32),
+        ..Default::default()
       });
     }
     CVPixelBufferUnlockBaseAddress(pixel_buffer, 1);
@@ -79,14 +159,108 @@ unsafe fn extract_frame(sample: &CMSampleBuffer) -> Option<FrameDataInternal> {
   None
 }
 
+/// `SCStreamErrorUserDeclined`: the user refused screen recording.
+const SC_STREAM_ERROR_USER_DECLINED: isize = -3801;
+
+/// `PermissionDenied` for permission errors, `code` otherwise.
+fn stream_error_code(error: &NSError, code: ErrorCode) -> ErrorCode {
+  if error.code() == SC_STREAM_ERROR_USER_DECLINED {
+    ErrorCode::PermissionDenied
+  } else {
+    code
+  }
+}
+
+/// Part of the source a stream shows, its output size and frame rate.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct StreamSetup {
+  rect: RegionInternal,
+  output_size: (u32, u32),
+  fps: u32,
+}
+
+impl StreamSetup {
+  /// Setup for the current `region` of a `width` x `height` display or window, in points.
+  fn new(
+    width: usize,
+    height: usize,
+    region: &SharedRegion,
+    scale: &ScaleOptions,
+    fps: u32,
+  ) -> Self {
+    let rect = region.rect_within(width as u32, height as u32);
+    Self {
+      rect,
+      output_size: scale.output_size(rect.width, rect.height),
+      fps,
+    }
+  }
+}
+
+/// Output size ScreenCaptureKit scales to. Displays of a stitched session keep their size, as
+/// `FrameSink` scales the stitched frame.
+fn native_scale(settings: &SharedSettings, stitch: bool) -> ScaleOptions {
+  if stitch {
+    ScaleOptions::default()
+  } else {
+    settings.output().scale
+  }
+}
+
+/// Samples ScreenCaptureKit keeps in flight.
+const QUEUE_DEPTH: u32 = 5;
+
+/// Stream settings for `setup`. ScreenCaptureKit scales to the requested output size itself
+/// and draws the pointer unless `hide_cursor` is set.
+unsafe fn stream_configuration(
+  setup: &StreamSetup,
+  hide_cursor: bool,
+) -> Retained<SCStreamConfiguration> {
+  let rect = setup.rect;
+  let config = SCStreamConfiguration::new();
+  config.setSourceRect(CGRect::new(
+    CGPoint::new(rect.x as f64, rect.y as f64),
+    CGSize::new(rect.width as f64, rect.height as f64),
+  ));
+  config.setWidth(setup.output_size.0 as usize);
+  config.setHeight(setup.output_size.1 as usize);
+  config.setMinimumFrameInterval(CMTime {
+    value: 1,
+    timescale: setup.fps as i32,
+    flags: CMTimeFlags(1),
+    epoch: 0,
+  });
+  config.setQueueDepth(QUEUE_DEPTH as isize);
+  config.setPixelFormat(1111970369); // kCVPixelFormatType_32BGRA
+  config.setShowsCursor(!hide_cursor);
+  config
+}
+
 pub struct StreamDelegateIvars {
-  tsfn_ptr: usize,
+  sink_ptr: usize,
+  region: SharedRegion,
+  settings: SharedSettings,
+  /// Setup the stream is currently configured with.
+  applied: StdMutex<StreamSetup>,
+  /// Size of the captured display or window in points.
+  source_size: StdMutex<(usize, usize)>,
+  /// Displays of the session, to follow mode changes of the captured display.
+  watch: StdMutex<Option<DisplayWatch>>,
+  window_id: Option<u32>,
+  display_id: Option<u32>,
+  events: EventSink,
+  hide_cursor: bool,
+  stitch: bool,
+  /// Set once `send` failed; the session is over and later frames are ignored.
+  failed: AtomicBool,
+  /// Samples left that may still follow the previous setup, whose dirty rectangles are ignored.
+  stale_frames: AtomicU32,
 }
 
 impl Drop for StreamDelegateIvars {
   fn drop(&mut self) {
-    if self.tsfn_ptr != 0 {
-      unsafe { drop(Box::from_raw(self.tsfn_ptr as *mut FrameTsfnType)) };
+    if self.sink_ptr != 0 {
+      unsafe { drop(Box::from_raw(self.sink_ptr as *mut FrameSink)) };
     }
   }
 }
@@ -99,38 +273,131 @@ define_class!(
 
     impl StreamDelegate {
         #[unsafe(method(stream:didOutputSampleBuffer:ofType:))]
-        fn did_output(&self, _stream: &SCStream, sample: &CMSampleBuffer, kind: SCStreamOutputType) {
+        fn did_output(&self, stream: &SCStream, sample: &CMSampleBuffer, kind: SCStreamOutputType) {
             if kind == SCStreamOutputType::Screen {
-                 let ptr = self.ivars().tsfn_ptr;
-                 if ptr != 0 {
-                     let tsfn = unsafe { &*(ptr as *const FrameTsfnType) };
-
-                     unsafe {
-                         if let Some(frame) = extract_frame(sample) {
-                             tsfn.call(frame, ThreadsafeFunctionCallMode::NonBlocking);
+                 self.check_displays();
+                 self.apply_settings(stream);
+                 let ivars = self.ivars();
+                 // The stream keeps running while paused; its frames are not even copied.
+                 let idle = ivars.failed.load(Ordering::Relaxed) || ivars.settings.is_paused();
+                 if ivars.sink_ptr != 0 && !idle {
+                     let sink = unsafe { &*(ivars.sink_ptr as *const FrameSink) };
+
+                     // Samples of a still screen come without an image.
+                     let status = match unsafe { extract_frame(sample) } {
+                         Some(mut frame) => {
+                             frame.display_id = ivars.display_id;
+                             let stale = ivars.stale_frames.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+                             if stale.is_ok() {
+                                 frame.dirty_rects = None;
+                             }
+                             sink.send(frame)
                          }
+                         None => sink.idle(),
+                     };
+                     if status != Status::Ok {
+                         ivars.failed.store(true, Ordering::Relaxed);
                      }
                  }
             }
         }
+
+        #[unsafe(method(stream:didStopWithError:))]
+        fn did_stop(&self, _stream: &SCStream, error: &NSError) {
+            // The stream ends on its own when the captured window closes; no more frames are delivered.
+            let ivars = self.ivars();
+            match ivars.window_id {
+                Some(id) => ivars.events.fail(
+                    stream_error_code(error, ErrorCode::WindowClosed),
+                    format!("Window {} was closed", id),
+                ),
+                None => ivars.events.fail(
+                    stream_error_code(error, ErrorCode::DisplayLost),
+                    format!("ScreenCaptureKit stream stopped: {}", error.localizedDescription()),
+                ),
+            }
+        }
     }
 );
 
 unsafe impl SCStreamOutput for StreamDelegate {}
+unsafe impl SCStreamDelegate for StreamDelegate {}
 unsafe impl NSObjectProtocol for StreamDelegate {}
 unsafe impl Send for StreamDelegate {}
 unsafe impl Sync for StreamDelegate {}
 
 impl StreamDelegate {
-  fn new(tsfn: FrameTsfnType) -> Retained<Self> {
-    let boxed = Box::new(tsfn);
+  fn new(
+    sink: FrameSink,
+    options: &CaptureOptions,
+    applied: StreamSetup,
+    source_size: (usize, usize),
+    display_id: Option<u32>,
+  ) -> Retained<Self> {
+    let boxed = Box::new(sink);
     let ptr = Box::into_raw(boxed) as usize;
+    let watch = display_id.and_then(|id| {
+      let displays = active_displays();
+      let target = DisplayWatch::find(&displays, Some(id))?;
+      Some(DisplayWatch::new(options, displays, target))
+    });
 
     let cls = Self::class();
     let obj: Allocated<Self> = unsafe { msg_send![cls, alloc] };
-    let obj = obj.set_ivars(StreamDelegateIvars { tsfn_ptr: ptr });
+    let obj = obj.set_ivars(StreamDelegateIvars {
+      sink_ptr: ptr,
+      region: options.region.clone(),
+      settings: options.settings.clone(),
+      applied: StdMutex::new(applied),
+      source_size: StdMutex::new(source_size),
+      watch: StdMutex::new(watch),
+      window_id: options.window_id,
+      display_id,
+      events: options.events.clone(),
+      hide_cursor: options.hide_cursor,
+      stitch: options.stitch,
+      failed: AtomicBool::new(false),
+      stale_frames: AtomicU32::new(0),
+    });
     unsafe { msg_send![super(obj), init] }
   }
+
+  /// Lists the displays every `POLL_INTERVAL` and records the new size of the captured display
+  /// when its mode changed, for `apply_settings` to reconfigure the stream. The stream stops on
+  /// its own when the display is disconnected, which `did_stop` reports.
+  fn check_displays(&self) {
+    let ivars = self.ivars();
+    let mut watch = ivars.watch.lock().unwrap();
+    let Some(watch) = watch.as_mut().filter(|watch| watch.due()) else {
+      return;
+    };
+    if let Change::Reinit(display) = watch.update(active_displays()) {
+      *ivars.source_size.lock().unwrap() = (display.width as usize, display.height as usize);
+    }
+  }
+
+  /// Reconfigures the running stream when the capture region, output size or frame rate changed.
+  fn apply_settings(&self, stream: &SCStream) {
+    let ivars = self.ivars();
+    let (width, height) = *ivars.source_size.lock().unwrap();
+    let settings = &ivars.settings;
+    let setup = StreamSetup::new(
+      width,
+      height,
+      &ivars.region,
+      &native_scale(settings, ivars.stitch),
+      settings.fps(),
+    );
+    let mut applied = ivars.applied.lock().unwrap();
+    if setup == *applied {
+      return;
+    }
+
+    let config = unsafe { stream_configuration(&setup, ivars.hide_cursor) };
+    unsafe { stream.updateConfiguration_completionHandler(&config, None) };
+    *applied = setup;
+    ivars.stale_frames.store(QUEUE_DEPTH, Ordering::Relaxed);
+  }
 }
 
 pub struct ScreenshotDelegateIvars {
@@ -205,7 +472,165 @@ impl ScreenshotDelegate {
 struct SendRetained<T>(Retained<T>);
 unsafe impl<T> Send for SendRetained<T> {}
 
+async fn shareable_content() -> Result<SendRetained<SCShareableContent>> {
+  let (tx, rx) = tokio::sync::oneshot::channel();
+  // Use StdMutex for sync callback
+  let tx = Arc::new(StdMutex::new(Some(tx)));
+
+  {
+    let handler = RcBlock::new(
+      move |content: *mut SCShareableContent, error: *mut NSError| {
+        let mut tx_guard = tx.lock().unwrap();
+        if let Some(tx) = tx_guard.take() {
+          if !error.is_null() {
+            let _ = tx.send(Err("SCK Error".to_string()));
+          } else {
+            // Unsafe unwrap assuming content is valid if error is null
+            let content = unsafe { Retained::retain(content) }.expect("Content is null");
+            let _ = tx.send(Ok(SendRetained(content)));
+          }
+        }
+      },
+    );
+
+    unsafe {
+      SCShareableContent::getShareableContentWithCompletionHandler(&handler);
+    }
+  }
+
+  let content_opt = rx
+    .await
+    .map_err(|e| Error::new(Status::GenericFailure, format!("Await error: {:?}", e)))?;
+  content_opt.map_err(|e| Error::new(Status::GenericFailure, e))
+}
+
+/// Picks the display with the given id, or the main display when no id is given.
+fn find_display(
+  content: &SCShareableContent,
+  display_id: Option<u32>,
+) -> Result<Retained<SCDisplay>> {
+  let displays = unsafe { content.displays() };
+  let wanted = display_id.unwrap_or_else(|| CGDisplay::main().id);
+
+  if let Some(display) = displays.iter().find(|d| unsafe { d.displayID() } == wanted) {
+    return Ok(display);
+  }
+
+  match display_id {
+    Some(id) => Err(display_not_found(id)),
+    None => displays
+      .firstObject()
+      .ok_or_else(|| Error::new(Status::GenericFailure, "No display found".to_string())),
+  }
+}
+
+fn find_window(content: &SCShareableContent, window_id: u32) -> Result<Retained<SCWindow>> {
+  let windows = unsafe { content.windows() };
+  windows
+    .iter()
+    .find(|w| unsafe { w.windowID() } == window_id)
+    .ok_or_else(|| window_not_found(window_id))
+}
+
+/// Content filter for the display or window selected by `options`, with its size in points
+/// and the id of the display (`None` for windows).
+fn content_filter(
+  content: &SCShareableContent,
+  options: &CaptureOptions,
+) -> Result<(Retained<SCContentFilter>, (usize, usize), Option<u32>)> {
+  if let Some(id) = options.window_id {
+    let window = find_window(content, id)?;
+    let frame = unsafe { window.frame() };
+    let filter = unsafe {
+      SCContentFilter::initWithDesktopIndependentWindow(SCContentFilter::alloc(), &window)
+    };
+    return Ok((
+      filter,
+      (frame.size.width as usize, frame.size.height as usize),
+      None,
+    ));
+  }
+
+  let display = find_display(content, options.display_id)?;
+  let filter = unsafe {
+    SCContentFilter::initWithDisplay_excludingApplications_exceptingWindows(
+      SCContentFilter::alloc(),
+      &display,
+      &NSArray::array(),
+      &NSArray::array(),
+    )
+  };
+  let size = unsafe { (display.width() as usize, display.height() as usize) };
+  Ok((filter, size, Some(unsafe { display.displayID() })))
+}
+
+fn window_info(window: &SCWindow, minimized: &[u32]) -> WindowInfoInternal {
+  let id = unsafe { window.windowID() };
+  let frame = unsafe { window.frame() };
+  let app = unsafe { window.owningApplication() };
+
+  WindowInfoInternal {
+    id,
+    title: unsafe { window.title() }
+      .map(|t| t.to_string())
+      .unwrap_or_default(),
+    app_name: app
+      .as_ref()
+      .map(|a| unsafe { a.applicationName() }.to_string())
+      .unwrap_or_default(),
+    pid: app
+      .as_ref()
+      .map(|a| unsafe { a.processID() } as u32)
+      .unwrap_or(0),
+    x: frame.origin.x as i32,
+    y: frame.origin.y as i32,
+    width: frame.size.width as u32,
+    height: frame.size.height as u32,
+    is_minimized: minimized.contains(&id),
+    is_on_screen: unsafe { window.isOnScreen() },
+  }
+}
+
+/// Display `id`, measured in points like `SCDisplay`.
+fn display_info(id: u32) -> DisplayInfoInternal {
+  let cg_display = CGDisplay::new(id);
+  let bounds = cg_display.bounds();
+  let scale_factor = cg_display
+    .display_mode()
+    .filter(|mode| mode.width() > 0)
+    .map(|mode| mode.pixel_width() as f64 / mode.width() as f64)
+    .unwrap_or(1.0);
+  let name = if cg_display.is_builtin() {
+    "Built-in Display".to_string()
+  } else {
+    format!("Display {}", id)
+  };
+
+  DisplayInfoInternal {
+    id,
+    name,
+    x: bounds.origin.x as i32,
+    y: bounds.origin.y as i32,
+    width: bounds.size.width as u32,
+    height: bounds.size.height as u32,
+    scale_factor,
+    rotation: cg_display.rotation(),
+    is_primary: cg_display.is_main(),
+  }
+}
+
+/// Displays as CoreGraphics reports them right now. Unlike `SCShareableContent` they can be read
+/// synchronously, on the queue frames arrive on.
+fn active_displays() -> Vec<DisplayInfoInternal> {
+  CGDisplay::active_displays()
+    .unwrap_or_default()
+    .into_iter()
+    .map(display_info)
+    .collect()
+}
+
 pub struct SCKBackend {
+  options: CaptureOptions,
   stream: Option<Retained<SCStream>>,
   delegate: Option<Retained<StreamDelegate>>,
 }
@@ -214,8 +639,9 @@ unsafe impl Send for SCKBackend {}
 unsafe impl Sync for SCKBackend {}
 
 impl SCKBackend {
-  pub fn new() -> Self {
+  pub fn new(options: CaptureOptions) -> Self {
     Self {
+      options,
       stream: None,
       delegate: None,
     }
@@ -224,86 +650,45 @@ impl SCKBackend {
 
 impl Default for SCKBackend {
   fn default() -> Self {
-    Self::new()
+    Self::new(CaptureOptions::default())
   }
 }
 
 impl CaptureBackendImpl for SCKBackend {
   fn start<'a>(
     &'a mut self,
-    tsfn: Option<FrameTsfnType>,
-    fps: u32,
+    sink: Option<FrameSink>,
   ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
     Box::pin(async move {
-      let (tx, rx) = tokio::sync::oneshot::channel();
-      // Use StdMutex for sync callback
-      let tx = Arc::new(StdMutex::new(Some(tx)));
-
-      {
-        let handler = RcBlock::new(
-          move |content: *mut SCShareableContent, error: *mut NSError| {
-            let mut tx_guard = tx.lock().unwrap();
-            if let Some(tx) = tx_guard.take() {
-              if !error.is_null() {
-                let _ = tx.send(Err("SCK Error".to_string()));
-              } else {
-                // Unsafe unwrap assuming content is valid if error is null
-                let content = unsafe { Retained::retain(content) }.expect("Content is null");
-                let _ = tx.send(Ok(SendRetained(content)));
-              }
-            }
-          },
-        );
-
-        unsafe {
-          SCShareableContent::getShareableContentWithCompletionHandler(&handler);
-        }
-      }
-
-      let content_opt = rx
-        .await
-        .map_err(|e| Error::new(Status::GenericFailure, format!("Await error: {:?}", e)))?;
-      let content_res = content_opt.map_err(|e| Error::new(Status::GenericFailure, e))?;
+      let content_res = shareable_content().await?;
 
       // Scope to ensure !Send types are dropped before await (if any, though here we just process and return)
       let (stream_wrapper, delegate_wrapper) = {
         let content = content_res.0;
-        let displays = unsafe { content.displays() };
-        let display = displays
-          .firstObject()
-          .ok_or_else(|| Error::new(Status::GenericFailure, "No display found".to_string()))?;
-
-        let filter = unsafe {
-          SCContentFilter::initWithDisplay_excludingApplications_exceptingWindows(
-            SCContentFilter::alloc(),
-            &display,
-            &NSArray::array(),
-            &NSArray::array(),
-          )
-        };
+        let (filter, source_size, display_id) = content_filter(&content, &self.options)?;
+
+        let settings = &self.options.settings;
+        let setup = StreamSetup::new(
+          source_size.0,
+          source_size.1,
+          &self.options.region,
+          &native_scale(settings, self.options.stitch),
+          settings.fps(),
+        );
+        let config = unsafe { stream_configuration(&setup, self.options.hide_cursor) };
 
-        let config = unsafe { SCStreamConfiguration::new() };
-        unsafe {
-          config.setWidth(display.width() as usize);
-          config.setHeight(display.height() as usize);
-          config.setMinimumFrameInterval(CMTime {
-            value: 1,
-            timescale: fps as i32,
-            flags: CMTimeFlags(1),
-            epoch: 0,
-          });
-          config.setQueueDepth(5);
-          config.setPixelFormat(1111970369); // kCVPixelFormatType_32BGRA
-        }
+        let delegate =
+          sink.map(|sink| StreamDelegate::new(sink, &self.options, setup, source_size, display_id));
 
         let stream = unsafe {
-          SCStream::initWithFilter_configuration_delegate(SCStream::alloc(), &filter, &config, None)
-        };
-
-        let delegate = if let Some(tsfn) = tsfn {
-          Some(StreamDelegate::new(tsfn))
-        } else {
-          None
+          SCStream::initWithFilter_configuration_delegate(
+            SCStream::alloc(),
+            &filter,
+            &config,
+            delegate
+              .as_deref()
+              .map(|d| ProtocolObject::from_ref(d) as &ProtocolObject<dyn SCStreamDelegate>),
+          )
         };
 
         let queue = unsafe { dispatch_queue_create(c"com.napi.sck".as_ptr(), ptr::null_mut()) };
@@ -315,9 +700,16 @@ impl CaptureBackendImpl for SCKBackend {
         }
 
         {
+          let events = self.options.events.clone();
           let start_handler = RcBlock::new(move |error: *mut NSError| {
-            if !error.is_null() {
-              // println!("SCK Start failed with error");
+            if let Some(error) = unsafe { error.as_ref() } {
+              events.fail(
+                stream_error_code(error, ErrorCode::CaptureFailed),
+                format!(
+                  "ScreenCaptureKit failed to start: {}",
+                  error.localizedDescription()
+                ),
+              );
             }
           });
 
@@ -349,63 +741,20 @@ impl CaptureBackendImpl for SCKBackend {
     &'a mut self,
   ) -> Pin<Box<dyn Future<Output = Result<FrameDataInternal>> + Send + 'a>> {
     Box::pin(async move {
-      let (tx, rx) = tokio::sync::oneshot::channel();
-      let tx = Arc::new(StdMutex::new(Some(tx)));
-
-      {
-        let handler = RcBlock::new(
-          move |content: *mut SCShareableContent, error: *mut NSError| {
-            let mut tx_guard = tx.lock().unwrap();
-            if let Some(tx) = tx_guard.take() {
-              if !error.is_null() {
-                let _ = tx.send(Err("SCK Error".to_string()));
-              } else {
-                let content = unsafe { Retained::retain(content) }.expect("Content is null");
-                let _ = tx.send(Ok(SendRetained(content)));
-              }
-            }
-          },
-        );
+      let content_res = shareable_content().await?;
 
-        unsafe {
-          SCShareableContent::getShareableContentWithCompletionHandler(&handler);
-        }
-      }
-
-      let content_opt = rx
-        .await
-        .map_err(|e| Error::new(Status::GenericFailure, format!("Await error: {:?}", e)))?;
-      let content_res = content_opt.map_err(|e| Error::new(Status::GenericFailure, e))?;
-
-      let (stream_wrapper, _delegate_wrapper, frame_rx) = {
+      let (stream_wrapper, _delegate_wrapper, frame_rx, display_id) = {
         let content = content_res.0;
-        let displays = unsafe { content.displays() };
-        let display = displays
-          .firstObject()
-          .ok_or_else(|| Error::new(Status::GenericFailure, "No display found".to_string()))?;
-
-        let filter = unsafe {
-          SCContentFilter::initWithDisplay_excludingApplications_exceptingWindows(
-            SCContentFilter::alloc(),
-            &display,
-            &NSArray::array(),
-            &NSArray::array(),
-          )
-        };
-
-        let config = unsafe { SCStreamConfiguration::new() };
-        unsafe {
-          config.setWidth(display.width() as usize);
-          config.setHeight(display.height() as usize);
-          config.setMinimumFrameInterval(CMTime {
-            value: 1,
-            timescale: 60,
-            flags: CMTimeFlags(1),
-            epoch: 0,
-          });
-          config.setQueueDepth(5);
-          config.setPixelFormat(1111970369); // kCVPixelFormatType_32BGRA
-        }
+        let (filter, (width, height), display_id) = content_filter(&content, &self.options)?;
+
+        let setup = StreamSetup::new(
+          width,
+          height,
+          &self.options.region,
+          &native_scale(&self.options.settings, self.options.stitch),
+          60,
+        );
+        let config = unsafe { stream_configuration(&setup, self.options.hide_cursor) };
 
         let stream = unsafe {
           SCStream::initWithFilter_configuration_delegate(SCStream::alloc(), &filter, &config, None)
@@ -428,7 +777,12 @@ impl CaptureBackendImpl for SCKBackend {
           }
         }
 
-        (SendRetained(stream), SendRetained(delegate), frame_rx)
+        (
+          SendRetained(stream),
+          SendRetained(delegate),
+          frame_rx,
+          display_id,
+        )
       };
 
       // Wait for frame
@@ -440,7 +794,10 @@ impl CaptureBackendImpl for SCKBackend {
       unsafe { stream.stopCaptureWithCompletionHandler(Some(&*handler)) };
 
       match frame_res {
-        Ok(frame) => Ok(frame),
+        Ok(frame) => Ok(FrameDataInternal {
+          display_id,
+          ..frame
+        }),
         Err(_) => Err(Error::new(
           Status::GenericFailure,
           "Failed to capture frame".to_string(),
@@ -448,4 +805,42 @@ impl CaptureBackendImpl for SCKBackend {
       }
     })
   }
+
+  fn list_displays<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<DisplayInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move {
+      let content = shareable_content().await?.0;
+      let displays = unsafe { content.displays() };
+      Ok(
+        displays
+          .iter()
+          .map(|d| display_info(unsafe { d.displayID() }))
+          .collect(),
+      )
+    })
+  }
+
+  fn list_windows<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<WindowInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move {
+      // ScreenCaptureKit does not report minimized windows; XCap reads it from CGWindowList.
+      let minimized: Vec<u32> = super::xcap::list_windows()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|w| w.is_minimized)
+        .map(|w| w.id)
+        .collect();
+
+      let content = shareable_content().await?.0;
+      let windows = unsafe { content.windows() };
+      Ok(
+        windows
+          .iter()
+          .map(|w| window_info(&w, &minimized))
+          .collect(),
+      )
+    })
+  }
 }
@@ -1,37 +1,784 @@
-use napi::threadsafe_function::ThreadsafeFunction;
-use napi::{sys, Result, Status};
 use std::future::Future;
-use std::pin::Pin;
-use std::sync::Arc;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex as StdMutex, MutexGuard, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
 
+use crate::error::{Error, Result, Status};
+use cursor::CursorInternal;
+use delta::{DeltaEncoder, DeltaOptions};
+use dirty::{ChangeFilter, ChangeOptions, DirtyTracker};
+use encode::ImageFormat;
+use events::{CaptureState, ErrorCode, EventSink};
+use multi::Stitcher;
+use output::OutputOptions;
+use pixel::PixelFormat;
+use replay::{ReplayBuffer, ReplayOptions};
+use stream::{FrameQueue, FrameStream, Push, QueueOptions};
+
+#[derive(Clone, Default)]
 pub struct FrameDataInternal {
   pub width: u32,
   pub height: u32,
   pub stride: u32,
+  /// Backends emit `Rgba` or `Bgra`, whichever the platform hands out without a copy.
+  pub format: PixelFormat,
+  /// Set once the `encode` output option compressed the frame; `data` then holds the image file.
+  pub encoding: Option<ImageFormat>,
   pub data: Vec<u8>,
+  /// When the frame was captured, on the `host_time` clock.
+  pub timestamp: Duration,
+  /// Position in the capture session, starting at 0. Set by `FrameSink`.
+  pub sequence: u64,
+  /// Display the frame shows; `None` for window captures and stitched frames.
+  pub display_id: Option<u32>,
+  /// Frames the backpressure policy discarded for this consumer just before this one.
+  pub dropped_since_last: u64,
+  /// Parts of the frame that changed since the previous one. Backends set it when the platform
+  /// reports them; with the `dirty_rects` option `FrameSink` compares tiles for the others.
+  pub dirty_rects: Option<Vec<RegionInternal>>,
+  /// Set once the `delta` output option turned the frame into a delta packet, held in `data`:
+  /// whether the packet is a keyframe.
+  pub keyframe: Option<bool>,
+  /// Pointer position and shape, when the backend reports them. `FrameSink` drops it unless
+  /// the `cursor_metadata` option is set.
+  pub cursor: Option<CursorInternal>,
+}
+
+/// Monotonic clock that frame timestamps are measured on, counted from its first use.
+/// Backends with native presentation times convert them to this clock.
+pub fn host_time() -> Duration {
+  static EPOCH: OnceLock<Instant> = OnceLock::new();
+  EPOCH.get_or_init(Instant::now).elapsed()
+}
+
+/// `host_time` of an event that happened `age` ago.
+pub fn host_time_before(age: Duration) -> Duration {
+  host_time().saturating_sub(age)
+}
+
+/// Runs `future` on the calling thread until it completes. Backends need no async runtime, so
+/// callers without one can wait for them this way.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+  match run(future, None) {
+    Some(output) => output,
+    None => unreachable!("block_on has no deadline"),
+  }
+}
+
+/// Like `block_on`, but gives up at `deadline` and returns `None`.
+pub fn block_on_until<F: Future>(future: F, deadline: Instant) -> Option<F::Output> {
+  run(future, Some(deadline))
+}
+
+fn run<F: Future>(future: F, deadline: Option<Instant>) -> Option<F::Output> {
+  struct Unpark(Thread);
+
+  impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+      self.0.unpark();
+    }
+  }
+
+  let waker = Waker::from(Arc::new(Unpark(thread::current())));
+  let mut cx = Context::from_waker(&waker);
+  let mut future = pin!(future);
+  loop {
+    if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+      return Some(output);
+    }
+    match deadline {
+      Some(deadline) => {
+        let now = Instant::now();
+        if now >= deadline {
+          return None;
+        }
+        thread::park_timeout(deadline - now);
+      }
+      None => thread::park(),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayInfoInternal {
+  pub id: u32,
+  pub name: String,
+  pub x: i32,
+  pub y: i32,
+  pub width: u32,
+  pub height: u32,
+  pub scale_factor: f64,
+  pub rotation: f64,
+  pub is_primary: bool,
+}
+
+pub struct WindowInfoInternal {
+  pub id: u32,
+  pub title: String,
+  pub app_name: String,
+  pub pid: u32,
+  pub x: i32,
+  pub y: i32,
+  pub width: u32,
+  pub height: u32,
+  pub is_minimized: bool,
+  pub is_on_screen: bool,
+}
+
+/// Rectangle relative to the top-left corner of the captured display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegionInternal {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+}
+
+impl RegionInternal {
+  pub fn full(width: u32, height: u32) -> Self {
+    Self {
+      x: 0,
+      y: 0,
+      width,
+      height,
+    }
+  }
+
+  pub fn validate(&self, bounds: Option<(u32, u32)>) -> Result<()> {
+    if self.width == 0 || self.height == 0 {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "Region width and height must be greater than 0".to_string(),
+      ));
+    }
+    if let Some((width, height)) = bounds {
+      let right = self.x as u64 + self.width as u64;
+      let bottom = self.y as u64 + self.height as u64;
+      if right > width as u64 || bottom > height as u64 {
+        return Err(Error::new(
+          Status::InvalidArg,
+          format!(
+            "Region {}x{} at ({}, {}) is outside the {}x{} display",
+            self.width, self.height, self.x, self.y, width, height
+          ),
+        ));
+      }
+    }
+    Ok(())
+  }
+
+  /// Intersection with a `width` x `height` frame, or `None` when they do not overlap.
+  pub fn clamp(&self, width: u32, height: u32) -> Option<Self> {
+    let right = self.x.saturating_add(self.width).min(width);
+    let bottom = self.y.saturating_add(self.height).min(height);
+    (self.x < right && self.y < bottom).then(|| Self {
+      x: self.x,
+      y: self.y,
+      width: right - self.x,
+      height: bottom - self.y,
+    })
+  }
+}
+
+#[derive(Debug, Default)]
+struct RegionState {
+  region: Option<RegionInternal>,
+  bounds: Option<(u32, u32)>,
+}
+
+/// Capture region shared between `ScreenCapture` and a running capture loop,
+/// so it can be moved without restarting the session.
+#[derive(Clone, Debug, Default)]
+pub struct SharedRegion(Arc<StdMutex<RegionState>>);
+
+impl SharedRegion {
+  pub fn new(region: Option<RegionInternal>) -> Result<Self> {
+    if let Some(region) = &region {
+      region.validate(None)?;
+    }
+    Ok(Self(Arc::new(StdMutex::new(RegionState {
+      region,
+      bounds: None,
+    }))))
+  }
+
+  pub fn get(&self) -> Option<RegionInternal> {
+    self.0.lock().unwrap().region
+  }
+
+  /// Replaces the region, checking it against the display size recorded by `bind`.
+  pub fn set(&self, region: Option<RegionInternal>) -> Result<()> {
+    let mut state = self.0.lock().unwrap();
+    if let Some(region) = &region {
+      region.validate(state.bounds)?;
+    }
+    state.region = region;
+    Ok(())
+  }
+
+  /// Records the size of the display being captured and checks the current region against it.
+  pub fn bind(&self, width: u32, height: u32) -> Result<()> {
+    let mut state = self.0.lock().unwrap();
+    if let Some(region) = &state.region {
+      region.validate(Some((width, height)))?;
+    }
+    state.bounds = Some((width, height));
+    Ok(())
+  }
+
+  /// Records the new size of the captured display after it changed. Unlike `bind` it never
+  /// fails: a region that no longer fits is clipped by `rect_within`.
+  pub fn resize(&self, width: u32, height: u32) {
+    self.0.lock().unwrap().bounds = Some((width, height));
+  }
+
+  /// Part of a `width` x `height` frame to deliver: the region, clipped to the
+  /// frame in case the display shrank, or the whole frame when no region is set.
+  pub fn rect_within(&self, width: u32, height: u32) -> RegionInternal {
+    self
+      .get()
+      .and_then(|region| region.clamp(width, height))
+      .unwrap_or(RegionInternal::full(width, height))
+  }
+}
+
+#[derive(Debug)]
+struct Settings {
+  fps: u32,
+  paused: bool,
+  output: OutputOptions,
+  /// Bumped whenever `fps` or `paused` changes, to wake loops waiting in `sleep`.
+  pacing_changes: u64,
+}
+
+#[derive(Debug)]
+struct SettingsState {
+  settings: StdMutex<Settings>,
+  pacing_changed: Condvar,
+}
+
+/// Frame rate, pause state and output settings shared between `ScreenCapture` and a
+/// running capture loop, so they can change without restarting the session.
+#[derive(Clone, Debug)]
+pub struct SharedSettings(Arc<SettingsState>);
+
+impl SharedSettings {
+  pub const DEFAULT_FPS: u32 = 60;
+
+  pub fn new(fps: u32, output: OutputOptions) -> Result<Self> {
+    validate_fps(fps)?;
+    let settings = Settings {
+      fps,
+      paused: false,
+      output,
+      pacing_changes: 0,
+    };
+    Ok(Self(Arc::new(SettingsState {
+      settings: StdMutex::new(settings),
+      pacing_changed: Condvar::new(),
+    })))
+  }
+
+  fn lock(&self) -> MutexGuard<'_, Settings> {
+    self.0.settings.lock().unwrap()
+  }
+
+  fn update_pacing(&self, update: impl FnOnce(&mut Settings)) {
+    let mut settings = self.lock();
+    update(&mut settings);
+    settings.pacing_changes += 1;
+    self.0.pacing_changed.notify_all();
+  }
+
+  pub fn fps(&self) -> u32 {
+    self.lock().fps
+  }
+
+  /// Time between two frames at the current frame rate.
+  pub fn frame_interval(&self) -> Duration {
+    Duration::from_secs_f64(1.0 / self.fps() as f64)
+  }
+
+  pub fn set_fps(&self, fps: u32) -> Result<()> {
+    validate_fps(fps)?;
+    self.update_pacing(|settings| settings.fps = fps);
+    Ok(())
+  }
+
+  /// While paused, capture loops keep their pace but capture nothing and `FrameSink`
+  /// discards frames pushed by the platform.
+  pub fn is_paused(&self) -> bool {
+    self.lock().paused
+  }
+
+  pub fn set_paused(&self, paused: bool) {
+    self.update_pacing(|settings| settings.paused = paused);
+  }
+
+  pub fn output(&self) -> OutputOptions {
+    self.lock().output.clone()
+  }
+
+  pub fn set_output(&self, output: OutputOptions) {
+    self.lock().output = output;
+  }
+
+  /// Sleeps for `duration`, or until the frame rate or pause state changes, so a loop
+  /// waiting at a low frame rate picks up a new one right away. Returns true when woken early.
+  pub fn sleep(&self, duration: Duration) -> bool {
+    let settings = self.lock();
+    let changes = settings.pacing_changes;
+    let (settings, _) = self
+      .0
+      .pacing_changed
+      .wait_timeout_while(settings, duration, |s| s.pacing_changes == changes)
+      .unwrap();
+    settings.pacing_changes != changes
+  }
+}
+
+impl Default for SharedSettings {
+  fn default() -> Self {
+    Self::new(Self::DEFAULT_FPS, OutputOptions::default()).unwrap()
+  }
+}
+
+fn validate_fps(fps: u32) -> Result<()> {
+  if fps == 0 {
+    return Err(Error::new(
+      Status::InvalidArg,
+      "fps must be greater than 0".to_string(),
+    ));
+  }
+  Ok(())
+}
+
+/// Backend independent capture settings.
+#[derive(Clone, Debug, Default)]
+pub struct CaptureOptions {
+  /// Display to capture, as reported by `list_displays`. `None` selects the primary display.
+  pub display_id: Option<u32>,
+  /// Displays captured together when there are several; `display_id` is then unset.
+  pub displays: Vec<u32>,
+  /// Deliver the frames of `displays` as one frame laid out like the virtual desktop. Set on
+  /// the backend of each display too, so it leaves scaling to `FrameSink`.
+  pub stitch: bool,
+  /// Window to capture instead of a display, as reported by `list_windows`.
+  pub window_id: Option<u32>,
+  /// Crop rectangle inside the display; read on every frame.
+  pub region: SharedRegion,
+  /// Frame rate, pause state and output settings; read on every frame.
+  pub settings: SharedSettings,
+  /// How frames wait for slow consumers.
+  pub queue: QueueOptions,
+  /// Keep the last frames of the session in memory.
+  pub replay: Option<ReplayOptions>,
+  /// Report the parts of each frame that changed since the previous one.
+  pub dirty_rects: bool,
+  /// Hold back frames identical to the previous one.
+  pub emit_only_on_change: Option<ChangeOptions>,
+  /// Deliver delta packets instead of frames; replaces `pixel_format` and `encode`.
+  pub delta: Option<DeltaOptions>,
+  /// Leave the pointer out of the frames. Backends that cannot control it ignore this.
+  pub hide_cursor: bool,
+  /// Report the pointer position and shape with every frame.
+  pub cursor_metadata: bool,
+  /// Where errors and state changes of the session are reported.
+  pub events: EventSink,
+}
+
+impl CaptureOptions {
+  /// Whether several displays are captured and delivered as separate frames.
+  pub fn separate_displays(&self) -> bool {
+    self.displays.len() > 1 && !self.stitch
+  }
+}
+
+pub fn display_not_found(id: u32) -> Error {
+  Error::new(Status::InvalidArg, format!("Display {} not found", id))
+}
+
+pub fn window_not_found(id: u32) -> Error {
+  Error::new(Status::InvalidArg, format!("Window {} not found", id))
+}
+
+/// Tells the consumer that a frame is waiting in the callback queue. A status other than `Ok`
+/// means it can no longer be called.
+pub type FrameNotify = Box<dyn Fn() -> Status + Send + Sync>;
+
+/// The frame callback and the queue its frames wait in. `notify` pops one frame per call,
+/// so it is only called for frames that grew the queue.
+pub struct FrameCallback {
+  pub notify: FrameNotify,
+  pub queue: Arc<FrameQueue>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+  captured: AtomicU64,
+  dropped: AtomicU64,
+  sequence: AtomicU64,
+}
+
+pub struct CaptureStatsInternal {
+  pub frames_captured: u64,
+  pub frames_dropped: u64,
+}
+
+/// Queue of a `FrameStream`, and whether it wants frames before the pixel format
+/// conversion and encoding.
+#[derive(Clone)]
+struct Subscriber {
+  queue: Arc<FrameQueue>,
+  unconverted: bool,
 }
 
-pub type FrameTsfn =
-  ThreadsafeFunction<FrameDataInternal, (), sys::napi_value, Status, false, false, 0>;
-pub type FrameTsfnType = Arc<FrameTsfn>;
+/// Delivers frames from a capture loop to the frame callback and to every open
+/// `FrameStream`, applying the output settings first.
+#[derive(Clone)]
+pub struct FrameSink {
+  callback: Option<Arc<FrameCallback>>,
+  streams: Arc<StdMutex<Vec<Subscriber>>>,
+  counters: Arc<Counters>,
+  settings: SharedSettings,
+  queue: QueueOptions,
+  events: EventSink,
+  replay: Option<ReplayBuffer>,
+  dirty: Option<Arc<StdMutex<DirtyTracker>>>,
+  changes: Option<Arc<StdMutex<ChangeFilter>>>,
+  delta: Option<Arc<StdMutex<DeltaEncoder>>>,
+  cursor_metadata: bool,
+  stitcher: Option<Arc<StdMutex<Stitcher>>>,
+}
+
+impl FrameSink {
+  /// Creates a sink for the session configured by `options`. Streams end whenever
+  /// `options.events` moves to `Stopped`, including when the backend fails.
+  pub fn new(callback: Option<FrameCallback>, options: &CaptureOptions) -> Self {
+    let sink = Self {
+      callback: callback.map(Arc::new),
+      streams: Arc::default(),
+      counters: Arc::default(),
+      settings: options.settings.clone(),
+      queue: options.queue,
+      events: options.events.clone(),
+      replay: options.replay.map(ReplayBuffer::new),
+      dirty: options.dirty_rects.then(Arc::default),
+      changes: options
+        .emit_only_on_change
+        .map(|changes| Arc::new(StdMutex::new(ChangeFilter::new(changes)))),
+      delta: options
+        .delta
+        .map(|delta| Arc::new(StdMutex::new(DeltaEncoder::new(delta)))),
+      cursor_metadata: options.cursor_metadata,
+      stitcher: None,
+    };
+    // Only the queues are captured: the listener must not keep the callback alive.
+    let streams = sink.streams.clone();
+    let callback_queue = sink.callback.as_ref().map(|c| c.queue.clone());
+    options.events.on_state_change(move |state| {
+      if state == CaptureState::Stopped {
+        close_queues(&streams, callback_queue.as_deref());
+      }
+    });
+    sink
+  }
+
+  /// A sink for the backends of a multi-display session that paints the frames they send into
+  /// `stitcher` and delivers the stitched frame at most once per frame interval.
+  pub fn stitched(&self, stitcher: Stitcher) -> Self {
+    Self {
+      stitcher: Some(Arc::new(StdMutex::new(stitcher))),
+      ..self.clone()
+    }
+  }
+
+  /// Opens a stream that receives every frame sent from now on.
+  pub fn subscribe(&self) -> FrameStream {
+    self.add_stream(false)
+  }
+
+  /// Like `subscribe`, but frames are only scaled: they keep the `Rgba` or `Bgra` format
+  /// of the backend whatever the `pixel_format` and `encode` settings. Used by recordings.
+  pub fn subscribe_unconverted(&self) -> FrameStream {
+    self.add_stream(true)
+  }
+
+  fn add_stream(&self, unconverted: bool) -> FrameStream {
+    // Delta packets of a new stream must start with a keyframe.
+    if !unconverted {
+      self.request_keyframe();
+    }
+    let queue = FrameQueue::new(self.queue);
+    self.streams.lock().unwrap().push(Subscriber {
+      queue: queue.clone(),
+      unconverted,
+    });
+    FrameStream::new(queue)
+  }
+
+  /// Ends all open streams and releases a capture loop blocked on a full queue.
+  pub fn close(&self) {
+    let callback_queue = self.callback.as_ref().map(|c| c.queue.as_ref());
+    close_queues(&self.streams, callback_queue);
+  }
+
+  /// Starts a new session: sequence numbers restart at 0, the callback receives frames again
+  /// after `close`, the replay buffer drops the frames of the previous session and the first
+  /// frame is dirty as a whole, or a keyframe with the `delta` option.
+  pub fn reopen(&self) {
+    self.counters.sequence.store(0, Ordering::Relaxed);
+    if let Some(dirty) = &self.dirty {
+      dirty.lock().unwrap().reset();
+    }
+    if let Some(delta) = &self.delta {
+      delta.lock().unwrap().reset();
+    }
+    self.refresh();
+    if let Some(callback) = &self.callback {
+      callback.queue.reopen();
+    }
+    if let Some(replay) = &self.replay {
+      replay.clear();
+    }
+  }
+
+  /// Lets the next frame through with `emit_only_on_change` even if it is unchanged, so new
+  /// output settings show on a still screen.
+  pub fn refresh(&self) {
+    if let Some(changes) = &self.changes {
+      changes.lock().unwrap().reset();
+    }
+  }
+
+  /// Makes the next delta packet a keyframe. Returns false when the `delta` option is not set.
+  pub fn request_keyframe(&self) -> bool {
+    match &self.delta {
+      Some(delta) => {
+        delta.lock().unwrap().request_keyframe();
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Last frames of the session, when the `replay` option is set.
+  pub fn replay(&self) -> Option<&ReplayBuffer> {
+    self.replay.as_ref()
+  }
+
+  pub fn stats(&self) -> CaptureStatsInternal {
+    CaptureStatsInternal {
+      frames_captured: self.counters.captured.load(Ordering::Relaxed),
+      frames_dropped: self.counters.dropped.load(Ordering::Relaxed),
+    }
+  }
+
+  /// Delivers a frame, or discards it while the session is paused or, with `emit_only_on_change`,
+  /// when it is unchanged. A status other than `Ok` means the session failed and was reported
+  /// to `events`; the capture loop should end.
+  pub fn send(&self, mut frame: FrameDataInternal) -> Status {
+    if self.settings.is_paused() {
+      return Status::Ok;
+    }
+    if !self.cursor_metadata {
+      frame.cursor = None;
+    }
+    if let Some(stitcher) = &self.stitcher {
+      let mut stitcher = stitcher.lock().unwrap();
+      stitcher.paint(frame);
+      match stitcher.take(host_time(), self.settings.frame_interval()) {
+        Some(stitched) => frame = stitched,
+        None => return Status::Ok,
+      }
+    }
+    self.deliver_changed(frame)
+  }
+
+  /// Called by capture loops on ticks without a new frame, such as DXGI timeouts. Delivers the
+  /// stitched frame held back by the frame interval, or repeats the last frame when the
+  /// `emit_only_on_change` keepalive is due.
+  pub fn idle(&self) -> Status {
+    if self.settings.is_paused() {
+      return Status::Ok;
+    }
+    let stitched = self.stitcher.as_ref().and_then(|stitcher| {
+      let mut stitcher = stitcher.lock().unwrap();
+      stitcher.take(host_time(), self.settings.frame_interval())
+    });
+    if let Some(frame) = stitched {
+      return self.deliver_changed(frame);
+    }
+    let keepalive = self
+      .changes
+      .as_ref()
+      .and_then(|changes| changes.lock().unwrap().keepalive(host_time()));
+    match keepalive {
+      Some(frame) => self.deliver(frame),
+      None => Status::Ok,
+    }
+  }
+
+  /// Delivers `frame` unless `emit_only_on_change` holds it back.
+  fn deliver_changed(&self, frame: FrameDataInternal) -> Status {
+    if let Some(changes) = &self.changes {
+      if !changes.lock().unwrap().accept(&frame) {
+        return Status::Ok;
+      }
+    }
+    self.deliver(frame)
+  }
+
+  fn deliver(&self, mut frame: FrameDataInternal) -> Status {
+    frame.sequence = self.counters.sequence.fetch_add(1, Ordering::Relaxed);
+    let (unconverted, streams): (Vec<_>, Vec<_>) = {
+      let mut streams = self.streams.lock().unwrap();
+      streams.retain(|stream| !stream.queue.is_closed());
+      streams
+        .iter()
+        .cloned()
+        .partition(|stream| stream.unconverted)
+    };
+    if self.callback.is_none()
+      && streams.is_empty()
+      && unconverted.is_empty()
+      && self.replay.is_none()
+    {
+      return Status::Ok;
+    }
+
+    let output = self.settings.output();
+    let mut frame = output.resize(frame);
+    match &self.dirty {
+      Some(dirty) => dirty.lock().unwrap().track(&mut frame),
+      None => frame.dirty_rects = None,
+    }
+    if let Some(replay) = &self.replay {
+      if let Err(e) = replay.push(frame.clone()) {
+        self.events.warn(ErrorCode::ProcessingFailed, e.reason);
+      }
+    }
+    let scaled = (!unconverted.is_empty()).then(|| frame.clone());
+    let status = if self.callback.is_none() && streams.is_empty() {
+      Status::Ok
+    } else {
+      let frame = match &self.delta {
+        Some(delta) => delta.lock().unwrap().encode(frame),
+        None => output.convert(frame),
+      };
+      let frame = match frame {
+        Ok(frame) => frame,
+        Err(e) => {
+          self.events.fail(ErrorCode::ProcessingFailed, e.reason);
+          return e.status;
+        }
+      };
+      // Streams may block, so the callback gets the frame first.
+      match streams.split_last() {
+        None => self.notify(frame),
+        Some((last, rest)) => {
+          let status = self.notify(frame.clone());
+          for stream in rest {
+            self.enqueue(&stream.queue, frame.clone());
+          }
+          self.enqueue(&last.queue, frame);
+          status
+        }
+      }
+    };
+    self.counters.captured.fetch_add(1, Ordering::Relaxed);
+    if let Some(scaled) = scaled {
+      for stream in &unconverted {
+        self.enqueue(&stream.queue, scaled.clone());
+      }
+    }
+    if status != Status::Ok {
+      self.events.fail(
+        ErrorCode::StoppedByConsumer,
+        format!("The frame callback can no longer be called: {}", status),
+      );
+    }
+    status
+  }
+
+  fn notify(&self, frame: FrameDataInternal) -> Status {
+    match &self.callback {
+      Some(callback) => match self.enqueue(&callback.queue, frame) {
+        Push::Queued => (callback.notify)(),
+        _ => Status::Ok,
+      },
+      None => Status::Ok,
+    }
+  }
+
+  fn enqueue(&self, queue: &FrameQueue, frame: FrameDataInternal) -> Push {
+    let packet = frame.keyframe.is_some();
+    let push = queue.push(frame);
+    if matches!(push, Push::Replaced | Push::Dropped) {
+      self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+      // Later packets build on the lost one, so the consumer needs a keyframe to catch up.
+      if packet {
+        self.request_keyframe();
+      }
+    }
+    push
+  }
+}
+
+fn close_queues(streams: &StdMutex<Vec<Subscriber>>, callback_queue: Option<&FrameQueue>) {
+  for stream in streams.lock().unwrap().drain(..) {
+    stream.queue.close();
+  }
+  if let Some(queue) = callback_queue {
+    queue.close();
+  }
+}
 
 pub trait CaptureBackendImpl: Send + Sync {
   fn start<'a>(
     &'a mut self,
-    tsfn: Option<FrameTsfnType>,
-    fps: u32,
+    sink: Option<FrameSink>,
   ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
   fn stop(&mut self) -> Result<()>;
 
   fn screenshot<'a>(
     &'a mut self,
   ) -> Pin<Box<dyn Future<Output = Result<FrameDataInternal>> + Send + 'a>>;
+
+  fn list_displays<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<DisplayInfoInternal>>> + Send + 'a>>;
+
+  fn list_windows<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<WindowInfoInternal>>> + Send + 'a>>;
 }
 
+pub mod clip;
+pub mod cursor;
+pub mod delta;
+pub mod dirty;
 #[cfg(target_os = "windows")]
 pub mod dxgi;
+pub mod encode;
+pub mod events;
+#[cfg(target_os = "linux")]
+pub mod linux;
 #[cfg(target_os = "macos")]
 pub mod macos;
+pub mod multi;
+pub mod output;
+pub mod pixel;
+pub mod record;
+pub mod replay;
+pub mod stream;
+pub mod synthetic;
+pub mod topology;
 #[cfg(target_os = "windows")]
 pub mod windows;
+#[cfg(target_os = "linux")]
+pub mod x11;
 pub mod xcap;
@@ -0,0 +1,295 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use super::cursor::CursorInternal;
+use super::output::{self, ScaleFilter};
+use super::pixel::{self, YuvOptions};
+use super::{
+  display_not_found, CaptureBackendImpl, CaptureOptions, DisplayInfoInternal, FrameDataInternal,
+  FrameSink, RegionInternal, SharedRegion, WindowInfoInternal,
+};
+use crate::error::{Error, Result, Status};
+
+/// Where the displays of a session sit in the stitched frame: their virtual-desktop positions,
+/// relative to the top-left corner of the box around them.
+#[derive(Clone, Debug)]
+pub struct Layout {
+  pub displays: Vec<(u32, RegionInternal)>,
+  pub width: u32,
+  pub height: u32,
+}
+
+impl Layout {
+  /// Layout of the displays `ids`, as reported by `list_displays`. The stitched frame is measured
+  /// in the units `list_displays` reports, points on macOS.
+  pub fn new(displays: &[DisplayInfoInternal], ids: &[u32]) -> Result<Self> {
+    let selected = ids
+      .iter()
+      .map(|&id| {
+        displays
+          .iter()
+          .find(|d| d.id == id)
+          .ok_or_else(|| display_not_found(id))
+      })
+      .collect::<Result<Vec<_>>>()?;
+    let left = selected.iter().map(|d| d.x as i64).min().unwrap_or(0);
+    let top = selected.iter().map(|d| d.y as i64).min().unwrap_or(0);
+    let right = selected.iter().map(|d| d.x as i64 + d.width as i64).max();
+    let bottom = selected.iter().map(|d| d.y as i64 + d.height as i64).max();
+    let (width, height) = (right.unwrap_or(0) - left, bottom.unwrap_or(0) - top);
+    if width <= 0 || height <= 0 || width > u32::MAX as i64 || height > u32::MAX as i64 {
+      return Err(Error::new(
+        Status::GenericFailure,
+        format!("Cannot stitch displays spanning {}x{}", width, height),
+      ));
+    }
+    Ok(Self {
+      displays: selected
+        .iter()
+        .map(|d| {
+          let rect = RegionInternal {
+            x: (d.x as i64 - left) as u32,
+            y: (d.y as i64 - top) as u32,
+            width: d.width,
+            height: d.height,
+          };
+          (d.id, rect)
+        })
+        .collect(),
+      width: width as u32,
+      height: height as u32,
+    })
+  }
+
+  fn rect(&self, id: u32) -> Option<RegionInternal> {
+    self
+      .displays
+      .iter()
+      .find(|(display, _)| *display == id)
+      .map(|(_, rect)| *rect)
+  }
+}
+
+/// Paints the frames of several displays into one frame laid out like the virtual desktop.
+/// Parts of the box no display covers stay black.
+pub struct Stitcher {
+  layout: Layout,
+  /// Crop rectangle inside the stitched frame.
+  region: SharedRegion,
+  /// Stitched frame so far, in the format of the first display frame.
+  canvas: Option<FrameDataInternal>,
+  /// Pointer in stitched frame pixels, and the display that reported it.
+  cursor: Option<(u32, CursorInternal)>,
+  /// Displays painted so far.
+  painted: HashSet<u32>,
+  /// Whether a display frame was painted since the last stitched frame.
+  pending: bool,
+  /// When the last stitched frame was taken.
+  taken: Option<Duration>,
+}
+
+impl Stitcher {
+  pub fn new(layout: Layout, region: SharedRegion) -> Self {
+    Self {
+      layout,
+      region,
+      canvas: None,
+      cursor: None,
+      painted: HashSet::new(),
+      pending: false,
+      taken: None,
+    }
+  }
+
+  /// Paints a display frame over its place in the layout, scaled to the size of the display.
+  /// Frames of displays outside the layout are ignored.
+  pub fn paint(&mut self, frame: FrameDataInternal) {
+    let Some((id, rect)) = frame
+      .display_id
+      .and_then(|id| Some((id, self.layout.rect(id)?)))
+    else {
+      return;
+    };
+    let (width, height) = (self.layout.width, self.layout.height);
+    let canvas = self.canvas.get_or_insert_with(|| FrameDataInternal {
+      width,
+      height,
+      stride: width * 4,
+      format: frame.format,
+      data: [0, 0, 0, 255].repeat(width as usize * height as usize),
+      ..Default::default()
+    });
+
+    let size = (frame.width, frame.height);
+    let cursor = frame
+      .cursor
+      .as_ref()
+      .filter(|c| c.x >= 0 && c.y >= 0 && (c.x as u32) < size.0 && (c.y as u32) < size.1)
+      .map(|c| c.scale(size, (rect.width, rect.height)));
+    match cursor {
+      Some(cursor) => {
+        let cursor = CursorInternal {
+          x: cursor.x + rect.x as i32,
+          y: cursor.y + rect.y as i32,
+          ..cursor
+        };
+        self.cursor = Some((id, cursor));
+      }
+      // The pointer left the display that reported it last.
+      None if self.cursor.as_ref().is_some_and(|(last, _)| *last == id) => self.cursor = None,
+      None => {}
+    }
+
+    let frame = pixel::convert(frame, canvas.format, YuvOptions::default());
+    let frame = if size == (rect.width, rect.height) {
+      frame
+    } else {
+      output::scale_frame(&frame, rect.width, rect.height, ScaleFilter::Bilinear)
+    };
+    let row_bytes = rect.width as usize * 4;
+    for y in 0..rect.height as usize {
+      let src = y * frame.stride as usize;
+      let dst = (rect.y as usize + y) * canvas.stride as usize + rect.x as usize * 4;
+      canvas.data[dst..dst + row_bytes].copy_from_slice(&frame.data[src..src + row_bytes]);
+    }
+    canvas.timestamp = canvas.timestamp.max(frame.timestamp);
+    self.painted.insert(id);
+    self.pending = true;
+  }
+
+  /// The stitched frame, when a display frame was painted since the last one and at least
+  /// `interval` passed, so displays updating one after the other yield one frame per interval.
+  /// Nothing is taken before every display was painted once.
+  pub fn take(&mut self, now: Duration, interval: Duration) -> Option<FrameDataInternal> {
+    let due = self.taken.is_none_or(|taken| now >= taken + interval);
+    if !self.pending || !due || self.painted.len() < self.layout.displays.len() {
+      return None;
+    }
+    self.pending = false;
+    self.taken = Some(now);
+    self.frame()
+  }
+
+  /// The part of the stitched frame selected by the region, once a display frame was painted.
+  pub fn frame(&self) -> Option<FrameDataInternal> {
+    let canvas = self.canvas.as_ref()?;
+    let rect = self.region.rect_within(canvas.width, canvas.height);
+    let row_bytes = rect.width as usize * 4;
+    let mut data = Vec::with_capacity(row_bytes * rect.height as usize);
+    for y in rect.y as usize..(rect.y + rect.height) as usize {
+      let start = y * canvas.stride as usize + rect.x as usize * 4;
+      data.extend_from_slice(&canvas.data[start..start + row_bytes]);
+    }
+    let cursor = self.cursor.as_ref().map(|(_, cursor)| CursorInternal {
+      x: cursor.x - rect.x as i32,
+      y: cursor.y - rect.y as i32,
+      ..cursor.clone()
+    });
+    Some(FrameDataInternal {
+      width: rect.width,
+      height: rect.height,
+      stride: rect.width * 4,
+      format: canvas.format,
+      data,
+      timestamp: canvas.timestamp,
+      cursor,
+      ..Default::default()
+    })
+  }
+}
+
+/// Captures several displays in one session with one backend per display. Their frames go to
+/// the same `FrameSink`, tagged with their display ids, or stitched together with `stitch`.
+pub struct MultiDisplayBackend {
+  backends: Vec<Box<dyn CaptureBackendImpl>>,
+  options: CaptureOptions,
+}
+
+impl MultiDisplayBackend {
+  /// `backends` capture the displays of `options.displays`, in the same order.
+  pub fn new(backends: Vec<Box<dyn CaptureBackendImpl>>, options: CaptureOptions) -> Self {
+    Self { backends, options }
+  }
+
+  async fn layout(&mut self) -> Result<Layout> {
+    let displays = self.list_displays().await?;
+    Layout::new(&displays, &self.options.displays)
+  }
+}
+
+impl CaptureBackendImpl for MultiDisplayBackend {
+  fn start<'a>(
+    &'a mut self,
+    sink: Option<FrameSink>,
+  ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+    Box::pin(async move {
+      let sink = match sink {
+        Some(sink) if self.options.stitch => {
+          let stitcher = Stitcher::new(self.layout().await?, self.options.region.clone());
+          Some(sink.stitched(stitcher))
+        }
+        sink => sink,
+      };
+      for started in 0..self.backends.len() {
+        if let Err(e) = self.backends[started].start(sink.clone()).await {
+          for backend in &mut self.backends[..started] {
+            let _ = backend.stop();
+          }
+          return Err(e);
+        }
+      }
+      Ok(())
+    })
+  }
+
+  fn stop(&mut self) -> Result<()> {
+    let mut result = Ok(());
+    for backend in &mut self.backends {
+      result = result.and(backend.stop());
+    }
+    result
+  }
+
+  /// One image of all displays, stitched together whether or not `stitch` is set.
+  fn screenshot<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<FrameDataInternal>> + Send + 'a>> {
+    Box::pin(async move {
+      let mut stitcher = Stitcher::new(self.layout().await?, self.options.region.clone());
+      for (backend, &id) in self.backends.iter_mut().zip(&self.options.displays) {
+        let frame = backend.screenshot().await?;
+        stitcher.paint(FrameDataInternal {
+          display_id: Some(id),
+          ..frame
+        });
+      }
+      stitcher
+        .frame()
+        .ok_or_else(|| Error::new(Status::GenericFailure, "No display found".to_string()))
+    })
+  }
+
+  fn list_displays<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<DisplayInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move {
+      match self.backends.first_mut() {
+        Some(backend) => backend.list_displays().await,
+        None => Ok(Vec::new()),
+      }
+    })
+  }
+
+  fn list_windows<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<WindowInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move {
+      match self.backends.first_mut() {
+        Some(backend) => backend.list_windows().await,
+        None => Ok(Vec::new()),
+      }
+    })
+  }
+}
@@ -0,0 +1,202 @@
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Rgba};
+
+use super::dirty;
+use super::encode::{self, EncodeOptions};
+use super::pixel::{self, PixelFormat, YuvOptions};
+use super::FrameDataInternal;
+use crate::error::Result;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScaleFilter {
+  Nearest,
+  #[default]
+  Bilinear,
+  Lanczos,
+}
+
+/// Requested output size. Unset dimensions follow the source aspect ratio.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScaleOptions {
+  pub width: Option<u32>,
+  pub height: Option<u32>,
+  /// Frames wider than this are scaled down, keeping their aspect ratio.
+  pub max_width: Option<u32>,
+  pub filter: ScaleFilter,
+}
+
+fn mul_div_round(value: u32, num: u32, den: u32) -> u32 {
+  ((value as u64 * num as u64 + den as u64 / 2) / den.max(1) as u64) as u32
+}
+
+impl ScaleOptions {
+  /// Size of the output frame for a `width` x `height` source frame.
+  pub fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+    let (mut w, mut h) = match (self.width, self.height) {
+      (Some(w), Some(h)) => (w, h),
+      (Some(w), None) => (w, mul_div_round(height, w, width)),
+      (None, Some(h)) => (mul_div_round(width, h, height), h),
+      (None, None) => (width, height),
+    };
+    if let Some(max_width) = self.max_width {
+      if w > max_width {
+        h = mul_div_round(h, max_width, w);
+        w = max_width;
+      }
+    }
+    (w.max(1), h.max(1))
+  }
+}
+
+/// Settings applied to every frame before it is delivered.
+#[derive(Clone, Debug, Default)]
+pub struct OutputOptions {
+  pub scale: ScaleOptions,
+  pub pixel_format: PixelFormat,
+  pub yuv: YuvOptions,
+  /// Compress frames instead of delivering raw pixels; `pixel_format` is then unused.
+  pub encode: Option<EncodeOptions>,
+}
+
+impl OutputOptions {
+  /// Scales the frame to the requested output size. Empty frames are returned unchanged.
+  pub fn resize(&self, frame: FrameDataInternal) -> FrameDataInternal {
+    if frame.width == 0 || frame.height == 0 {
+      return frame;
+    }
+    let (width, height) = self.scale.output_size(frame.width, frame.height);
+    if (width, height) == (frame.width, frame.height) {
+      return frame;
+    }
+    scale_frame(&frame, width, height, self.scale.filter)
+  }
+
+  /// Scales the frame, then encodes it or converts it to the requested pixel format.
+  pub fn process(&self, frame: FrameDataInternal) -> Result<FrameDataInternal> {
+    self.convert(self.resize(frame))
+  }
+
+  /// Encodes a scaled frame or converts it to the requested pixel format.
+  pub fn convert(&self, frame: FrameDataInternal) -> Result<FrameDataInternal> {
+    match self.encode {
+      Some(options) => encode::encode(frame, options),
+      None => Ok(pixel::convert(frame, self.pixel_format, self.yuv)),
+    }
+  }
+}
+
+/// Resizes an RGBA or BGRA frame to `width` x `height`. An empty frame has no pixels to sample
+/// and is returned unchanged.
+pub fn scale_frame(
+  frame: &FrameDataInternal,
+  width: u32,
+  height: u32,
+  filter: ScaleFilter,
+) -> FrameDataInternal {
+  if frame.width == 0 || frame.height == 0 {
+    return frame.clone();
+  }
+  let data = match filter {
+    ScaleFilter::Nearest => scale_nearest(frame, width, height),
+    ScaleFilter::Bilinear => scale_bilinear(frame, width, height),
+    ScaleFilter::Lanczos => {
+      let src = ImageBuffer::<Rgba<u8>, _>::from_raw(frame.width, frame.height, compact(frame))
+        .expect("frame buffer matches its size");
+      imageops::resize(&src, width, height, FilterType::Lanczos3).into_raw()
+    }
+  };
+
+  let dirty_rects = frame
+    .dirty_rects
+    .as_ref()
+    .map(|rects| dirty::scale_rects(rects, (frame.width, frame.height), (width, height), filter));
+  let cursor = frame
+    .cursor
+    .as_ref()
+    .map(|cursor| cursor.scale((frame.width, frame.height), (width, height)));
+
+  FrameDataInternal {
+    width,
+    height,
+    stride: width * 4,
+    format: frame.format,
+    encoding: None,
+    data,
+    dirty_rects,
+    cursor,
+    ..*frame
+  }
+}
+
+/// Pixel rows without stride padding.
+fn compact(frame: &FrameDataInternal) -> Vec<u8> {
+  let row = frame.width as usize * 4;
+  if frame.stride as usize == row {
+    return frame.data[..row * frame.height as usize].to_vec();
+  }
+  frame
+    .data
+    .chunks(frame.stride as usize)
+    .take(frame.height as usize)
+    .flat_map(|r| &r[..row])
+    .copied()
+    .collect()
+}
+
+/// Source pixel under the center of output pixel `i`.
+fn nearest_index(i: u32, dst: u32, src: u32) -> usize {
+  (((i as u64 * 2 + 1) * src as u64) / (dst as u64 * 2)) as usize
+}
+
+/// The two source pixels around the center of output pixel `i`, and the
+/// weight of the second one out of 256.
+fn bilinear_taps(i: u32, dst: u32, src: u32) -> (usize, usize, u32) {
+  // 16.16 fixed point, shifted by half a pixel so weights are measured between pixel centers.
+  let pos = ((i as u64 * 2 + 1) * src as u64 * 0x8000 / dst as u64).saturating_sub(0x8000);
+  let last = src as usize - 1;
+  let i0 = ((pos >> 16) as usize).min(last);
+  let i1 = (i0 + 1).min(last);
+  (i0, i1, ((pos & 0xffff) >> 8) as u32)
+}
+
+fn scale_nearest(frame: &FrameDataInternal, width: u32, height: u32) -> Vec<u8> {
+  let columns: Vec<usize> = (0..width)
+    .map(|x| nearest_index(x, width, frame.width) * 4)
+    .collect();
+
+  let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+  for y in 0..height {
+    let row_start = nearest_index(y, height, frame.height) * frame.stride as usize;
+    let row = &frame.data[row_start..];
+    for &x in &columns {
+      data.extend_from_slice(&row[x..x + 4]);
+    }
+  }
+  data
+}
+
+fn scale_bilinear(frame: &FrameDataInternal, width: u32, height: u32) -> Vec<u8> {
+  let stride = frame.stride as usize;
+  let row = frame.width as usize * 4;
+  let columns: Vec<(usize, usize, u32)> = (0..width)
+    .map(|x| {
+      let (x0, x1, fx) = bilinear_taps(x, width, frame.width);
+      (x0 * 4, x1 * 4, fx)
+    })
+    .collect();
+
+  let mut data = vec![0; width as usize * height as usize * 4];
+  for (y, out) in data.chunks_exact_mut(width as usize * 4).enumerate() {
+    let (y0, y1, fy) = bilinear_taps(y as u32, height, frame.height);
+    let top = &frame.data[y0 * stride..y0 * stride + row];
+    let bottom = &frame.data[y1 * stride..y1 * stride + row];
+    for (px, &(x0, x1, fx)) in out.chunks_exact_mut(4).zip(&columns) {
+      for c in 0..4 {
+        let t = top[x0 + c] as u32 * (256 - fx) + top[x1 + c] as u32 * fx;
+        let b = bottom[x0 + c] as u32 * (256 - fx) + bottom[x1 + c] as u32 * fx;
+        px[c] = ((t * (256 - fy) + b * fy + 0x8000) >> 16) as u8;
+      }
+    }
+  }
+  data
+}
@@ -0,0 +1,194 @@
+use super::FrameDataInternal;
+
+/// Layout of `FrameDataInternal::data`.
+///
+/// Packed formats store `stride` bytes per row. Planar formats store a full size Y plane
+/// (`stride` bytes per row) followed by the chroma planes at half the width and height,
+/// rounded up: U then V for `I420`, a single interleaved UV plane for `Nv12`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+  #[default]
+  Rgba,
+  Bgra,
+  Rgb,
+  I420,
+  Nv12,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMatrix {
+  #[default]
+  Bt601,
+  Bt709,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorRange {
+  #[default]
+  Limited,
+  Full,
+}
+
+/// How RGB frames are converted to `I420` and `Nv12`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct YuvOptions {
+  pub matrix: ColorMatrix,
+  pub range: ColorRange,
+}
+
+/// RGB to YUV coefficients in 16.16 fixed point.
+struct Coefficients {
+  y: [i32; 3],
+  u: [i32; 3],
+  v: [i32; 3],
+  y_offset: i32,
+}
+
+impl Coefficients {
+  fn new(options: YuvOptions) -> Self {
+    let (kr, kb) = match options.matrix {
+      ColorMatrix::Bt601 => (0.299, 0.114),
+      ColorMatrix::Bt709 => (0.2126, 0.0722),
+    };
+    let kg = 1.0 - kr - kb;
+    let (y_scale, c_scale, y_offset) = match options.range {
+      ColorRange::Limited => (219.0 / 255.0, 224.0 / 255.0, 16),
+      ColorRange::Full => (1.0, 1.0, 0),
+    };
+    let fixed = |value: f64| (value * 65536.0).round() as i32;
+    let u = c_scale / (2.0 * (1.0 - kb));
+    let v = c_scale / (2.0 * (1.0 - kr));
+
+    Self {
+      y: [
+        fixed(kr * y_scale),
+        fixed(kg * y_scale),
+        fixed(kb * y_scale),
+      ],
+      u: [fixed(-kr * u), fixed(-kg * u), fixed((1.0 - kb) * u)],
+      v: [fixed((1.0 - kr) * v), fixed(-kg * v), fixed(-kb * v)],
+      y_offset,
+    }
+  }
+
+  fn apply(k: &[i32; 3], [r, g, b]: [i32; 3], offset: i32) -> u8 {
+    (((k[0] * r + k[1] * g + k[2] * b + 0x8000) >> 16) + offset).clamp(0, 255) as u8
+  }
+
+  fn luma(&self, rgb: [i32; 3]) -> u8 {
+    Self::apply(&self.y, rgb, self.y_offset)
+  }
+
+  fn chroma(&self, rgb: [i32; 3]) -> (u8, u8) {
+    (
+      Self::apply(&self.u, rgb, 128),
+      Self::apply(&self.v, rgb, 128),
+    )
+  }
+}
+
+/// Converts an `Rgba` or `Bgra` frame to `format`. Frames already in `format` are returned as is.
+pub fn convert(
+  frame: FrameDataInternal,
+  format: PixelFormat,
+  yuv: YuvOptions,
+) -> FrameDataInternal {
+  if frame.format == format {
+    return frame;
+  }
+  // Offset of the red channel; blue sits on the other side of green.
+  let red = match frame.format {
+    PixelFormat::Rgba => 0,
+    PixelFormat::Bgra => 2,
+    // Backends only produce packed 4-byte frames.
+    _ => return frame,
+  };
+
+  let width = frame.width as usize;
+  let (data, stride) = match format {
+    // The other 4-byte order: swap red and blue.
+    PixelFormat::Rgba | PixelFormat::Bgra => (repack(&frame, [2, 1, 0, 3]), width * 4),
+    PixelFormat::Rgb => (repack(&frame, [red, 1, 2 - red]), width * 3),
+    PixelFormat::I420 | PixelFormat::Nv12 => (
+      to_yuv(
+        &frame,
+        red,
+        format == PixelFormat::Nv12,
+        &Coefficients::new(yuv),
+      ),
+      width,
+    ),
+  };
+
+  FrameDataInternal {
+    width: frame.width,
+    height: frame.height,
+    stride: stride as u32,
+    format,
+    encoding: None,
+    data,
+    ..frame
+  }
+}
+
+/// Compact rows made of the source channels at `order`.
+fn repack<const N: usize>(frame: &FrameDataInternal, order: [usize; N]) -> Vec<u8> {
+  let width = frame.width as usize;
+  let mut data = Vec::with_capacity(width * frame.height as usize * N);
+  for row in frame
+    .data
+    .chunks(frame.stride as usize)
+    .take(frame.height as usize)
+  {
+    for px in row[..width * 4].chunks_exact(4) {
+      data.extend(order.map(|i| px[i]));
+    }
+  }
+  data
+}
+
+fn to_yuv(frame: &FrameDataInternal, red: usize, interleaved: bool, k: &Coefficients) -> Vec<u8> {
+  let (width, height) = (frame.width as usize, frame.height as usize);
+  let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+  let chroma_size = chroma_width * chroma_height;
+  let stride = frame.stride as usize;
+  let rgb = |x: usize, y: usize| {
+    let px = &frame.data[y * stride + x * 4..];
+    [px[red] as i32, px[1] as i32, px[2 - red] as i32]
+  };
+
+  let mut data = vec![0; width * height + chroma_size * 2];
+  let (luma, chroma) = data.split_at_mut(width * height);
+  for (y, row) in luma.chunks_exact_mut(width).enumerate() {
+    for (x, out) in row.iter_mut().enumerate() {
+      *out = k.luma(rgb(x, y));
+    }
+  }
+
+  // Each chroma sample covers a 2x2 block, or what is left of it at odd edges.
+  for cy in 0..chroma_height {
+    for cx in 0..chroma_width {
+      let mut sum = [0; 3];
+      let mut count = 0;
+      for y in cy * 2..(cy * 2 + 2).min(height) {
+        for x in cx * 2..(cx * 2 + 2).min(width) {
+          let px = rgb(x, y);
+          for c in 0..3 {
+            sum[c] += px[c];
+          }
+          count += 1;
+        }
+      }
+      let (u, v) = k.chroma(sum.map(|s| (s + count / 2) / count));
+      let i = cy * chroma_width + cx;
+      if interleaved {
+        chroma[i * 2] = u;
+        chroma[i * 2 + 1] = v;
+      } else {
+        chroma[i] = u;
+        chroma[chroma_size + i] = v;
+      }
+    }
+  }
+  data
+}
@@ -0,0 +1,277 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::events::{ErrorCode, EventSink};
+use super::output::{self, ScaleFilter};
+use super::pixel::{self, PixelFormat, YuvOptions};
+use super::stream::FrameStream;
+use super::FrameDataInternal;
+use crate::error::{Error, Result, Status};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordingFormat {
+  /// YUV4MPEG2 with 4:2:0 frames at a constant frame rate, readable by ffmpeg and most players.
+  #[default]
+  Y4m,
+  /// Frames exactly as captured, with an `.idx` text file listing their timing and layout.
+  Raw,
+}
+
+impl RecordingFormat {
+  /// `Y4m` for `.y4m` files, `Raw` otherwise.
+  pub fn from_path(path: &Path) -> Self {
+    match path.extension() {
+      Some(ext) if ext.eq_ignore_ascii_case("y4m") => Self::Y4m,
+      _ => Self::Raw,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RecordingOptions {
+  pub format: RecordingFormat,
+  /// Frame rate of Y4M files. Raw files keep the capture timestamps instead.
+  pub fps: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RecordingSummary {
+  /// Frames written to the file, repeated frames included.
+  pub frames: u64,
+  /// Frames written again to fill gaps in the Y4M timeline.
+  pub repeated_frames: u64,
+  /// Frames left out because another frame already filled their Y4M time slot.
+  pub skipped_frames: u64,
+  /// Play time of Y4M files; time from the first to the last frame of raw files.
+  pub duration: Duration,
+}
+
+/// Y4M timeline: frame `n` is shown from `n / fps` seconds after the first frame.
+struct Y4m {
+  fps: u32,
+  /// Size of the first frame; later frames are scaled to it.
+  size: (u32, u32),
+  /// The last frame written, as I420 planes, repeated to fill gaps.
+  last: Vec<u8>,
+  next_slot: u64,
+}
+
+/// Raw frames and their index.
+struct Raw {
+  index: BufWriter<File>,
+  offset: u64,
+}
+
+enum Writer {
+  Y4m(Y4m),
+  Raw(Raw),
+}
+
+/// Writes frames to a recording file, timed by their capture timestamps.
+pub struct Recorder {
+  file: BufWriter<File>,
+  writer: Writer,
+  /// Timestamp of the first frame, where the recording starts.
+  start: Option<Duration>,
+  summary: RecordingSummary,
+}
+
+impl Recorder {
+  /// Creates the file at `path`, and `path` + `.idx` for raw recordings.
+  pub fn create(path: impl AsRef<Path>, options: RecordingOptions) -> Result<Self> {
+    let path = path.as_ref();
+    let create = |path: &Path| {
+      File::create(path).map(BufWriter::new).map_err(|e| {
+        Error::new(
+          Status::GenericFailure,
+          format!("Failed to create recording file {}: {}", path.display(), e),
+        )
+      })
+    };
+    let writer = match options.format {
+      RecordingFormat::Y4m => {
+        if options.fps == 0 {
+          return Err(Error::new(
+            Status::InvalidArg,
+            "fps must be greater than 0".to_string(),
+          ));
+        }
+        Writer::Y4m(Y4m {
+          fps: options.fps,
+          size: (0, 0),
+          last: Vec::new(),
+          next_slot: 0,
+        })
+      }
+      RecordingFormat::Raw => {
+        let mut index = create(&index_path(path))?;
+        writeln!(index, "# rs-capture raw index v1").map_err(write_failed)?;
+        writeln!(
+          index,
+          "# sequence timestamp_us offset length width height stride format"
+        )
+        .map_err(write_failed)?;
+        Writer::Raw(Raw { index, offset: 0 })
+      }
+    };
+
+    Ok(Self {
+      file: create(path)?,
+      writer,
+      start: None,
+      summary: RecordingSummary::default(),
+    })
+  }
+
+  /// Appends an `Rgba` or `Bgra` frame.
+  pub fn write(&mut self, frame: &FrameDataInternal) -> Result<()> {
+    if frame.encoding.is_some() || !matches!(frame.format, PixelFormat::Rgba | PixelFormat::Bgra) {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "Recordings need RGBA or BGRA frames".to_string(),
+      ));
+    }
+    let start = *self.start.get_or_insert(frame.timestamp);
+    let elapsed = frame.timestamp.saturating_sub(start);
+    let summary = &mut self.summary;
+
+    match &mut self.writer {
+      Writer::Y4m(y4m) => {
+        if summary.frames == 0 {
+          y4m.size = (frame.width, frame.height);
+          writeln!(
+            self.file,
+            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg XCOLORRANGE=LIMITED",
+            frame.width, frame.height, y4m.fps
+          )
+          .map_err(write_failed)?;
+        }
+        let slot = (elapsed.as_secs_f64() * y4m.fps as f64).round() as u64;
+        if slot < y4m.next_slot {
+          summary.skipped_frames += 1;
+          return Ok(());
+        }
+        // The previous frame stays on screen until this one was captured.
+        while y4m.next_slot < slot {
+          write_y4m_frame(&mut self.file, &y4m.last)?;
+          y4m.next_slot += 1;
+          summary.frames += 1;
+          summary.repeated_frames += 1;
+        }
+
+        let (width, height) = y4m.size;
+        let yuv = if (frame.width, frame.height) == (width, height) {
+          pixel::convert(frame.clone(), PixelFormat::I420, YuvOptions::default())
+        } else {
+          let scaled = output::scale_frame(frame, width, height, ScaleFilter::Bilinear);
+          pixel::convert(scaled, PixelFormat::I420, YuvOptions::default())
+        };
+        write_y4m_frame(&mut self.file, &yuv.data)?;
+        y4m.last = yuv.data;
+        y4m.next_slot += 1;
+        summary.frames += 1;
+        summary.duration = Duration::from_secs(y4m.next_slot) / y4m.fps;
+      }
+      Writer::Raw(raw) => {
+        let length = frame.data.len() as u64;
+        self.file.write_all(&frame.data).map_err(write_failed)?;
+        writeln!(
+          raw.index,
+          "{} {} {} {} {} {} {} {}",
+          frame.sequence,
+          elapsed.as_micros(),
+          raw.offset,
+          length,
+          frame.width,
+          frame.height,
+          frame.stride,
+          if frame.format == PixelFormat::Bgra {
+            "BGRA"
+          } else {
+            "RGBA"
+          }
+        )
+        .map_err(write_failed)?;
+        raw.offset += length;
+        summary.frames += 1;
+        summary.duration = elapsed;
+      }
+    }
+    Ok(())
+  }
+
+  /// Flushes the files and returns what was written.
+  pub fn finish(mut self) -> Result<RecordingSummary> {
+    self.file.flush().map_err(write_failed)?;
+    if let Writer::Raw(raw) = &mut self.writer {
+      raw.index.flush().map_err(write_failed)?;
+    }
+    Ok(self.summary)
+  }
+}
+
+/// Index file of a raw recording.
+pub fn index_path(path: &Path) -> PathBuf {
+  let mut index = path.as_os_str().to_owned();
+  index.push(".idx");
+  PathBuf::from(index)
+}
+
+fn write_y4m_frame(file: &mut impl Write, planes: &[u8]) -> Result<()> {
+  file
+    .write_all(b"FRAME\n")
+    .and_then(|()| file.write_all(planes))
+    .map_err(write_failed)
+}
+
+fn write_failed(e: std::io::Error) -> Error {
+  Error::new(
+    Status::GenericFailure,
+    format!("Failed to write recording: {}", e),
+  )
+}
+
+/// Records a frame stream on its own thread until `stop` or the end of the session.
+pub struct Recording {
+  stream: Arc<FrameStream>,
+  thread: JoinHandle<Result<RecordingSummary>>,
+}
+
+impl Recording {
+  /// Starts writing `stream` to `recorder`. Write errors are reported to `events` as warnings
+  /// and end the recording, not the capture session.
+  pub fn start(stream: FrameStream, mut recorder: Recorder, events: EventSink) -> Self {
+    let stream = Arc::new(stream);
+    let frames = stream.clone();
+    let thread = thread::spawn(move || {
+      let result = (|| {
+        while let Some(frame) = frames.blocking_next() {
+          recorder.write(&frame)?;
+        }
+        recorder.finish()
+      })();
+      if let Err(e) = &result {
+        // Stop queuing frames nobody reads, which would otherwise block a `Block` session.
+        frames.close();
+        events.warn(ErrorCode::RecordingFailed, e.reason.clone());
+      }
+      result
+    });
+    Self { stream, thread }
+  }
+
+  /// Writes the frames still queued, closes the files and returns what was written.
+  pub fn stop(self) -> Result<RecordingSummary> {
+    self.stream.close();
+    self.thread.join().unwrap_or_else(|_| {
+      Err(Error::new(
+        Status::GenericFailure,
+        "Recording thread panicked".to_string(),
+      ))
+    })
+  }
+}
@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use super::encode::{self, EncodeOptions, ImageFormat};
+use super::FrameDataInternal;
+use crate::error::{Error, Result, Status};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ReplayOptions {
+  /// Frames older than this, measured from the newest frame, are evicted.
+  pub duration: Duration,
+  /// Frames are evicted, oldest first, while the buffer holds more pixel data than this.
+  pub max_bytes: usize,
+  /// Store frames as JPEG at this quality, trading CPU time on the capture thread for memory.
+  pub jpeg_quality: Option<u8>,
+}
+
+impl ReplayOptions {
+  pub const DEFAULT_MAX_BYTES: usize = 256 << 20;
+
+  pub fn validate(&self) -> Result<()> {
+    let invalid = |reason: &str| Err(Error::new(Status::InvalidArg, reason.to_string()));
+    if self.duration.is_zero() {
+      return invalid("durationMs must be greater than 0");
+    }
+    if self.max_bytes == 0 {
+      return invalid("maxBytes must be greater than 0");
+    }
+    if self
+      .jpeg_quality
+      .is_some_and(|quality| !(1..=100).contains(&quality))
+    {
+      return invalid("jpegQuality must be between 1 and 100");
+    }
+    Ok(())
+  }
+}
+
+#[derive(Default)]
+struct Frames {
+  frames: VecDeque<Arc<FrameDataInternal>>,
+  bytes: usize,
+}
+
+/// The last frames of a session, kept in memory for `saveReplay`. Fed by `FrameSink` with
+/// scaled frames, before pixel format conversion and encoding.
+#[derive(Clone)]
+pub struct ReplayBuffer {
+  options: ReplayOptions,
+  frames: Arc<StdMutex<Frames>>,
+}
+
+impl ReplayBuffer {
+  pub fn new(options: ReplayOptions) -> Self {
+    Self {
+      options,
+      frames: Arc::default(),
+    }
+  }
+
+  /// Adds an `Rgba` or `Bgra` frame, compressing it first if configured, and evicts the frames
+  /// that no longer fit. The newest frame is always kept.
+  pub fn push(&self, frame: FrameDataInternal) -> Result<()> {
+    let frame = match self.options.jpeg_quality {
+      Some(quality) => encode::encode(
+        frame,
+        EncodeOptions {
+          format: ImageFormat::Jpeg,
+          quality,
+        },
+      )?,
+      None => frame,
+    };
+
+    let mut frames = self.frames.lock().unwrap();
+    let oldest_kept = frame.timestamp.saturating_sub(self.options.duration);
+    frames.bytes += frame.data.len();
+    frames.frames.push_back(Arc::new(frame));
+    while frames.frames.len() > 1 {
+      let oldest = &frames.frames[0];
+      if oldest.timestamp >= oldest_kept && frames.bytes <= self.options.max_bytes {
+        break;
+      }
+      frames.bytes -= oldest.data.len();
+      frames.frames.pop_front();
+    }
+    Ok(())
+  }
+
+  /// The buffered frames, oldest first. JPEG frames stay compressed. The frames are shared with
+  /// the buffer, so this only holds the lock to copy pointers.
+  pub fn frames(&self) -> Vec<Arc<FrameDataInternal>> {
+    self.frames.lock().unwrap().frames.iter().cloned().collect()
+  }
+
+  pub fn clear(&self) {
+    *self.frames.lock().unwrap() = Frames::default();
+  }
+}
@@ -0,0 +1,259 @@
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex as StdMutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+use super::dirty;
+use super::{block_on, block_on_until, FrameDataInternal, RegionInternal};
+
+/// What a full queue does with the next frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backpressure {
+  /// Discard the oldest queued frame to make room.
+  #[default]
+  DropOldest,
+  /// Discard the new frame.
+  DropNewest,
+  /// Make the capture loop wait until the consumer catches up.
+  Block,
+}
+
+/// Queue settings shared by the frame callback and every `FrameStream`.
+#[derive(Clone, Copy, Debug)]
+pub struct QueueOptions {
+  pub max_queued_frames: usize,
+  pub backpressure: Backpressure,
+}
+
+impl QueueOptions {
+  pub const DEFAULT_MAX_QUEUED_FRAMES: usize = 4;
+}
+
+impl Default for QueueOptions {
+  fn default() -> Self {
+    Self {
+      max_queued_frames: Self::DEFAULT_MAX_QUEUED_FRAMES,
+      backpressure: Backpressure::default(),
+    }
+  }
+}
+
+/// Outcome of `FrameQueue::push`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Push {
+  Queued,
+  /// Queued after dropping the oldest frame.
+  Replaced,
+  /// The new frame was dropped.
+  Dropped,
+  Closed,
+}
+
+struct QueueState {
+  frames: VecDeque<FrameDataInternal>,
+  closed: bool,
+  wakers: Vec<Waker>,
+  /// Frames dropped since the last queued one, added to the next frame's `dropped_since_last`.
+  pending_drops: u64,
+  /// Dirty rectangles of those frames, added to the next frame's `dirty_rects`.
+  pending_dirty: Vec<RegionInternal>,
+  /// The consumer has not seen a frame of this session yet, so the next one is dirty as a whole.
+  resync: bool,
+  /// Id of the last pointer shape handed out, so frames only carry a shape when it changed.
+  cursor_shape: Option<u64>,
+}
+
+impl QueueState {
+  fn pop_front(&mut self) -> Option<FrameDataInternal> {
+    let mut frame = self.frames.pop_front()?;
+    if let Some(cursor) = &mut frame.cursor {
+      let id = cursor.shape_id();
+      cursor.shape_changed = id.is_some() && id != self.cursor_shape;
+      if id.is_some() {
+        self.cursor_shape = id;
+      }
+    }
+    Some(frame)
+  }
+}
+
+/// Bounded queue between a capture loop and one consumer.
+pub struct FrameQueue {
+  state: StdMutex<QueueState>,
+  space: Condvar,
+  options: QueueOptions,
+}
+
+impl FrameQueue {
+  pub fn new(options: QueueOptions) -> Arc<Self> {
+    let options = QueueOptions {
+      max_queued_frames: options.max_queued_frames.max(1),
+      ..options
+    };
+    Arc::new(Self {
+      state: StdMutex::new(QueueState {
+        frames: VecDeque::with_capacity(options.max_queued_frames),
+        closed: false,
+        wakers: Vec::new(),
+        pending_drops: 0,
+        pending_dirty: Vec::new(),
+        resync: true,
+        cursor_shape: None,
+      }),
+      space: Condvar::new(),
+      options,
+    })
+  }
+
+  /// Queues a frame, applying the backpressure policy when the queue is full.
+  /// `Block` waits on the capture thread until there is room or the queue is closed.
+  ///
+  /// Dropped frames are reported in `dropped_since_last` of the next frame the consumer receives,
+  /// and their dirty rectangles are added to its `dirty_rects`.
+  pub fn push(&self, mut frame: FrameDataInternal) -> Push {
+    let mut state = self.state.lock().unwrap();
+    let full = |state: &QueueState| state.frames.len() >= self.options.max_queued_frames;
+    let mut push = Push::Queued;
+    if full(&state) && !state.closed {
+      match self.options.backpressure {
+        Backpressure::DropOldest => {
+          if let Some(oldest) = state.frames.pop_front() {
+            let lost = oldest.dropped_since_last + 1;
+            let dirty_rects = oldest.dirty_rects.unwrap_or_default();
+            match state.frames.front_mut() {
+              Some(next) => {
+                next.dropped_since_last += lost;
+                dirty::merge(next, dirty_rects);
+              }
+              None => {
+                state.pending_drops += lost;
+                state.pending_dirty.extend(dirty_rects);
+              }
+            }
+          }
+          push = Push::Replaced;
+        }
+        Backpressure::DropNewest => {
+          state.pending_drops += frame.dropped_since_last + 1;
+          state
+            .pending_dirty
+            .extend(frame.dirty_rects.unwrap_or_default());
+          return Push::Dropped;
+        }
+        Backpressure::Block => {
+          while full(&state) && !state.closed {
+            state = self.space.wait(state).unwrap();
+          }
+        }
+      }
+    }
+    if state.closed {
+      return Push::Closed;
+    }
+    frame.dropped_since_last += std::mem::take(&mut state.pending_drops);
+    dirty::merge(&mut frame, std::mem::take(&mut state.pending_dirty));
+    if std::mem::take(&mut state.resync) && frame.dirty_rects.is_some() {
+      frame.dirty_rects = Some(vec![RegionInternal::full(frame.width, frame.height)]);
+    }
+    state.frames.push_back(frame);
+    state.wakers.drain(..).for_each(Waker::wake);
+    push
+  }
+
+  /// Takes the oldest frame without waiting.
+  pub fn pop(&self) -> Option<FrameDataInternal> {
+    let frame = self.state.lock().unwrap().pop_front();
+    if frame.is_some() {
+      self.space.notify_one();
+    }
+    frame
+  }
+
+  /// Ends the stream. Frames already queued are still handed out and blocked producers return.
+  pub fn close(&self) {
+    let mut state = self.state.lock().unwrap();
+    state.closed = true;
+    state.wakers.drain(..).for_each(Waker::wake);
+    self.space.notify_all();
+  }
+
+  /// Accepts frames again after `close`.
+  pub fn reopen(&self) {
+    let mut state = self.state.lock().unwrap();
+    state.closed = false;
+    state.pending_drops = 0;
+    state.pending_dirty.clear();
+    state.resync = true;
+    state.cursor_shape = None;
+  }
+
+  pub fn is_closed(&self) -> bool {
+    self.state.lock().unwrap().closed
+  }
+
+  fn poll_pop(&self, cx: &mut Context<'_>) -> Poll<Option<FrameDataInternal>> {
+    let mut state = self.state.lock().unwrap();
+    if let Some(frame) = state.pop_front() {
+      self.space.notify_one();
+      return Poll::Ready(Some(frame));
+    }
+    if state.closed {
+      return Poll::Ready(None);
+    }
+    if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+      state.wakers.push(cx.waker().clone());
+    }
+    Poll::Pending
+  }
+}
+
+/// Frames of a capture session, in order. Ends when the session is stopped.
+///
+/// A consumer that falls behind is handled by the session's `Backpressure` policy.
+pub struct FrameStream {
+  queue: Arc<FrameQueue>,
+}
+
+impl FrameStream {
+  pub fn new(queue: Arc<FrameQueue>) -> Self {
+    Self { queue }
+  }
+
+  /// Waits for the next frame, or `None` once the stream has ended.
+  pub fn next_frame(&self) -> impl Future<Output = Option<FrameDataInternal>> + Send + 'static {
+    let queue = self.queue.clone();
+    async move { poll_fn(|cx| queue.poll_pop(cx)).await }
+  }
+
+  /// Blocks the calling thread until the next frame, or returns `None` once the stream has ended.
+  pub fn blocking_next(&self) -> Option<FrameDataInternal> {
+    block_on(self.next_frame())
+  }
+
+  /// Like `blocking_next`, but also returns `None` once `deadline` has passed.
+  pub fn blocking_next_until(&self, deadline: Instant) -> Option<FrameDataInternal> {
+    block_on_until(self.next_frame(), deadline).flatten()
+  }
+
+  /// Ends the stream early and releases the capture loop.
+  pub fn close(&self) {
+    self.queue.close();
+  }
+}
+
+impl Stream for FrameStream {
+  type Item = FrameDataInternal;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    self.queue.poll_pop(cx)
+  }
+}
+
+impl Drop for FrameStream {
+  fn drop(&mut self) {
+    self.queue.close();
+  }
+}
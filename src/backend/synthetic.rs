@@ -0,0 +1,463 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::Instant;
+
+use super::cursor::{CursorInternal, CursorShape};
+use super::events::ErrorCode;
+use super::pixel::PixelFormat;
+use super::topology::{Change, DisplayWatch};
+use super::{
+  display_not_found, host_time, window_not_found, CaptureBackendImpl, CaptureOptions,
+  DisplayInfoInternal, FrameDataInternal, FrameSink, RegionInternal, SharedSettings,
+  WindowInfoInternal,
+};
+use crate::error::{Error, Result, Status};
+
+/// Colors of the bars, left to right, as RGBA.
+pub const BAR_COLORS: [[u8; 4]; 8] = [
+  [255, 255, 255, 255], // White
+  [255, 255, 0, 255],   // Yellow
+  [0, 255, 255, 255],   // Cyan
+  [0, 255, 0, 255],     // Green
+  [255, 0, 255, 255],   // Magenta
+  [255, 0, 0, 255],     // Red
+  [0, 0, 255, 255],     // Blue
+  [0, 0, 0, 255],       // Black
+];
+
+/// Horizontal distance in pixels the bars move between two consecutive frames.
+pub const BAR_STEP: u32 = 4;
+
+/// Frames the simulated pointer keeps a shape before switching to the other one.
+pub const CURSOR_SHAPE_FRAMES: u64 = 10;
+
+/// The simulated pointer is hidden for the last `CURSOR_HIDDEN_FRAMES` of every
+/// `CURSOR_PERIOD` frames.
+pub const CURSOR_PERIOD: u64 = 30;
+pub const CURSOR_HIDDEN_FRAMES: u64 = 5;
+
+#[derive(Clone, Debug)]
+pub struct SyntheticOptions {
+  pub width: u32,
+  pub height: u32,
+  /// Number of displays to report. They are laid out left to right with ids starting at 1.
+  pub displays: u32,
+  /// Number of windows to report, each half the display size and cascaded from the top-left corner.
+  pub windows: u32,
+  /// Make `start` fail immediately.
+  pub fail_on_start: bool,
+  /// Make `screenshot` fail immediately.
+  pub fail_screenshot: bool,
+  /// End the capture loop after this many frames, as if the display went away.
+  pub fail_after_frames: Option<u64>,
+  /// Frame `n` shows the image of frame `n - n % hold_frames`, so the image only changes
+  /// every `hold_frames` frames.
+  pub hold_frames: u64,
+  /// Simulate a pointer moving over the target; see `pointer`.
+  pub cursor: bool,
+  /// Swap the width and height of every display from this frame on, as if they were rotated.
+  pub rotate_after_frames: Option<u64>,
+  /// Report one more display from this frame on, as if it was connected.
+  pub connect_after_frames: Option<u64>,
+}
+
+impl Default for SyntheticOptions {
+  fn default() -> Self {
+    Self {
+      width: 640,
+      height: 480,
+      displays: 1,
+      windows: 0,
+      fail_on_start: false,
+      fail_screenshot: false,
+      fail_after_frames: None,
+      hold_frames: 1,
+      cursor: false,
+      rotate_after_frames: None,
+      connect_after_frames: None,
+    }
+  }
+}
+
+impl SyntheticOptions {
+  /// Displays reported while frame `index` is captured. They are laid out left to right with
+  /// ids starting at 1.
+  pub fn displays_at(&self, index: u64) -> Vec<DisplayInfoInternal> {
+    let reached = |frames: Option<u64>| frames.is_some_and(|frames| index >= frames);
+    let (width, height) = if reached(self.rotate_after_frames) {
+      (self.height, self.width)
+    } else {
+      (self.width, self.height)
+    };
+    let count = self.displays.max(1) + reached(self.connect_after_frames) as u32;
+    (0..count)
+      .map(|i| DisplayInfoInternal {
+        id: i + 1,
+        name: format!("Synthetic {}", i + 1),
+        x: (i * width) as i32,
+        y: 0,
+        width,
+        height,
+        scale_factor: 1.0,
+        rotation: 0.0,
+        is_primary: i == 0,
+      })
+      .collect()
+  }
+
+  /// Index of the image shown by frame `index`.
+  fn held(&self, index: u64) -> u64 {
+    index - index % self.hold_frames.max(1)
+  }
+
+  /// Frame `index` of a `size` target, with the simulated pointer when the `cursor` option is set.
+  /// The pointer keeps moving while the image is held.
+  fn render(
+    &self,
+    index: u64,
+    pattern_index: u32,
+    size: (u32, u32),
+    region: RegionInternal,
+    hide_cursor: bool,
+  ) -> FrameDataInternal {
+    let cursor = self.cursor.then(|| pointer(index, size.0, size.1, region));
+    let drawn = cursor.as_ref().filter(|_| !hide_cursor);
+    let mut frame = render_frame(size.0, pattern_index, self.held(index), region, drawn);
+    frame.cursor = cursor;
+    frame
+  }
+}
+
+/// The two shapes of the simulated pointer: an 8x8 arrow pointing at its top-left pixel (id 1)
+/// and a 5x5 square centered on the pointer (id 2). Both have a black outline and white inside.
+fn cursor_shapes() -> &'static [Arc<CursorShape>; 2] {
+  static SHAPES: OnceLock<[Arc<CursorShape>; 2]> = OnceLock::new();
+  SHAPES.get_or_init(|| {
+    let shape = |id, size: u32, hotspot, pixel: fn(u32, u32) -> Option<bool>| {
+      let data = (0..size * size)
+        .flat_map(|i| match pixel(i % size, i / size) {
+          Some(true) => [0, 0, 0, 255],
+          Some(false) => [255, 255, 255, 255],
+          None => [0, 0, 0, 0],
+        })
+        .collect();
+      Arc::new(CursorShape {
+        id,
+        width: size,
+        height: size,
+        hotspot_x: hotspot,
+        hotspot_y: hotspot,
+        data,
+      })
+    };
+    [
+      shape(1, 8, 0, |x, y| {
+        (x <= y).then_some(x == 0 || x == y || y == 7)
+      }),
+      shape(2, 5, 2, |x, y| Some(x % 4 == 0 || y % 4 == 0)),
+    ]
+  })
+}
+
+/// Simulated pointer of frame `index` on a `width` x `height` target, relative to `region`.
+///
+/// The pointer sits at `(index * 7 % width, index * 5 % height)` of the target, switches shape
+/// every `CURSOR_SHAPE_FRAMES` frames and is hidden for part of every `CURSOR_PERIOD` frames.
+pub fn pointer(index: u64, width: u32, height: u32, region: RegionInternal) -> CursorInternal {
+  let x = (index * 7 % width.max(1) as u64) as i32 - region.x as i32;
+  let y = (index * 5 % height.max(1) as u64) as i32 - region.y as i32;
+  let visible = index % CURSOR_PERIOD < CURSOR_PERIOD - CURSOR_HIDDEN_FRAMES;
+  let shape = &cursor_shapes()[(index / CURSOR_SHAPE_FRAMES % 2) as usize];
+  CursorInternal::new(x, y, visible, Some(shape.clone()))
+}
+
+/// Renders the `region` of test pattern frame number `index` for a `width` pixels wide target,
+/// with `cursor` drawn on top.
+///
+/// The target is made of eight vertical bars (`BAR_COLORS`) scrolled left by
+/// `index * BAR_STEP` pixels; display or window `n` starts `pattern_index = n - 1` bars further.
+/// The top-left pixel of the returned frame is replaced by the frame counter:
+/// its R, G and B channels hold bits 16..24, 8..16 and 0..8 of `index`.
+pub fn render_frame(
+  width: u32,
+  pattern_index: u32,
+  index: u64,
+  region: RegionInternal,
+  cursor: Option<&CursorInternal>,
+) -> FrameDataInternal {
+  let w = region.width as usize;
+  let h = region.height as usize;
+  let bar_width = (width as usize / BAR_COLORS.len()).max(1);
+  let period = (bar_width * BAR_COLORS.len()) as u64;
+  let shift = index * BAR_STEP as u64 + pattern_index as u64 * bar_width as u64 + region.x as u64;
+  let offset = (shift % period) as usize;
+
+  let mut row = Vec::with_capacity(w * 4);
+  for x in 0..w {
+    let bar = ((x + offset) / bar_width) % BAR_COLORS.len();
+    row.extend_from_slice(&BAR_COLORS[bar]);
+  }
+
+  let mut data = Vec::with_capacity(w * h * 4);
+  for _ in 0..h {
+    data.extend_from_slice(&row);
+  }
+
+  let mut frame = FrameDataInternal {
+    width: region.width,
+    height: region.height,
+    stride: region.width * 4,
+    format: PixelFormat::Rgba,
+    encoding: None,
+    data,
+    timestamp: host_time(),
+    ..Default::default()
+  };
+  if let Some(cursor) = cursor {
+    cursor.draw(&mut frame);
+  }
+  if !frame.data.is_empty() {
+    frame.data[0] = (index >> 16) as u8;
+    frame.data[1] = (index >> 8) as u8;
+    frame.data[2] = index as u8;
+    frame.data[3] = 255;
+  }
+  frame
+}
+
+/// Sleeps until one frame interval after the previous deadline `next`, so frame times do not
+/// drift. A loop that fell behind, or whose frame rate changed, restarts from now instead of
+/// catching up with a burst of frames.
+fn pace(next: &mut Instant, settings: &SharedSettings) {
+  *next += settings.frame_interval();
+  let now = Instant::now();
+  match next.checked_duration_since(now) {
+    Some(wait) if !settings.sleep(wait) => {}
+    _ => *next = Instant::now(),
+  }
+}
+
+/// Deterministic backend that renders moving color bars instead of reading a
+/// display, so capture sessions can be exercised on headless machines.
+pub struct SyntheticBackend {
+  options: SyntheticOptions,
+  capture_options: CaptureOptions,
+  running: Arc<AtomicBool>,
+  frame_index: Arc<AtomicU64>,
+  handle: Option<thread::JoinHandle<()>>,
+}
+
+impl SyntheticBackend {
+  pub fn new(options: SyntheticOptions, capture_options: CaptureOptions) -> Self {
+    Self {
+      options,
+      capture_options,
+      running: Arc::new(AtomicBool::new(false)),
+      frame_index: Arc::new(AtomicU64::new(0)),
+      handle: None,
+    }
+  }
+
+  fn displays(&self) -> Vec<DisplayInfoInternal> {
+    let index = self.frame_index.load(Ordering::SeqCst);
+    self.options.displays_at(index)
+  }
+
+  fn windows(&self) -> Vec<WindowInfoInternal> {
+    (0..self.options.windows)
+      .map(|i| WindowInfoInternal {
+        id: i + 1,
+        title: format!("Synthetic Window {}", i + 1),
+        app_name: "rs-capture".to_string(),
+        pid: std::process::id(),
+        x: (i * 20) as i32,
+        y: (i * 20) as i32,
+        width: (self.options.width / 2).max(1),
+        height: (self.options.height / 2).max(1),
+        is_minimized: false,
+        is_on_screen: true,
+      })
+      .collect()
+  }
+
+  /// Id of the captured display, `None` when capturing a window.
+  fn display_id(&self, pattern_index: u32) -> Option<u32> {
+    match self.capture_options.window_id {
+      Some(_) => None,
+      None => Some(pattern_index + 1),
+    }
+  }
+
+  /// Pattern index and size of the display or window selected by `capture_options`.
+  fn target(&self) -> Result<(u32, u32, u32)> {
+    if let Some(id) = self.capture_options.window_id {
+      let window = self
+        .windows()
+        .into_iter()
+        .find(|w| w.id == id)
+        .ok_or_else(|| window_not_found(id))?;
+      return Ok((id - 1, window.width, window.height));
+    }
+
+    let displays = self.displays();
+    let id = self.capture_options.display_id;
+    let display =
+      DisplayWatch::find(&displays, id).ok_or_else(|| display_not_found(id.unwrap_or(1)))?;
+    Ok((display.id - 1, display.width, display.height))
+  }
+}
+
+impl Default for SyntheticBackend {
+  fn default() -> Self {
+    Self::new(SyntheticOptions::default(), CaptureOptions::default())
+  }
+}
+
+impl CaptureBackendImpl for SyntheticBackend {
+  fn start<'a>(
+    &'a mut self,
+    sink: Option<FrameSink>,
+  ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+    Box::pin(async move {
+      if self.options.fail_on_start {
+        return Err(Error::new(
+          Status::GenericFailure,
+          "Synthetic start failure".to_string(),
+        ));
+      }
+
+      if self.running.load(Ordering::SeqCst) {
+        return Ok(());
+      }
+
+      // A previous loop may have ended on its own (fail_after_frames).
+      if let Some(handle) = self.handle.take() {
+        let _ = handle.join();
+      }
+
+      self.frame_index.store(0, Ordering::SeqCst);
+      let (pattern_index, mut width, mut height) = self.target()?;
+      // Displays are compared on every frame, as they change on given frames.
+      let mut watch = self.capture_options.window_id.is_none().then(|| {
+        let displays = self.displays();
+        let target = displays[pattern_index as usize].clone();
+        DisplayWatch::new(&self.capture_options, displays, target)
+      });
+
+      self.running.store(true, Ordering::SeqCst);
+      let running = self.running.clone();
+      let frame_index = self.frame_index.clone();
+      let options = self.options.clone();
+      let window_id = self.capture_options.window_id;
+      let events = self.capture_options.events.clone();
+      let display_id = self.display_id(pattern_index);
+      let region = self.capture_options.region.clone();
+      let settings = self.capture_options.settings.clone();
+      let hide_cursor = self.capture_options.hide_cursor;
+
+      let handle = thread::spawn(move || {
+        let mut next = Instant::now();
+
+        while running.load(Ordering::SeqCst) {
+          if settings.is_paused() {
+            pace(&mut next, &settings);
+            continue;
+          }
+
+          let index = frame_index.load(Ordering::SeqCst);
+          if options
+            .fail_after_frames
+            .is_some_and(|limit| index >= limit)
+          {
+            match window_id {
+              Some(id) => events.fail(ErrorCode::WindowClosed, format!("Window {} was closed", id)),
+              None => events.fail(
+                ErrorCode::DisplayLost,
+                format!("Synthetic capture failed after {} frames", index),
+              ),
+            }
+            running.store(false, Ordering::SeqCst);
+            break;
+          }
+
+          if let Some(watch) = &mut watch {
+            match watch.update(options.displays_at(index)) {
+              Change::None => {}
+              Change::Reinit(display) => (width, height) = (display.width, display.height),
+              Change::Lost => {
+                events.fail(ErrorCode::DisplayLost, "Synthetic display was disconnected");
+                running.store(false, Ordering::SeqCst);
+                break;
+              }
+            }
+          }
+
+          if let Some(sink) = &sink {
+            let rect = region.rect_within(width, height);
+            let frame = FrameDataInternal {
+              display_id,
+              ..options.render(index, pattern_index, (width, height), rect, hide_cursor)
+            };
+            let status = sink.send(frame);
+            if status != Status::Ok {
+              running.store(false, Ordering::SeqCst);
+              break;
+            }
+          }
+          frame_index.store(index + 1, Ordering::SeqCst);
+          pace(&mut next, &settings);
+        }
+      });
+
+      self.handle = Some(handle);
+      Ok(())
+    })
+  }
+
+  fn stop(&mut self) -> Result<()> {
+    self.running.store(false, Ordering::SeqCst);
+    if let Some(handle) = self.handle.take() {
+      let _ = handle.join();
+    }
+    Ok(())
+  }
+
+  fn screenshot<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<FrameDataInternal>> + Send + 'a>> {
+    Box::pin(async move {
+      if self.options.fail_screenshot {
+        return Err(Error::new(
+          Status::GenericFailure,
+          "Synthetic screenshot failure".to_string(),
+        ));
+      }
+
+      let (pattern_index, width, height) = self.target()?;
+      let index = self.frame_index.load(Ordering::SeqCst);
+      let rect = self.capture_options.region.rect_within(width, height);
+      let hide_cursor = self.capture_options.hide_cursor;
+      Ok(FrameDataInternal {
+        display_id: self.display_id(pattern_index),
+        ..self
+          .options
+          .render(index, pattern_index, (width, height), rect, hide_cursor)
+      })
+    })
+  }
+
+  fn list_displays<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<DisplayInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move { Ok(self.displays()) })
+  }
+
+  fn list_windows<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<WindowInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move { Ok(self.windows()) })
+  }
+}
@@ -0,0 +1,106 @@
+use std::time::{Duration, Instant};
+
+use super::events::{DisplayChangeInternal, DisplayChangeKind, EventSink};
+use super::{CaptureOptions, DisplayInfoInternal, SharedRegion};
+
+/// How often backends the platform does not notify list the displays again.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What a backend has to do after the displays changed.
+#[derive(Debug)]
+pub enum Change {
+  /// The captured display kept its id, position and size.
+  None,
+  /// The captured display moved, was resized or got a new id: set up the capture again.
+  Reinit(DisplayInfoInternal),
+  /// The captured display was disconnected.
+  Lost,
+}
+
+/// Compares the displays seen by a running session with the ones it saw last, reporting
+/// `displaysChanged` and `resolutionChanged` events and telling the backend when to set up the
+/// capture of its display again.
+pub struct DisplayWatch {
+  events: EventSink,
+  region: SharedRegion,
+  displays: Vec<DisplayInfoInternal>,
+  target: DisplayInfoInternal,
+  checked: Instant,
+}
+
+impl DisplayWatch {
+  /// Watches `target`, one of `displays`, for the session configured by `options`.
+  pub fn new(
+    options: &CaptureOptions,
+    displays: Vec<DisplayInfoInternal>,
+    target: DisplayInfoInternal,
+  ) -> Self {
+    Self {
+      events: options.events.clone(),
+      region: options.region.clone(),
+      displays,
+      target,
+      checked: Instant::now(),
+    }
+  }
+
+  /// The display `id` of `displays`, or the primary display when `id` is `None`.
+  pub fn find(displays: &[DisplayInfoInternal], id: Option<u32>) -> Option<DisplayInfoInternal> {
+    match id {
+      Some(id) => displays.iter().find(|d| d.id == id),
+      None => displays.iter().find(|d| d.is_primary).or(displays.first()),
+    }
+    .cloned()
+  }
+
+  /// The captured display as last seen.
+  pub fn target(&self) -> &DisplayInfoInternal {
+    &self.target
+  }
+
+  /// Whether `POLL_INTERVAL` passed since the displays were last compared.
+  pub fn due(&self) -> bool {
+    self.checked.elapsed() >= POLL_INTERVAL
+  }
+
+  /// Compares `displays` with the ones seen last and reports what changed. The captured display
+  /// is followed by id, or by name when the platform gave it a new id after reconnecting it.
+  pub fn update(&mut self, displays: Vec<DisplayInfoInternal>) -> Change {
+    self.checked = Instant::now();
+    if displays == self.displays {
+      return Change::None;
+    }
+    let target = displays
+      .iter()
+      .find(|d| d.id == self.target.id)
+      .or_else(|| displays.iter().find(|d| d.name == self.target.name))
+      .cloned();
+    let Some(target) = target else {
+      return Change::Lost;
+    };
+
+    let resized = (target.width, target.height) != (self.target.width, self.target.height);
+    let moved = (target.id, target.x, target.y) != (self.target.id, self.target.x, self.target.y);
+    if resized {
+      self.region.resize(target.width, target.height);
+    }
+    self.events.display_changed(DisplayChangeInternal {
+      kind: if resized {
+        DisplayChangeKind::ResolutionChanged
+      } else {
+        DisplayChangeKind::DisplaysChanged
+      },
+      display_id: target.id,
+      width: target.width,
+      height: target.height,
+      displays: displays.clone(),
+    });
+    self.displays = displays;
+    self.target = target.clone();
+    if resized || moved {
+      Change::Reinit(target)
+    } else {
+      Change::None
+    }
+  }
+}
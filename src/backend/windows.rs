@@ -1,10 +1,14 @@
-use napi::Result;
 use std::future::Future;
 use std::pin::Pin;
 
 use super::dxgi::DxgiBackend;
+use super::events::ErrorCode;
 use super::xcap::XCapBackend;
-use super::{CaptureBackendImpl, FrameDataInternal, FrameTsfnType};
+use super::{
+  CaptureBackendImpl, CaptureOptions, DisplayInfoInternal, FrameDataInternal, FrameSink,
+  WindowInfoInternal,
+};
+use crate::error::Result;
 
 pub struct WindowsBackend {
   inner: Box<dyn CaptureBackendImpl>,
@@ -14,18 +18,28 @@ unsafe impl Send for WindowsBackend {}
 unsafe impl Sync for WindowsBackend {}
 
 impl WindowsBackend {
-  pub fn new() -> Self {
-    match DxgiBackend::new() {
+  pub fn new(options: CaptureOptions) -> Self {
+    // Window targets are captured through XCap's `Window`.
+    if options.window_id.is_some() {
+      return Self {
+        inner: Box::new(XCapBackend::new(options)),
+      };
+    }
+
+    match DxgiBackend::new(options.clone()) {
       Ok(dxgi) => Self {
         inner: Box::new(dxgi),
       },
       Err(e) => {
-        eprintln!(
-          "Windows capture init failed: {:?}. Falling back to XCap.",
-          e
+        options.events.warn(
+          ErrorCode::BackendFallback,
+          format!(
+            "Windows capture init failed: {:?}. Falling back to XCap.",
+            e
+          ),
         );
         Self {
-          inner: Box::new(XCapBackend::new()),
+          inner: Box::new(XCapBackend::new(options)),
         }
       }
     }
@@ -34,17 +48,16 @@ impl WindowsBackend {
 
 impl Default for WindowsBackend {
   fn default() -> Self {
-    Self::new()
+    Self::new(CaptureOptions::default())
   }
 }
 
 impl CaptureBackendImpl for WindowsBackend {
   fn start<'a>(
     &'a mut self,
-    tsfn: Option<FrameTsfnType>,
-    fps: u32,
+    sink: Option<FrameSink>,
   ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
-    self.inner.start(tsfn, fps)
+    self.inner.start(sink)
   }
 
   fn stop(&mut self) -> Result<()> {
@@ -56,4 +69,16 @@ impl CaptureBackendImpl for WindowsBackend {
   ) -> Pin<Box<dyn Future<Output = Result<FrameDataInternal>> + Send + 'a>> {
     self.inner.screenshot()
   }
+
+  fn list_displays<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<DisplayInfoInternal>>> + Send + 'a>> {
+    self.inner.list_displays()
+  }
+
+  fn list_windows<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<WindowInfoInternal>>> + Send + 'a>> {
+    self.inner.list_windows()
+  }
 }
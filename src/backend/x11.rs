@@ -0,0 +1,675 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use xcb::{damage, randr, shm, x, xfixes, Xid};
+
+use super::cursor::{CursorInternal, CursorShape};
+use super::events::ErrorCode;
+use super::pixel::PixelFormat;
+use super::topology::{Change, DisplayWatch};
+use super::{
+  host_time, CaptureBackendImpl, CaptureOptions, DisplayInfoInternal, FrameDataInternal, FrameSink,
+  RegionInternal, SharedRegion, WindowInfoInternal,
+};
+use crate::error::{self, Error, Status};
+
+/// Returns true when the current session is an X11 one (including Xvfb), as
+/// opposed to Wayland where the X root window only shows XWayland clients.
+pub fn is_x11_session() -> bool {
+  if let Ok(kind) = std::env::var("XDG_SESSION_TYPE") {
+    if kind == "x11" {
+      return true;
+    }
+    if kind == "wayland" {
+      return false;
+    }
+  }
+  std::env::var_os("WAYLAND_DISPLAY").is_none() && std::env::var_os("DISPLAY").is_some()
+}
+
+pub struct X11Backend {
+  options: CaptureOptions,
+  running: Arc<AtomicBool>,
+  handle: Option<thread::JoinHandle<()>>,
+  /// Connection and SHM segment of `screenshot`, kept between calls.
+  screenshot_state: Option<X11State>,
+}
+
+impl X11Backend {
+  pub fn new(options: CaptureOptions) -> Result<Self> {
+    // Check the server and the display up front, without allocating the SHM segment yet.
+    let (conn, root) = connect()?;
+    if !has_extension(&conn, xcb::Extension::Shm) {
+      return Err(anyhow!("MIT-SHM extension is not available"));
+    }
+    find_monitor(&list_monitors(&conn, root)?, options.display_id)?;
+
+    Ok(Self {
+      options,
+      running: Arc::new(AtomicBool::new(false)),
+      handle: None,
+      screenshot_state: None,
+    })
+  }
+}
+
+fn connect() -> Result<(xcb::Connection, x::Window)> {
+  let (conn, screen_num) = xcb::Connection::connect_with_extensions(
+    None,
+    &[],
+    &[
+      xcb::Extension::Shm,
+      xcb::Extension::XFixes,
+      xcb::Extension::Damage,
+      xcb::Extension::RandR,
+    ],
+  )?;
+
+  let setup = conn.get_setup();
+  if setup.image_byte_order() != x::ImageOrder::LsbFirst {
+    return Err(anyhow!("Unsupported X server image byte order"));
+  }
+  let screen = setup
+    .roots()
+    .nth(screen_num as usize)
+    .ok_or_else(|| anyhow!("X screen {} not found", screen_num))?;
+  let depth = screen.root_depth();
+  // Frames are read as BGRX, which needs 32 bits per pixel whatever the depth.
+  let bits_per_pixel = setup
+    .pixmap_formats()
+    .iter()
+    .find(|format| format.depth() == depth)
+    .map(|format| format.bits_per_pixel());
+  if (depth != 24 && depth != 32) || bits_per_pixel != Some(32) {
+    return Err(anyhow!(
+      "Unsupported root depth {} with {} bits per pixel",
+      depth,
+      bits_per_pixel.unwrap_or(0)
+    ));
+  }
+  let root = screen.root();
+
+  Ok((conn, root))
+}
+
+fn has_extension(conn: &xcb::Connection, ext: xcb::Extension) -> bool {
+  conn.active_extensions().any(|e| e == ext)
+}
+
+/// Lists the active RandR monitors. Each display is identified by its first
+/// RandR output, which matches the monitor ids used by XCap. Without RandR 1.5
+/// the whole root window is reported as a single display with id 0.
+fn list_monitors(conn: &xcb::Connection, root: x::Window) -> Result<Vec<DisplayInfoInternal>> {
+  let randr_15 = has_extension(conn, xcb::Extension::RandR)
+    && conn
+      .wait_for_reply(conn.send_request(&randr::QueryVersion {
+        major_version: 1,
+        minor_version: 5,
+      }))
+      .is_ok_and(|v| v.major_version() > 1 || v.minor_version() >= 5);
+
+  if !randr_15 {
+    let geometry = conn.wait_for_reply(conn.send_request(&x::GetGeometry {
+      drawable: x::Drawable::Window(root),
+    }))?;
+    return Ok(vec![DisplayInfoInternal {
+      id: 0,
+      name: "Screen".to_string(),
+      x: 0,
+      y: 0,
+      width: geometry.width() as u32,
+      height: geometry.height() as u32,
+      scale_factor: 1.0,
+      rotation: 0.0,
+      is_primary: true,
+    }]);
+  }
+
+  let reply = conn.wait_for_reply(conn.send_request(&randr::GetMonitors {
+    window: root,
+    get_active: true,
+  }))?;
+
+  let mut displays = Vec::new();
+  for monitor in reply.monitors() {
+    let Some(output) = monitor.outputs().first().copied() else {
+      continue;
+    };
+
+    let name = conn
+      .wait_for_reply(conn.send_request(&x::GetAtomName {
+        atom: monitor.name(),
+      }))
+      .map(|r| r.name().to_utf8().into_owned())
+      .unwrap_or_default();
+
+    let rotation = conn
+      .wait_for_reply(conn.send_request(&randr::GetOutputInfo {
+        output,
+        config_timestamp: x::CURRENT_TIME,
+      }))
+      .ok()
+      .and_then(|info| {
+        conn
+          .wait_for_reply(conn.send_request(&randr::GetCrtcInfo {
+            crtc: info.crtc(),
+            config_timestamp: x::CURRENT_TIME,
+          }))
+          .ok()
+      })
+      .map(|crtc| {
+        let rotation = crtc.rotation();
+        if rotation.contains(randr::Rotation::ROTATE_90) {
+          90.0
+        } else if rotation.contains(randr::Rotation::ROTATE_180) {
+          180.0
+        } else if rotation.contains(randr::Rotation::ROTATE_270) {
+          270.0
+        } else {
+          0.0
+        }
+      })
+      .unwrap_or(0.0);
+
+    displays.push(DisplayInfoInternal {
+      id: output.resource_id(),
+      name,
+      x: monitor.x() as i32,
+      y: monitor.y() as i32,
+      width: monitor.width() as u32,
+      height: monitor.height() as u32,
+      scale_factor: 1.0,
+      rotation,
+      is_primary: monitor.primary(),
+    });
+  }
+
+  Ok(displays)
+}
+
+/// The display to capture is gone, reported as `ErrorCode::DisplayLost`.
+#[derive(Debug)]
+struct MissingDisplay(String);
+
+impl std::fmt::Display for MissingDisplay {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl std::error::Error for MissingDisplay {}
+
+/// The display `id` of `displays`, or the primary display when `id` is `None`.
+fn find_monitor(displays: &[DisplayInfoInternal], id: Option<u32>) -> Result<&DisplayInfoInternal> {
+  match id {
+    Some(id) => displays
+      .iter()
+      .find(|d| d.id == id)
+      .ok_or_else(|| MissingDisplay(format!("Display {} not found", id)).into()),
+    None => displays
+      .iter()
+      .find(|d| d.is_primary)
+      .or(displays.first())
+      .ok_or_else(|| MissingDisplay("No monitors found".to_string()).into()),
+  }
+}
+
+/// A System V shared memory segment attached to both this process and the X server.
+struct ShmSegment {
+  seg: shm::Seg,
+  addr: *mut u8,
+  size: usize,
+}
+
+// The mapping belongs to the process, and `X11State` only touches it through `&mut self`.
+unsafe impl Send for ShmSegment {}
+unsafe impl Sync for ShmSegment {}
+
+impl ShmSegment {
+  unsafe fn new(conn: &xcb::Connection, size: usize) -> Result<Self> {
+    let shmid = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+    if shmid < 0 {
+      return Err(anyhow!(
+        "shmget failed: {}",
+        std::io::Error::last_os_error()
+      ));
+    }
+
+    let addr = libc::shmat(shmid, ptr::null(), 0);
+    if addr as isize == -1 {
+      let err = std::io::Error::last_os_error();
+      libc::shmctl(shmid, libc::IPC_RMID, ptr::null_mut());
+      return Err(anyhow!("shmat failed: {}", err));
+    }
+
+    let seg: shm::Seg = conn.generate_id();
+    let attached = conn.send_and_check_request(&shm::Attach {
+      shmseg: seg,
+      shmid: shmid as u32,
+      read_only: false,
+    });
+
+    // Once both sides are attached the id is no longer needed; marking it for
+    // removal now means the segment is freed even if the process crashes.
+    libc::shmctl(shmid, libc::IPC_RMID, ptr::null_mut());
+
+    if let Err(e) = attached {
+      libc::shmdt(addr);
+      return Err(anyhow!("ShmAttach failed: {:?}", e));
+    }
+
+    Ok(Self {
+      seg,
+      addr: addr as *mut u8,
+      size,
+    })
+  }
+
+  unsafe fn detach(&mut self, conn: &xcb::Connection) {
+    let _ = conn.send_and_check_request(&shm::Detach { shmseg: self.seg });
+    libc::shmdt(self.addr as *const libc::c_void);
+  }
+}
+
+struct X11State {
+  conn: xcb::Connection,
+  root: x::Window,
+  /// Id of the captured display, as reported by `list_monitors`.
+  display_id: u32,
+  x: i16,
+  y: i16,
+  width: u16,
+  height: u16,
+  /// Part of the monitor held in `base`.
+  rect: RegionInternal,
+  shm: ShmSegment,
+  xfixes: bool,
+  damage: Option<damage::Damage>,
+  damaged: bool,
+  /// Set when RandR reports that monitors were connected, moved or changed mode.
+  screen_changed: bool,
+  base: Vec<u8>,
+  cursor: Option<(i16, i16, u32)>,
+  /// Image of the pointer with the serial `cursor` holds, converted to straight RGBA.
+  cursor_shape: Option<Arc<CursorShape>>,
+  hide_cursor: bool,
+}
+
+impl Drop for X11State {
+  fn drop(&mut self) {
+    unsafe {
+      if let Some(damage) = self.damage.take() {
+        let _ = self
+          .conn
+          .send_and_check_request(&damage::Destroy { damage });
+      }
+      self.shm.detach(&self.conn);
+    }
+  }
+}
+
+impl X11State {
+  unsafe fn new(display_id: Option<u32>, hide_cursor: bool) -> Result<Self> {
+    let (conn, root) = connect()?;
+    if !has_extension(&conn, xcb::Extension::Shm) {
+      return Err(anyhow!("MIT-SHM extension is not available"));
+    }
+
+    let displays = list_monitors(&conn, root)?;
+    let display = find_monitor(&displays, display_id)?;
+    let display_id = display.id;
+    let (x, y) = (display.x as i16, display.y as i16);
+    let (width, height) = (display.width as u16, display.height as u16);
+    let shm = ShmSegment::new(&conn, width as usize * height as usize * 4)?;
+
+    let xfixes = has_extension(&conn, xcb::Extension::XFixes)
+      && conn
+        .wait_for_reply(conn.send_request(&xfixes::QueryVersion {
+          client_major_version: 4,
+          client_minor_version: 0,
+        }))
+        .is_ok();
+
+    let damage = if xfixes && has_extension(&conn, xcb::Extension::Damage) {
+      let version = conn.wait_for_reply(conn.send_request(&damage::QueryVersion {
+        client_major_version: 1,
+        client_minor_version: 1,
+      }));
+      let id: damage::Damage = conn.generate_id();
+      let created = conn.send_and_check_request(&damage::Create {
+        damage: id,
+        drawable: x::Drawable::Window(root),
+        level: damage::ReportLevel::NonEmpty,
+      });
+      (version.is_ok() && created.is_ok()).then_some(id)
+    } else {
+      None
+    };
+
+    if has_extension(&conn, xcb::Extension::RandR) {
+      let _ = conn.send_and_check_request(&randr::SelectInput {
+        window: root,
+        enable: randr::NotifyMask::SCREEN_CHANGE
+          | randr::NotifyMask::CRTC_CHANGE
+          | randr::NotifyMask::OUTPUT_CHANGE,
+      });
+    }
+
+    Ok(Self {
+      conn,
+      root,
+      display_id,
+      x,
+      y,
+      width,
+      height,
+      rect: RegionInternal::full(width as u32, height as u32),
+      shm,
+      xfixes,
+      damage,
+      damaged: true,
+      screen_changed: false,
+      base: Vec::new(),
+      cursor: None,
+      cursor_shape: None,
+      hide_cursor,
+    })
+  }
+
+  unsafe fn capture_frame(&mut self, region: &SharedRegion) -> Result<FrameDataInternal> {
+    let rect = region.rect_within(self.width as u32, self.height as u32);
+    if rect != self.rect {
+      self.rect = rect;
+      self.base.clear();
+    }
+
+    while let Some(event) = self.conn.poll_for_event()? {
+      match event {
+        xcb::Event::Damage(damage::Event::Notify(_)) => self.damaged = true,
+        xcb::Event::RandR(randr::Event::ScreenChangeNotify(_) | randr::Event::Notify(_)) => {
+          self.screen_changed = true
+        }
+        _ => {}
+      }
+    }
+    if self.damage.is_none() {
+      self.damaged = true;
+    }
+
+    let cursor = if self.xfixes {
+      self
+        .conn
+        .wait_for_reply(self.conn.send_request(&xfixes::GetCursorImage {}))
+        .ok()
+    } else {
+      None
+    };
+    let cursor_key = cursor.as_ref().map(|c| (c.x(), c.y(), c.cursor_serial()));
+    let cursor = cursor.map(|reply| self.pointer(&reply));
+
+    // Nothing moved on screen: reuse the last image instead of a server round trip.
+    if !self.damaged && cursor_key == self.cursor && !self.base.is_empty() {
+      return Ok(self.frame_with_cursor(cursor));
+    }
+
+    if self.damaged || self.base.is_empty() {
+      if let Some(damage) = self.damage {
+        self.conn.send_request(&damage::Subtract {
+          damage,
+          repair: xfixes::Region::none(),
+          parts: xfixes::Region::none(),
+        });
+      }
+      self.damaged = false;
+
+      self
+        .conn
+        .wait_for_reply(self.conn.send_request(&shm::GetImage {
+          drawable: x::Drawable::Window(self.root),
+          x: self.x + rect.x as i16,
+          y: self.y + rect.y as i16,
+          width: rect.width as u16,
+          height: rect.height as u16,
+          plane_mask: u32::MAX,
+          format: x::ImageFormat::ZPixmap as u8,
+          shmseg: self.shm.seg,
+          offset: 0,
+        }))?;
+
+      let len = (rect.width as usize * rect.height as usize * 4).min(self.shm.size);
+      let src = std::slice::from_raw_parts(self.shm.addr, len);
+      self.base = bgrx_to_bgra(src);
+    }
+
+    self.cursor = cursor_key;
+    Ok(self.frame_with_cursor(cursor))
+  }
+
+  /// Pointer reported by XFixes, relative to the captured part of the monitor. Its image is
+  /// only converted when the cursor serial changes.
+  fn pointer(&mut self, reply: &xfixes::GetCursorImageReply) -> CursorInternal {
+    let serial = reply.cursor_serial() as u64;
+    if self.cursor_shape.as_ref().map(|shape| shape.id) != Some(serial) {
+      self.cursor_shape = Some(Arc::new(CursorShape {
+        id: serial,
+        width: reply.width() as u32,
+        height: reply.height() as u32,
+        hotspot_x: reply.xhot() as u32,
+        hotspot_y: reply.yhot() as u32,
+        data: argb_to_rgba(reply.cursor_image()),
+      }));
+    }
+    CursorInternal::new(
+      reply.x() as i32 - self.x as i32 - self.rect.x as i32,
+      reply.y() as i32 - self.y as i32 - self.rect.y as i32,
+      true,
+      self.cursor_shape.clone(),
+    )
+  }
+
+  fn frame_with_cursor(&self, cursor: Option<CursorInternal>) -> FrameDataInternal {
+    let rect = self.rect;
+    let mut frame = FrameDataInternal {
+      width: rect.width,
+      height: rect.height,
+      stride: rect.width * 4,
+      format: PixelFormat::Bgra,
+      encoding: None,
+      data: self.base.clone(),
+      timestamp: host_time(),
+      display_id: Some(self.display_id),
+      ..Default::default()
+    };
+    if let Some(cursor) = cursor.as_ref().filter(|_| !self.hide_cursor) {
+      cursor.draw(&mut frame);
+    }
+    frame.cursor = cursor;
+    frame
+  }
+}
+
+/// ZPixmap pixels are BGRX; the padding byte becomes an opaque alpha.
+fn bgrx_to_bgra(src: &[u8]) -> Vec<u8> {
+  let mut dst = src.to_vec();
+  for px in dst.chunks_exact_mut(4) {
+    px[3] = 255;
+  }
+  dst
+}
+
+/// Converts a premultiplied ARGB cursor image, as returned by XFixes, to straight RGBA.
+fn argb_to_rgba(pixels: &[u32]) -> Vec<u8> {
+  pixels
+    .iter()
+    .flat_map(|&argb| {
+      let a = argb >> 24;
+      let channel = |shift: u32| match a {
+        0 => 0,
+        a => ((((argb >> shift) & 0xff) * 255 + a / 2) / a).min(255) as u8,
+      };
+      [channel(16), channel(8), channel(0), a as u8]
+    })
+    .collect()
+}
+
+impl CaptureBackendImpl for X11Backend {
+  fn start<'a>(
+    &'a mut self,
+    sink: Option<FrameSink>,
+  ) -> Pin<Box<dyn Future<Output = error::Result<()>> + Send + 'a>> {
+    Box::pin(async move {
+      if self.running.load(Ordering::SeqCst) {
+        return Ok(());
+      }
+
+      self.running.store(true, Ordering::SeqCst);
+      let running = self.running.clone();
+      let options = self.options.clone();
+      let events = self.options.events.clone();
+
+      let handle = thread::spawn(move || {
+        let result = unsafe { run_capture_loop(running.clone(), sink, &options) };
+        if let Err(e) = result {
+          if e.is::<MissingDisplay>() {
+            events.fail(ErrorCode::DisplayLost, e.to_string());
+          } else {
+            events.fail(
+              ErrorCode::CaptureFailed,
+              format!("X11 capture failed: {:#}", e),
+            );
+          }
+          running.store(false, Ordering::SeqCst);
+        }
+      });
+
+      self.handle = Some(handle);
+      Ok(())
+    })
+  }
+
+  fn stop(&mut self) -> error::Result<()> {
+    self.running.store(false, Ordering::SeqCst);
+    if let Some(handle) = self.handle.take() {
+      let _ = handle.join();
+    }
+    Ok(())
+  }
+
+  fn screenshot<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = error::Result<FrameDataInternal>> + Send + 'a>> {
+    Box::pin(async move {
+      let options = &self.options;
+      let cached = &mut self.screenshot_state;
+      let mut capture = || unsafe {
+        // Set the state up again once the monitors changed, or after a failed capture.
+        if cached.as_ref().is_some_and(|state| state.screen_changed) {
+          *cached = None;
+        }
+        let state = match cached {
+          Some(state) => state,
+          None => cached.insert(X11State::new(options.display_id, options.hide_cursor)?),
+        };
+        let frame = state.capture_frame(&options.region);
+        if frame.is_err() {
+          *cached = None;
+        }
+        frame
+      };
+      capture().map_err(|e: anyhow::Error| {
+        Error::new(
+          Status::GenericFailure,
+          format!("X11 capture failed: {:#}", e),
+        )
+      })
+    })
+  }
+
+  fn list_displays<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = error::Result<Vec<DisplayInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move {
+      let list = || {
+        let (conn, root) = connect()?;
+        list_monitors(&conn, root)
+      };
+      list().map_err(|e: anyhow::Error| {
+        Error::new(
+          Status::GenericFailure,
+          format!("Failed to list X11 monitors: {:#}", e),
+        )
+      })
+    })
+  }
+
+  fn list_windows<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = error::Result<Vec<WindowInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move { super::xcap::list_windows() })
+  }
+}
+
+unsafe fn run_capture_loop(
+  running: Arc<AtomicBool>,
+  sink: Option<FrameSink>,
+  options: &CaptureOptions,
+) -> Result<()> {
+  let settings = &options.settings;
+  let mut state = X11State::new(options.display_id, options.hide_cursor)?;
+  let displays = list_monitors(&state.conn, state.root)?;
+  let target = DisplayWatch::find(&displays, Some(state.display_id))
+    .ok_or_else(|| anyhow!("Display {} not found", state.display_id))?;
+  let mut watch = DisplayWatch::new(options, displays, target);
+
+  while running.load(Ordering::SeqCst) {
+    let start_time = Instant::now();
+    let target_interval = settings.frame_interval();
+    if settings.is_paused() {
+      settings.sleep(target_interval);
+      continue;
+    }
+
+    // RandR reports changes as they happen; polling covers servers without it.
+    if std::mem::take(&mut state.screen_changed) || watch.due() {
+      match watch.update(list_monitors(&state.conn, state.root)?) {
+        Change::None => {}
+        Change::Reinit(display) => state = X11State::new(Some(display.id), options.hide_cursor)?,
+        Change::Lost => {
+          let id = watch.target().id;
+          options.events.fail(
+            ErrorCode::DisplayLost,
+            format!("Display {} was disconnected", id),
+          );
+          running.store(false, Ordering::SeqCst);
+          break;
+        }
+      }
+    }
+
+    let frame = match state.capture_frame(&options.region) {
+      Ok(frame) => frame,
+      // The monitor shrank before the change was handled: handle it and capture again.
+      Err(_) if state.screen_changed => continue,
+      Err(e) => return Err(e),
+    };
+    if let Some(sink) = &sink {
+      let status = sink.send(frame);
+      if status != Status::Ok {
+        running.store(false, Ordering::SeqCst);
+      }
+    }
+
+    let elapsed = start_time.elapsed();
+    if elapsed < target_interval {
+      settings.sleep(target_interval - elapsed);
+    }
+  }
+
+  Ok(())
+}
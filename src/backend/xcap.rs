@@ -5,20 +5,28 @@ use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
-use napi::threadsafe_function::ThreadsafeFunctionCallMode;
-use napi::{Error, Result, Status};
-use xcap::Monitor;
+use xcap::image::{imageops, RgbaImage};
+use xcap::{Monitor, Window};
 
-use super::{CaptureBackendImpl, FrameDataInternal, FrameTsfnType};
+use super::events::ErrorCode;
+use super::pixel::PixelFormat;
+use super::topology::{Change, DisplayWatch};
+use super::{
+  display_not_found, host_time, window_not_found, CaptureBackendImpl, CaptureOptions,
+  DisplayInfoInternal, FrameDataInternal, FrameSink, SharedRegion, WindowInfoInternal,
+};
+use crate::error::{Error, Result, Status};
 
 pub struct XCapBackend {
+  options: CaptureOptions,
   running: Arc<AtomicBool>,
   handle: Option<thread::JoinHandle<()>>,
 }
 
 impl XCapBackend {
-  pub fn new() -> Self {
+  pub fn new(options: CaptureOptions) -> Self {
     Self {
+      options,
       running: Arc::new(AtomicBool::new(false)),
       handle: None,
     }
@@ -27,73 +35,297 @@ impl XCapBackend {
 
 impl Default for XCapBackend {
   fn default() -> Self {
-    Self::new()
+    Self::new(CaptureOptions::default())
   }
 }
 
+/// Picks the monitor with the given id, or the primary monitor when no id is given.
+fn find_monitor(display_id: Option<u32>) -> Result<Monitor> {
+  let monitors = Monitor::all().map_err(|e| {
+    Error::new(
+      Status::GenericFailure,
+      format!("Failed to get monitors: {}", e),
+    )
+  })?;
+
+  if monitors.is_empty() {
+    return Err(Error::new(
+      Status::GenericFailure,
+      "No monitors found".to_string(),
+    ));
+  }
+
+  match display_id {
+    Some(id) => monitors
+      .into_iter()
+      .find(|m| m.id().ok() == Some(id))
+      .ok_or_else(|| display_not_found(id)),
+    None => {
+      let primary = monitors
+        .iter()
+        .position(|m| m.is_primary().unwrap_or(false))
+        .unwrap_or(0);
+      Ok(monitors.into_iter().nth(primary).unwrap())
+    }
+  }
+}
+
+fn all_windows() -> Result<Vec<Window>> {
+  Window::all().map_err(|e| {
+    Error::new(
+      Status::GenericFailure,
+      format!("Failed to get windows: {}", e),
+    )
+  })
+}
+
+fn find_window(window_id: u32) -> Result<Window> {
+  all_windows()?
+    .into_iter()
+    .find(|w| w.id().ok() == Some(window_id))
+    .ok_or_else(|| window_not_found(window_id))
+}
+
+enum CaptureTarget {
+  Monitor(Monitor),
+  Window(u32, Window),
+}
+
+impl CaptureTarget {
+  fn find(options: &CaptureOptions) -> Result<Self> {
+    match options.window_id {
+      Some(id) => Ok(Self::Window(id, find_window(id)?)),
+      None => Ok(Self::Monitor(find_monitor(options.display_id)?)),
+    }
+  }
+
+  /// Captures the part of the target selected by `region`, or all of it when no region is set.
+  fn capture(&self, region: &SharedRegion) -> xcap::XCapResult<RgbaImage> {
+    match self {
+      Self::Monitor(monitor) => {
+        if region.get().is_none() {
+          return monitor.capture_image();
+        }
+        let rect = region.rect_within(monitor.width()?, monitor.height()?);
+        monitor.capture_region(rect.x, rect.y, rect.width, rect.height)
+      }
+      Self::Window(_, window) => {
+        let img = window.capture_image()?;
+        if region.get().is_none() {
+          return Ok(img);
+        }
+        let rect = region.rect_within(img.width(), img.height());
+        Ok(imageops::crop_imm(&img, rect.x, rect.y, rect.width, rect.height).to_image())
+      }
+    }
+  }
+
+  /// Like `capture`, with the frame stamped with the time the capture started.
+  fn capture_frame(&self, region: &SharedRegion) -> xcap::XCapResult<FrameDataInternal> {
+    let timestamp = host_time();
+    let img = self.capture(region)?;
+    Ok(FrameDataInternal {
+      timestamp,
+      display_id: match self {
+        Self::Monitor(monitor) => monitor.id().ok(),
+        Self::Window(..) => None,
+      },
+      ..into_frame(img)
+    })
+  }
+
+  /// Whether the captured window no longer exists.
+  fn is_closed(&self) -> bool {
+    match self {
+      Self::Monitor(_) => false,
+      Self::Window(id, _) => find_window(*id).is_err_and(|e| e.status == Status::InvalidArg),
+    }
+  }
+}
+
+fn into_frame(img: RgbaImage) -> FrameDataInternal {
+  let width = img.width();
+  let height = img.height();
+  let data = img.into_raw();
+  let stride = width * 4;
+
+  FrameDataInternal {
+    width,
+    height,
+    stride,
+    format: PixelFormat::Rgba,
+    encoding: None,
+    data,
+    ..Default::default()
+  }
+}
+
+pub(crate) fn monitor_info(monitor: &Monitor) -> xcap::XCapResult<DisplayInfoInternal> {
+  Ok(DisplayInfoInternal {
+    id: monitor.id()?,
+    name: monitor.name()?,
+    x: monitor.x()?,
+    y: monitor.y()?,
+    width: monitor.width()?,
+    height: monitor.height()?,
+    scale_factor: monitor.scale_factor()? as f64,
+    rotation: monitor.rotation()? as f64,
+    is_primary: monitor.is_primary()?,
+  })
+}
+
+/// Monitors as reported by XCap.
+fn list_displays() -> Result<Vec<DisplayInfoInternal>> {
+  let monitors = Monitor::all().map_err(|e| {
+    Error::new(
+      Status::GenericFailure,
+      format!("Failed to get monitors: {}", e),
+    )
+  })?;
+
+  monitors
+    .iter()
+    .map(monitor_info)
+    .collect::<xcap::XCapResult<Vec<_>>>()
+    .map_err(|e| {
+      Error::new(
+        Status::GenericFailure,
+        format!("Failed to read monitor info: {}", e),
+      )
+    })
+}
+
+pub(crate) fn window_info(window: &Window) -> xcap::XCapResult<WindowInfoInternal> {
+  let is_minimized = window.is_minimized()?;
+  Ok(WindowInfoInternal {
+    id: window.id()?,
+    title: window.title()?,
+    app_name: window.app_name()?,
+    pid: window.pid()?,
+    x: window.x()?,
+    y: window.y()?,
+    width: window.width()?,
+    height: window.height()?,
+    is_minimized,
+    is_on_screen: !is_minimized,
+  })
+}
+
+/// Windows as reported by XCap. Windows whose properties cannot be read are skipped.
+pub(crate) fn list_windows() -> Result<Vec<WindowInfoInternal>> {
+  Ok(
+    all_windows()?
+      .iter()
+      .filter_map(|w| window_info(w).ok())
+      .collect(),
+  )
+}
+
 impl CaptureBackendImpl for XCapBackend {
   fn start<'a>(
     &'a mut self,
-    tsfn: Option<FrameTsfnType>,
-    fps: u32,
+    sink: Option<FrameSink>,
   ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
     Box::pin(async move {
       if self.running.load(Ordering::SeqCst) {
         return Ok(());
       }
 
+      if let Some(id) = self.options.window_id {
+        find_window(id)?;
+      }
+
       self.running.store(true, Ordering::SeqCst);
       let running = self.running.clone();
+      let options = self.options.clone();
 
       let handle = thread::spawn(move || {
-        let monitors = match Monitor::all() {
-          Ok(m) => m,
+        let events = &options.events;
+        let mut target = match CaptureTarget::find(&options) {
+          Ok(t) => t,
           Err(e) => {
-            eprintln!("Failed to get monitors: {}", e);
+            let code = match options.window_id {
+              Some(_) => ErrorCode::WindowClosed,
+              None => ErrorCode::DisplayLost,
+            };
+            events.fail(code, e.reason);
+            running.store(false, Ordering::SeqCst);
             return;
           }
         };
-
-        if monitors.is_empty() {
-          eprintln!("No monitors found");
-          return;
-        }
-
-        let monitor = &monitors[0];
-        let target_interval = Duration::from_secs_f64(1.0 / fps as f64);
+        // XCap does not report display changes: a `Monitor` keeps the geometry it was created
+        // with, so the monitors are listed again every `POLL_INTERVAL`.
+        let mut watch = match &target {
+          CaptureTarget::Monitor(monitor) => list_displays()
+            .ok()
+            .zip(monitor_info(monitor).ok())
+            .map(|(displays, display)| DisplayWatch::new(&options, displays, display)),
+          CaptureTarget::Window(..) => None,
+        };
+        // Failures repeat on every frame; only the first of a streak is reported.
+        let mut failing = false;
 
         while running.load(Ordering::SeqCst) {
           let start = Instant::now();
-          match monitor.capture_image() {
-            Ok(img) => {
-              if let Some(tsfn) = &tsfn {
-                let width = img.width();
-                let height = img.height();
-                let data = img.into_raw();
-                let stride = width * 4;
-
-                let frame = FrameDataInternal {
-                  width,
-                  height,
-                  stride,
-                  data,
-                };
-
-                let status = tsfn.call(frame, ThreadsafeFunctionCallMode::NonBlocking);
+          let target_interval = options.settings.frame_interval();
+          if options.settings.is_paused() {
+            options.settings.sleep(target_interval);
+            continue;
+          }
+
+          if let Some(watch) = watch.as_mut().filter(|watch| watch.due()) {
+            if let Ok(displays) = list_displays() {
+              match watch.update(displays) {
+                Change::None => {}
+                // Should the monitor be gone already, the next poll reports it lost.
+                Change::Reinit(display) => {
+                  if let Ok(monitor) = find_monitor(Some(display.id)) {
+                    target = CaptureTarget::Monitor(monitor);
+                  }
+                }
+                Change::Lost => {
+                  let id = watch.target().id;
+                  events.fail(
+                    ErrorCode::DisplayLost,
+                    format!("Display {} was disconnected", id),
+                  );
+                  running.store(false, Ordering::SeqCst);
+                  break;
+                }
+              }
+            }
+          }
+
+          match target.capture_frame(&options.region) {
+            Ok(frame) => {
+              failing = false;
+              if let Some(sink) = &sink {
+                let status = sink.send(frame);
                 if status != Status::Ok {
                   break;
                 }
               }
             }
             Err(e) => {
-              eprintln!("Capture failed: {}", e);
+              if let CaptureTarget::Window(id, _) = &target {
+                if target.is_closed() {
+                  // The session ends; frames stop and `start` will reject until the id is valid.
+                  events.fail(ErrorCode::WindowClosed, format!("Window {} was closed", id));
+                  running.store(false, Ordering::SeqCst);
+                  break;
+                }
+              }
+              if !failing {
+                events.warn(ErrorCode::CaptureFailed, format!("Capture failed: {}", e));
+                failing = true;
+              }
               thread::sleep(Duration::from_millis(100));
             }
           }
 
           let elapsed = start.elapsed();
           if elapsed < target_interval {
-            thread::sleep(target_interval - elapsed);
+            options.settings.sleep(target_interval - elapsed);
           }
         }
       });
@@ -115,36 +347,22 @@ impl CaptureBackendImpl for XCapBackend {
     &'a mut self,
   ) -> Pin<Box<dyn Future<Output = Result<FrameDataInternal>> + Send + 'a>> {
     Box::pin(async move {
-      let monitors = Monitor::all().map_err(|e| {
-        Error::new(
-          Status::GenericFailure,
-          format!("Failed to get monitors: {}", e),
-        )
-      })?;
-
-      if monitors.is_empty() {
-        return Err(Error::new(
-          Status::GenericFailure,
-          "No monitors found".to_string(),
-        ));
-      }
-
-      let monitor = &monitors[0];
-      let img = monitor
-        .capture_image()
-        .map_err(|e| Error::new(Status::GenericFailure, format!("Capture failed: {}", e)))?;
-
-      let width = img.width();
-      let height = img.height();
-      let data = img.into_raw();
-      let stride = width * 4;
-
-      Ok(FrameDataInternal {
-        width,
-        height,
-        stride,
-        data,
-      })
+      let target = CaptureTarget::find(&self.options)?;
+      target
+        .capture_frame(&self.options.region)
+        .map_err(|e| Error::new(Status::GenericFailure, format!("Capture failed: {}", e)))
     })
   }
+
+  fn list_displays<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<DisplayInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move { list_displays() })
+  }
+
+  fn list_windows<'a>(
+    &'a mut self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<WindowInfoInternal>>> + Send + 'a>> {
+    Box::pin(async move { list_windows() })
+  }
 }
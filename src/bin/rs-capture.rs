@@ -0,0 +1,601 @@
+//! `rs-capture`, a command-line front end of `Capturer` for scripts and ops: list what can be
+//! captured, take screenshots, record to files or pipe raw frames to another program.
+
+use std::env;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rs_capture::backend::block_on;
+use rs_capture::backend::record::{Recorder, RecordingFormat, RecordingOptions};
+use rs_capture::backend::SharedSettings;
+use rs_capture::capturer::{
+  BackendKind, Backpressure, CaptureError, DisplayInfo, EncodeOptions, Frame, ImageFormat,
+  PixelFormat, Region, WindowInfo,
+};
+use rs_capture::{Capturer, CapturerBuilder, Error, Result, Status};
+
+const USAGE: &str = "\
+Usage: rs-capture <command> [options]
+
+Commands:
+  list-displays              List the displays that can be captured
+  list-windows               List the windows that can be captured
+  screenshot -o <file>       Save one frame as PNG, JPEG or WebP, chosen by the extension
+  record -o <file>           Record to a .y4m file, or raw frames with an .idx index otherwise
+  stream --stdout            Write raw frames to stdout, e.g. for `ffmpeg -f rawvideo`
+
+Options:
+  --backend <name>           screencapturekit, xcap, x11 or synthetic
+  --display <id>             Display to capture, the primary one by default
+  --window <id>              Window to capture instead of a display
+  --region <x,y,width,height>
+                             Only capture this part of the display or window
+  --fps <n>                  Frames per second of record and stream (default 60)
+  --width <n>, --height <n>  Scale frames to this size, keeping the aspect ratio if only one is set
+  --max-width <n>            Scale frames down to at most this width
+  --no-cursor                Leave the pointer out of the frames
+  -o, --output <file>        File written by screenshot and record
+  --quality <1-100>          JPEG quality of screenshot (default 80)
+  --duration <time>          Stop record and stream after e.g. 10s, 500ms or 2m
+  --format <format>          Pixel format of stream: rgba, bgra, rgb, i420 or nv12 (default rgba)
+  --stdout                   Confirm that stream may write binary frames to stdout
+  --json                     Print results as JSON
+  -h, --help                 Show this help";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Command {
+  ListDisplays,
+  ListWindows,
+  Screenshot,
+  Record,
+  Stream,
+}
+
+#[derive(Debug, Default)]
+struct Args {
+  command: Option<Command>,
+  backend: Option<BackendKind>,
+  display: Option<u32>,
+  window: Option<u32>,
+  region: Option<Region>,
+  fps: Option<u32>,
+  width: Option<u32>,
+  height: Option<u32>,
+  max_width: Option<u32>,
+  no_cursor: bool,
+  output: Option<PathBuf>,
+  quality: Option<u8>,
+  duration: Option<Duration>,
+  format: Option<PixelFormat>,
+  stdout: bool,
+  json: bool,
+  help: bool,
+}
+
+/// Why the command line was rejected, printed with a hint to `--help`.
+struct Usage(String);
+
+impl Args {
+  fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, Usage> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+      // Accept both `--fps 30` and `--fps=30`.
+      let (name, inline) = match arg.split_once('=') {
+        Some((name, value)) if name.starts_with("--") => {
+          (name.to_string(), Some(value.to_string()))
+        }
+        _ => (arg, None),
+      };
+      let mut value = || {
+        inline
+          .clone()
+          .or_else(|| args.next())
+          .ok_or_else(|| Usage(format!("{} needs a value", name)))
+      };
+      match name.as_str() {
+        "-h" | "--help" => parsed.help = true,
+        "--json" => parsed.json = true,
+        "--stdout" => parsed.stdout = true,
+        "--no-cursor" => parsed.no_cursor = true,
+        "--backend" => parsed.backend = Some(parse_backend(&value()?)?),
+        "--display" => parsed.display = Some(parse_number(&name, &value()?)?),
+        "--window" => parsed.window = Some(parse_number(&name, &value()?)?),
+        "--region" => parsed.region = Some(parse_region(&value()?)?),
+        "--fps" => parsed.fps = Some(parse_number(&name, &value()?)?),
+        "--width" => parsed.width = Some(parse_number(&name, &value()?)?),
+        "--height" => parsed.height = Some(parse_number(&name, &value()?)?),
+        "--max-width" => parsed.max_width = Some(parse_number(&name, &value()?)?),
+        "-o" | "--output" => parsed.output = Some(PathBuf::from(value()?)),
+        "--quality" => parsed.quality = Some(parse_number(&name, &value()?)?),
+        "--duration" => parsed.duration = Some(parse_duration(&value()?)?),
+        "--format" => parsed.format = Some(parse_format(&value()?)?),
+        option if option.starts_with('-') && option != "-" => {
+          return Err(Usage(format!("Unknown option {}", option)))
+        }
+        command if parsed.command.is_none() => {
+          parsed.command = Some(match command {
+            "list-displays" => Command::ListDisplays,
+            "list-windows" => Command::ListWindows,
+            "screenshot" => Command::Screenshot,
+            "record" => Command::Record,
+            "stream" => Command::Stream,
+            _ => return Err(Usage(format!("Unknown command {}", command))),
+          })
+        }
+        extra => return Err(Usage(format!("Unexpected argument {}", extra))),
+      }
+    }
+    Ok(parsed)
+  }
+
+  /// The session described by the capture options.
+  fn builder(&self) -> CapturerBuilder {
+    let mut builder = CapturerBuilder::new().show_cursor(!self.no_cursor);
+    if let Some(backend) = self.backend {
+      builder = builder.backend(backend);
+    }
+    if let Some(id) = self.display {
+      builder = builder.display(id);
+    }
+    if let Some(id) = self.window {
+      builder = builder.window(id);
+    }
+    if let Some(region) = self.region {
+      builder = builder.region(region);
+    }
+    if let Some(fps) = self.fps {
+      builder = builder.fps(fps);
+    }
+    if let Some(width) = self.width {
+      builder = builder.output_width(width);
+    }
+    if let Some(height) = self.height {
+      builder = builder.output_height(height);
+    }
+    if let Some(width) = self.max_width {
+      builder = builder.max_width(width);
+    }
+    builder
+  }
+
+  fn output(&self) -> Result<&Path, Usage> {
+    self
+      .output
+      .as_deref()
+      .ok_or_else(|| Usage("-o <file> is required".to_string()))
+  }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, Usage> {
+  value
+    .parse()
+    .map_err(|_| Usage(format!("{} must be a positive number, got {}", name, value)))
+}
+
+fn parse_backend(value: &str) -> Result<BackendKind, Usage> {
+  match value.to_ascii_lowercase().as_str() {
+    "screencapturekit" => Ok(BackendKind::ScreenCaptureKit),
+    "xcap" => Ok(BackendKind::XCap),
+    "x11" => Ok(BackendKind::X11),
+    "synthetic" => Ok(BackendKind::Synthetic),
+    _ => Err(Usage(format!("Unknown backend {}", value))),
+  }
+}
+
+fn parse_region(value: &str) -> Result<Region, Usage> {
+  let parts = value
+    .split(',')
+    .map(|part| part.trim().parse::<u32>())
+    .collect::<Result<Vec<_>, _>>();
+  match parts.as_deref() {
+    Ok(&[x, y, width, height]) => Ok(Region {
+      x,
+      y,
+      width,
+      height,
+    }),
+    _ => Err(Usage(format!(
+      "--region must be x,y,width,height, got {}",
+      value
+    ))),
+  }
+}
+
+fn parse_duration(value: &str) -> Result<Duration, Usage> {
+  let split = value
+    .find(|c: char| !c.is_ascii_digit() && c != '.')
+    .unwrap_or(value.len());
+  let (number, unit) = value.split_at(split);
+  let scale = match unit {
+    "ms" => 0.001,
+    "" | "s" => 1.0,
+    "m" => 60.0,
+    "h" => 3600.0,
+    _ => f64::NAN,
+  };
+  match number.parse::<f64>() {
+    Ok(number) if scale.is_finite() && number > 0.0 => Ok(Duration::from_secs_f64(number * scale)),
+    _ => Err(Usage(format!(
+      "--duration must be a time like 10s, 500ms or 2m, got {}",
+      value
+    ))),
+  }
+}
+
+fn parse_format(value: &str) -> Result<PixelFormat, Usage> {
+  match value.to_ascii_lowercase().as_str() {
+    "rgba" => Ok(PixelFormat::Rgba),
+    "bgra" => Ok(PixelFormat::Bgra),
+    "rgb" => Ok(PixelFormat::Rgb),
+    "i420" => Ok(PixelFormat::I420),
+    "nv12" => Ok(PixelFormat::Nv12),
+    _ => Err(Usage(format!("Unknown pixel format {}", value))),
+  }
+}
+
+/// Image format of a screenshot file, from its extension.
+fn image_format(path: &Path) -> Result<ImageFormat, Usage> {
+  let extension = path
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .map(str::to_ascii_lowercase);
+  match extension.as_deref() {
+    Some("png") => Ok(ImageFormat::Png),
+    Some("jpg" | "jpeg") => Ok(ImageFormat::Jpeg),
+    Some("webp") => Ok(ImageFormat::Webp),
+    _ => Err(Usage(format!(
+      "Cannot tell the image format of {}, use .png, .jpg or .webp",
+      path.display()
+    ))),
+  }
+}
+
+fn format_name(format: PixelFormat) -> &'static str {
+  match format {
+    PixelFormat::Rgba => "rgba",
+    PixelFormat::Bgra => "bgra",
+    PixelFormat::Rgb => "rgb",
+    PixelFormat::I420 => "i420",
+    PixelFormat::Nv12 => "nv12",
+  }
+}
+
+/// `value` as a JSON string literal.
+fn json_string(value: &str) -> String {
+  let mut json = String::with_capacity(value.len() + 2);
+  json.push('"');
+  for c in value.chars() {
+    match c {
+      '"' => json.push_str("\\\""),
+      '\\' => json.push_str("\\\\"),
+      '\n' => json.push_str("\\n"),
+      '\r' => json.push_str("\\r"),
+      '\t' => json.push_str("\\t"),
+      c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+      c => json.push(c),
+    }
+  }
+  json.push('"');
+  json
+}
+
+fn display_json(display: &DisplayInfo) -> String {
+  format!(
+    "{{\"id\":{},\"name\":{},\"x\":{},\"y\":{},\"width\":{},\"height\":{},\"scaleFactor\":{},\"rotation\":{},\"isPrimary\":{}}}",
+    display.id,
+    json_string(&display.name),
+    display.x,
+    display.y,
+    display.width,
+    display.height,
+    display.scale_factor,
+    display.rotation,
+    display.is_primary
+  )
+}
+
+fn window_json(window: &WindowInfo) -> String {
+  format!(
+    "{{\"id\":{},\"title\":{},\"appName\":{},\"pid\":{},\"x\":{},\"y\":{},\"width\":{},\"height\":{},\"isMinimized\":{},\"isOnScreen\":{}}}",
+    window.id,
+    json_string(&window.title),
+    json_string(&window.app_name),
+    window.pid,
+    window.x,
+    window.y,
+    window.width,
+    window.height,
+    window.is_minimized,
+    window.is_on_screen
+  )
+}
+
+/// Geometry of the frames written by `stream`, so the reader can split them.
+fn frame_json(frame: &Frame) -> String {
+  format!(
+    "{{\"width\":{},\"height\":{},\"stride\":{},\"format\":{},\"bytes\":{}}}",
+    frame.width,
+    frame.height,
+    frame.stride,
+    json_string(format_name(frame.format)),
+    frame.data.len()
+  )
+}
+
+fn list_displays(args: &Args) -> Result<()> {
+  let displays = block_on(args.builder().list_displays())?;
+  if args.json {
+    let displays = displays.iter().map(display_json).collect::<Vec<_>>();
+    println!("[{}]", displays.join(","));
+    return Ok(());
+  }
+  for display in displays {
+    println!(
+      "{:>4}  {}x{} at {},{}  scale {}{}  {}",
+      display.id,
+      display.width,
+      display.height,
+      display.x,
+      display.y,
+      display.scale_factor,
+      if display.is_primary { "  primary" } else { "" },
+      display.name
+    );
+  }
+  Ok(())
+}
+
+fn list_windows(args: &Args) -> Result<()> {
+  let windows = block_on(args.builder().list_windows())?;
+  if args.json {
+    let windows = windows.iter().map(window_json).collect::<Vec<_>>();
+    println!("[{}]", windows.join(","));
+    return Ok(());
+  }
+  for window in windows {
+    println!(
+      "{:>8}  {}x{} at {},{}  {}: {}",
+      window.id, window.width, window.height, window.x, window.y, window.app_name, window.title
+    );
+  }
+  Ok(())
+}
+
+fn screenshot(args: &Args) -> Result<(), Failure> {
+  let path = args.output()?;
+  let options = EncodeOptions {
+    format: image_format(path)?,
+    quality: args.quality.unwrap_or(EncodeOptions::DEFAULT_QUALITY),
+  };
+  let capturer = args.builder().encode(options).build()?;
+  let frame = block_on(capturer.screenshot())?;
+  fs::write(path, &frame.data).map_err(|e| {
+    Error::new(
+      Status::GenericFailure,
+      format!("Failed to write {}: {}", path.display(), e),
+    )
+  })?;
+
+  if args.json {
+    println!(
+      "{{\"path\":{},\"width\":{},\"height\":{},\"bytes\":{}}}",
+      json_string(&path.to_string_lossy()),
+      frame.width,
+      frame.height,
+      frame.data.len()
+    );
+  } else {
+    println!(
+      "{}x{} screenshot saved to {}",
+      frame.width,
+      frame.height,
+      path.display()
+    );
+  }
+  Ok(())
+}
+
+/// Starts `capturer`, and stops it after `duration` when given. Fatal capture errors end the
+/// session; they are returned by the function this returns once the frames ran out.
+fn run_session(
+  capturer: Capturer,
+  duration: Option<Duration>,
+) -> Result<(Arc<Capturer>, impl Fn() -> Result<()>)> {
+  let fatal = Arc::new(Mutex::new(None::<CaptureError>));
+  let failed = fatal.clone();
+  capturer.on_error(move |e| {
+    if e.fatal {
+      *failed.lock().unwrap() = Some(e.clone());
+    } else {
+      eprintln!("rs-capture: warning: {}", e.message);
+    }
+  });
+  block_on(capturer.start())?;
+
+  let capturer = Arc::new(capturer);
+  if let Some(duration) = duration {
+    let capturer = capturer.clone();
+    thread::spawn(move || {
+      thread::sleep(duration);
+      let _ = capturer.stop();
+    });
+  }
+  let finished = move || match fatal.lock().unwrap().take() {
+    Some(e) => Err(Error::new(Status::GenericFailure, e.message)),
+    None => Ok(()),
+  };
+  Ok((capturer, finished))
+}
+
+fn record(args: &Args) -> Result<(), Failure> {
+  let path = args.output()?;
+  let duration = args
+    .duration
+    .ok_or_else(|| Usage("record needs --duration".to_string()))?;
+  let fps = args.fps.unwrap_or(SharedSettings::DEFAULT_FPS);
+  let mut recorder = Recorder::create(
+    path,
+    RecordingOptions {
+      format: RecordingFormat::from_path(path),
+      fps,
+    },
+  )?;
+
+  // Files have no deadline: wait for the disk instead of losing frames.
+  let capturer = args
+    .builder()
+    .fps(fps)
+    .backpressure(Backpressure::Block)
+    .build()?;
+  let frames = capturer.frames();
+  let (capturer, finished) = run_session(capturer, Some(duration))?;
+  while let Some(frame) = frames.blocking_next() {
+    if let Err(e) = recorder.write(&frame) {
+      let _ = capturer.stop();
+      return Err(e.into());
+    }
+  }
+  let summary = recorder.finish()?;
+  finished()?;
+
+  if args.json {
+    println!(
+      "{{\"path\":{},\"frames\":{},\"repeatedFrames\":{},\"skippedFrames\":{},\"durationMs\":{}}}",
+      json_string(&path.to_string_lossy()),
+      summary.frames,
+      summary.repeated_frames,
+      summary.skipped_frames,
+      summary.duration.as_millis()
+    );
+  } else {
+    println!(
+      "{} frames ({:.1}s) recorded to {}",
+      summary.frames,
+      summary.duration.as_secs_f64(),
+      path.display()
+    );
+  }
+  Ok(())
+}
+
+/// Writes every frame to stdout, back to back without headers. Each time the frame geometry
+/// changes it is described on stderr, as a JSON line with `--json`.
+fn stream(args: &Args) -> Result<(), Failure> {
+  if !args.stdout {
+    return Err(
+      Usage("stream writes binary frames to stdout, confirm with --stdout".to_string()).into(),
+    );
+  }
+  let format = args.format.unwrap_or_default();
+  let capturer = args.builder().pixel_format(format).build()?;
+  let frames = capturer.frames();
+  let (capturer, finished) = run_session(capturer, args.duration)?;
+
+  let mut stdout = io::stdout().lock();
+  let mut geometry = None;
+  let mut written = 0u64;
+  while let Some(frame) = frames.blocking_next() {
+    if geometry != Some((frame.width, frame.height, frame.stride)) {
+      geometry = Some((frame.width, frame.height, frame.stride));
+      if args.json {
+        eprintln!("{}", frame_json(&frame));
+      } else {
+        eprintln!(
+          "rs-capture: {}x{} {} frames, {} bytes each",
+          frame.width,
+          frame.height,
+          format_name(frame.format),
+          frame.data.len()
+        );
+      }
+    }
+    match stdout.write_all(&frame.data).and_then(|()| stdout.flush()) {
+      Ok(()) => written += 1,
+      // The reader went away, e.g. `head` or a closed player: that ends the stream.
+      Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+        let _ = capturer.stop();
+        break;
+      }
+      Err(e) => {
+        let _ = capturer.stop();
+        return Err(
+          Error::new(
+            Status::GenericFailure,
+            format!("Failed to write frames: {}", e),
+          )
+          .into(),
+        );
+      }
+    }
+  }
+  finished()?;
+
+  let dropped = capturer.stats().frames_dropped;
+  if args.json {
+    eprintln!("{{\"frames\":{},\"droppedFrames\":{}}}", written, dropped);
+  } else {
+    eprintln!("rs-capture: {} frames streamed", written);
+  }
+  Ok(())
+}
+
+/// Why a command failed: a rejected command line or an error of the capture.
+enum Failure {
+  Usage(Usage),
+  Capture(Error),
+}
+
+impl From<Usage> for Failure {
+  fn from(usage: Usage) -> Self {
+    Self::Usage(usage)
+  }
+}
+
+impl From<Error> for Failure {
+  fn from(error: Error) -> Self {
+    Self::Capture(error)
+  }
+}
+
+fn run(args: &Args) -> Result<(), Failure> {
+  match args.command {
+    Some(Command::ListDisplays) => Ok(list_displays(args)?),
+    Some(Command::ListWindows) => Ok(list_windows(args)?),
+    Some(Command::Screenshot) => screenshot(args),
+    Some(Command::Record) => record(args),
+    Some(Command::Stream) => stream(args),
+    None => Err(Usage("Missing command".to_string()).into()),
+  }
+}
+
+fn main() -> ExitCode {
+  let result = Args::parse(env::args().skip(1))
+    .map_err(Failure::from)
+    .and_then(|args| {
+      if args.help {
+        println!("{}", USAGE);
+        Ok(())
+      } else {
+        run(&args)
+      }
+    });
+  match result {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(Failure::Usage(Usage(reason))) => {
+      eprintln!(
+        "rs-capture: {}\nTry `rs-capture --help` for the usage.",
+        reason
+      );
+      ExitCode::from(2)
+    }
+    Err(Failure::Capture(e)) => {
+      eprintln!("rs-capture: {}", e.reason);
+      ExitCode::FAILURE
+    }
+  }
+}
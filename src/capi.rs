@@ -0,0 +1,457 @@
+//! C ABI over `Capturer`, for hosts without Node.js or Rust. `include/rs_capture.h` declares it
+//! and is generated from this file with `pnpm build:header`.
+//!
+//! Every `RsCaptureFrame` handed out, by `rs_capture_screenshot` or to the frame callback,
+//! belongs to the caller until it is passed to `rs_capture_frame_release`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CString};
+use std::ptr;
+use std::sync::Mutex as StdMutex;
+use std::thread::{self, JoinHandle};
+
+use crate::backend::block_on;
+use crate::capturer::{
+  BackendKind, Backpressure, Capturer, CapturerBuilder, Frame, PixelFormat, Region,
+  SyntheticOptions,
+};
+use crate::error::{Error, Result, Status};
+
+/// Version of the C ABI. Changes whenever a type or function changes incompatibly.
+pub const RS_CAPTURE_ABI_VERSION: u32 = 1;
+
+pub const RS_CAPTURE_BACKEND_AUTO: u32 = 0;
+pub const RS_CAPTURE_BACKEND_SCREEN_CAPTURE_KIT: u32 = 1;
+pub const RS_CAPTURE_BACKEND_XCAP: u32 = 2;
+pub const RS_CAPTURE_BACKEND_SYNTHETIC: u32 = 3;
+pub const RS_CAPTURE_BACKEND_X11: u32 = 4;
+
+pub const RS_CAPTURE_PIXEL_FORMAT_RGBA: u32 = 0;
+pub const RS_CAPTURE_PIXEL_FORMAT_BGRA: u32 = 1;
+pub const RS_CAPTURE_PIXEL_FORMAT_RGB: u32 = 2;
+pub const RS_CAPTURE_PIXEL_FORMAT_I420: u32 = 3;
+pub const RS_CAPTURE_PIXEL_FORMAT_NV12: u32 = 4;
+
+pub const RS_CAPTURE_BACKPRESSURE_DROP_OLDEST: u32 = 0;
+pub const RS_CAPTURE_BACKPRESSURE_DROP_NEWEST: u32 = 1;
+pub const RS_CAPTURE_BACKPRESSURE_BLOCK: u32 = 2;
+
+/// Result of the functions that can fail. `rs_capture_last_error` describes the failure.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RsCaptureStatus {
+  Ok = 0,
+  InvalidArg = 1,
+  GenericFailure = 2,
+  Closing = 3,
+}
+
+impl From<Status> for RsCaptureStatus {
+  fn from(status: Status) -> Self {
+    match status {
+      Status::Ok => Self::Ok,
+      Status::InvalidArg => Self::InvalidArg,
+      Status::GenericFailure => Self::GenericFailure,
+      Status::Closing => Self::Closing,
+    }
+  }
+}
+
+/// Rectangle relative to the top-left corner of the captured display or window.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RsCaptureRegion {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+}
+
+/// What the synthetic backend generates. Fields left at 0 keep their default.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RsCaptureSyntheticConfig {
+  pub width: u32,
+  pub height: u32,
+  pub displays: u32,
+  pub windows: u32,
+}
+
+/// Settings of a capture session. A zeroed config captures the primary display with the
+/// platform's default backend at 60 fps; fields left at 0 keep their default.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RsCaptureConfig {
+  /// One of `RS_CAPTURE_BACKEND_*`.
+  pub backend: u32,
+  pub fps: u32,
+  /// Capture the display `display_id` instead of the primary one.
+  pub has_display_id: bool,
+  pub display_id: u32,
+  /// Window to capture instead of a display.
+  pub window_id: u32,
+  /// Only capture this part of the display or window. A zero width and height capture all of it.
+  pub region: RsCaptureRegion,
+  /// Scale frames to this size. When only one side is set the other follows the aspect ratio.
+  pub output_width: u32,
+  pub output_height: u32,
+  /// Scale frames down to at most this width, keeping the aspect ratio.
+  pub max_width: u32,
+  /// One of `RS_CAPTURE_PIXEL_FORMAT_*`.
+  pub pixel_format: u32,
+  /// One of `RS_CAPTURE_BACKPRESSURE_*`: what happens to new frames while `max_queued_frames`
+  /// frames wait for the callback. The default drops the oldest one.
+  pub backpressure: u32,
+  /// Frames that may wait for the callback. 0 keeps the default of 4.
+  pub max_queued_frames: u32,
+  /// Leave the pointer out of the frames.
+  pub hide_cursor: bool,
+  pub synthetic: RsCaptureSyntheticConfig,
+}
+
+/// A captured frame. `data` holds `data_len` bytes laid out as `format`, rows `stride` bytes
+/// apart for packed formats.
+#[repr(C)]
+pub struct RsCaptureFrame {
+  pub width: u32,
+  pub height: u32,
+  pub stride: u32,
+  /// One of `RS_CAPTURE_PIXEL_FORMAT_*`.
+  pub format: u32,
+  pub data: *const u8,
+  pub data_len: usize,
+  /// When the frame was captured, in microseconds on a monotonic clock.
+  pub timestamp_us: u64,
+  /// Position in the capture session, starting at 0.
+  pub sequence: u64,
+  /// Whether the frame shows the single display `display_id`. False for window captures.
+  pub has_display_id: bool,
+  pub display_id: u32,
+  /// Frames dropped by the backpressure policy just before this one.
+  pub dropped_since_last: u64,
+}
+
+impl From<Frame> for RsCaptureFrame {
+  fn from(frame: Frame) -> Self {
+    let data = Box::into_raw(frame.data.into_boxed_slice());
+    Self {
+      width: frame.width,
+      height: frame.height,
+      stride: frame.stride,
+      format: match frame.format {
+        PixelFormat::Rgba => RS_CAPTURE_PIXEL_FORMAT_RGBA,
+        PixelFormat::Bgra => RS_CAPTURE_PIXEL_FORMAT_BGRA,
+        PixelFormat::Rgb => RS_CAPTURE_PIXEL_FORMAT_RGB,
+        PixelFormat::I420 => RS_CAPTURE_PIXEL_FORMAT_I420,
+        PixelFormat::Nv12 => RS_CAPTURE_PIXEL_FORMAT_NV12,
+      },
+      data: data as *const u8,
+      data_len: data.len(),
+      timestamp_us: frame.timestamp.as_micros() as u64,
+      sequence: frame.sequence,
+      has_display_id: frame.display_id.is_some(),
+      display_id: frame.display_id.unwrap_or(0),
+      dropped_since_last: frame.dropped_since_last,
+    }
+  }
+}
+
+/// Called with every frame, in order, on a delivery thread of the session. Frames wait for it
+/// in a queue handled by the `backpressure` setting, so a slow callback does not slow the
+/// capture down. The frame belongs to the callback, which must pass it to
+/// `rs_capture_frame_release` once done with it, possibly on another thread.
+pub type RsCaptureFrameCallback =
+  Option<unsafe extern "C" fn(frame: *mut RsCaptureFrame, user_data: *mut c_void)>;
+
+/// A capture session.
+pub struct RsCapture {
+  capturer: Capturer,
+  callback: Option<Callback>,
+  /// Thread passing the frames of the running session to `callback`.
+  delivery: StdMutex<Option<JoinHandle<()>>>,
+}
+
+/// The frame callback and its `user_data`, which the host promises may be used from the
+/// delivery thread.
+#[derive(Clone, Copy)]
+struct Callback {
+  call: unsafe extern "C" fn(frame: *mut RsCaptureFrame, user_data: *mut c_void),
+  user_data: *mut c_void,
+}
+
+unsafe impl Send for Callback {}
+
+impl Callback {
+  // A method, so closures capture the whole `Callback` rather than its pointer field.
+  fn deliver(&self, frame: Frame) {
+    let frame = Box::into_raw(Box::new(RsCaptureFrame::from(frame)));
+    unsafe { (self.call)(frame, self.user_data) };
+  }
+}
+
+impl RsCapture {
+  fn start(&self) -> Result<()> {
+    if let Some(callback) = self.callback {
+      let mut delivery = self.delivery.lock().unwrap();
+      // The thread of a session that failed has ended on its own.
+      if delivery.as_ref().is_none_or(JoinHandle::is_finished) {
+        let frames = self.capturer.frames();
+        *delivery = Some(thread::spawn(move || {
+          while let Some(frame) = frames.blocking_next() {
+            callback.deliver(frame);
+          }
+        }));
+      }
+    }
+    let result = block_on(self.capturer.start());
+    if result.is_err() {
+      self.join_delivery();
+    }
+    result
+  }
+
+  fn stop(&self) -> Result<()> {
+    let result = self.capturer.stop();
+    self.join_delivery();
+    result
+  }
+
+  /// Waits for the delivery thread, which ends once the session stopped and the frames still
+  /// queued were delivered.
+  fn join_delivery(&self) {
+    let delivery = self.delivery.lock().unwrap().take();
+    if let Some(delivery) = delivery {
+      let _ = delivery.join();
+    }
+  }
+}
+
+impl Drop for RsCapture {
+  fn drop(&mut self) {
+    let _ = self.stop();
+  }
+}
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Records `e` for `rs_capture_last_error` and turns it into a status.
+fn fail(e: Error) -> RsCaptureStatus {
+  let reason = CString::new(e.reason.replace('\0', " ")).unwrap_or_default();
+  LAST_ERROR.with(|last| *last.borrow_mut() = Some(reason));
+  e.status.into()
+}
+
+fn report(result: Result<()>) -> RsCaptureStatus {
+  result.map_or_else(fail, |()| RsCaptureStatus::Ok)
+}
+
+fn invalid(reason: &str) -> Error {
+  Error::new(Status::InvalidArg, reason.to_string())
+}
+
+fn builder(config: &RsCaptureConfig) -> Result<CapturerBuilder> {
+  let mut builder = CapturerBuilder::new();
+  builder = match config.backend {
+    RS_CAPTURE_BACKEND_AUTO => builder,
+    RS_CAPTURE_BACKEND_SCREEN_CAPTURE_KIT => builder.backend(BackendKind::ScreenCaptureKit),
+    RS_CAPTURE_BACKEND_XCAP => builder.backend(BackendKind::XCap),
+    RS_CAPTURE_BACKEND_SYNTHETIC => builder.backend(BackendKind::Synthetic),
+    RS_CAPTURE_BACKEND_X11 => builder.backend(BackendKind::X11),
+    backend => return Err(invalid(&format!("Unknown backend {}", backend))),
+  };
+  builder = builder.pixel_format(match config.pixel_format {
+    RS_CAPTURE_PIXEL_FORMAT_RGBA => PixelFormat::Rgba,
+    RS_CAPTURE_PIXEL_FORMAT_BGRA => PixelFormat::Bgra,
+    RS_CAPTURE_PIXEL_FORMAT_RGB => PixelFormat::Rgb,
+    RS_CAPTURE_PIXEL_FORMAT_I420 => PixelFormat::I420,
+    RS_CAPTURE_PIXEL_FORMAT_NV12 => PixelFormat::Nv12,
+    format => return Err(invalid(&format!("Unknown pixel format {}", format))),
+  });
+  builder = builder.backpressure(match config.backpressure {
+    RS_CAPTURE_BACKPRESSURE_DROP_OLDEST => Backpressure::DropOldest,
+    RS_CAPTURE_BACKPRESSURE_DROP_NEWEST => Backpressure::DropNewest,
+    RS_CAPTURE_BACKPRESSURE_BLOCK => Backpressure::Block,
+    backpressure => return Err(invalid(&format!("Unknown backpressure {}", backpressure))),
+  });
+
+  if config.fps != 0 {
+    builder = builder.fps(config.fps);
+  }
+  if config.has_display_id {
+    builder = builder.display(config.display_id);
+  }
+  if config.window_id != 0 {
+    builder = builder.window(config.window_id);
+  }
+  let region = config.region;
+  if region.width != 0 || region.height != 0 {
+    builder = builder.region(Region {
+      x: region.x,
+      y: region.y,
+      width: region.width,
+      height: region.height,
+    });
+  }
+  if config.output_width != 0 {
+    builder = builder.output_width(config.output_width);
+  }
+  if config.output_height != 0 {
+    builder = builder.output_height(config.output_height);
+  }
+  if config.max_width != 0 {
+    builder = builder.max_width(config.max_width);
+  }
+  if config.max_queued_frames != 0 {
+    builder = builder.max_queued_frames(config.max_queued_frames as usize);
+  }
+
+  let synthetic = config.synthetic;
+  let defaults = SyntheticOptions::default();
+  let or_default = |value: u32, default: u32| if value == 0 { default } else { value };
+  Ok(
+    builder
+      .show_cursor(!config.hide_cursor)
+      .synthetic(SyntheticOptions {
+        width: or_default(synthetic.width, defaults.width),
+        height: or_default(synthetic.height, defaults.height),
+        displays: or_default(synthetic.displays, defaults.displays),
+        windows: synthetic.windows,
+        ..defaults
+      }),
+  )
+}
+
+/// Version of the C ABI the library implements, `RS_CAPTURE_ABI_VERSION` when it matches the
+/// header.
+#[no_mangle]
+pub extern "C" fn rs_capture_abi_version() -> u32 {
+  RS_CAPTURE_ABI_VERSION
+}
+
+/// Describes the last failure on the calling thread, or returns NULL. The string stays valid
+/// until the next failing call on the thread.
+#[no_mangle]
+pub extern "C" fn rs_capture_last_error() -> *const c_char {
+  LAST_ERROR.with(|last| {
+    last
+      .borrow()
+      .as_ref()
+      .map_or(ptr::null(), |reason| reason.as_ptr())
+  })
+}
+
+/// Sets up a capture session without starting it and stores it in `*out`. `config` may be NULL
+/// for the defaults. Without `callback` frames are only captured by `rs_capture_screenshot`.
+///
+/// # Safety
+///
+/// `config` must be NULL or point to a valid `RsCaptureConfig`, and `out` to writable memory.
+/// `user_data` is passed to `callback` from the delivery thread until the session is destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn rs_capture_create(
+  config: *const RsCaptureConfig,
+  callback: RsCaptureFrameCallback,
+  user_data: *mut c_void,
+  out: *mut *mut RsCapture,
+) -> RsCaptureStatus {
+  if out.is_null() {
+    return fail(invalid("out must not be NULL"));
+  }
+  *out = ptr::null_mut();
+  let config = config.as_ref().copied().unwrap_or_default();
+  match builder(&config).and_then(CapturerBuilder::build) {
+    Ok(capturer) => {
+      *out = Box::into_raw(Box::new(RsCapture {
+        capturer,
+        callback: callback.map(|call| Callback { call, user_data }),
+        delivery: StdMutex::new(None),
+      }));
+      RsCaptureStatus::Ok
+    }
+    Err(e) => fail(e),
+  }
+}
+
+/// Starts capturing, blocking until the backend delivers frames or failed.
+///
+/// # Safety
+///
+/// `capture` must come from `rs_capture_create` and not be destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn rs_capture_start(capture: *mut RsCapture) -> RsCaptureStatus {
+  match capture.as_ref() {
+    Some(capture) => report(capture.start()),
+    None => fail(invalid("capture must not be NULL")),
+  }
+}
+
+/// Stops capturing. Frames still queued for the callback are delivered first; once it returns
+/// the callback is not called again.
+///
+/// # Safety
+///
+/// `capture` must come from `rs_capture_create` and not be destroyed, and the function must not
+/// be called from the callback.
+#[no_mangle]
+pub unsafe extern "C" fn rs_capture_stop(capture: *mut RsCapture) -> RsCaptureStatus {
+  match capture.as_ref() {
+    Some(capture) => report(capture.stop()),
+    None => fail(invalid("capture must not be NULL")),
+  }
+}
+
+/// Captures a single frame with the output settings of the session and stores it in `*out`.
+///
+/// # Safety
+///
+/// `capture` must come from `rs_capture_create` and not be destroyed, and `out` must point to
+/// writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn rs_capture_screenshot(
+  capture: *mut RsCapture,
+  out: *mut *mut RsCaptureFrame,
+) -> RsCaptureStatus {
+  let (Some(capture), false) = (capture.as_ref(), out.is_null()) else {
+    return fail(invalid("capture and out must not be NULL"));
+  };
+  *out = ptr::null_mut();
+  match block_on(capture.capturer.screenshot()) {
+    Ok(frame) => {
+      *out = Box::into_raw(Box::new(RsCaptureFrame::from(frame)));
+      RsCaptureStatus::Ok
+    }
+    Err(e) => fail(e),
+  }
+}
+
+/// Frees a frame and its pixels. NULL is ignored.
+///
+/// # Safety
+///
+/// `frame` must be NULL or a frame handed out by the library that was not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn rs_capture_frame_release(frame: *mut RsCaptureFrame) {
+  if frame.is_null() {
+    return;
+  }
+  let frame = Box::from_raw(frame);
+  let data = ptr::slice_from_raw_parts_mut(frame.data as *mut u8, frame.data_len);
+  drop(Box::from_raw(data));
+}
+
+/// Stops the session if it is running and frees it. NULL is ignored. Frames handed out before
+/// stay valid until released.
+///
+/// # Safety
+///
+/// `capture` must be NULL or come from `rs_capture_create`, and not be used afterwards. The
+/// function must not be called from the callback.
+#[no_mangle]
+pub unsafe extern "C" fn rs_capture_destroy(capture: *mut RsCapture) {
+  if capture.is_null() {
+    return;
+  }
+  drop(Box::from_raw(capture));
+}
@@ -0,0 +1,1066 @@
+//! Capture sessions for Rust programs, without Node.js. `Capturer` is the session the
+//! `ScreenCapture` class of the bindings wraps: configure it with `CapturerBuilder`, then take
+//! frames from the `on_frame` callback or from `frames()`.
+
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use crate::backend::clip::Clip;
+use crate::backend::dirty::ChangeOptions;
+use crate::backend::encode;
+use crate::backend::events::EventSink;
+#[cfg(target_os = "linux")]
+use crate::backend::linux::LinuxBackend;
+#[cfg(target_os = "macos")]
+use crate::backend::macos::SCKBackend;
+use crate::backend::multi::{Layout, MultiDisplayBackend};
+use crate::backend::output::{OutputOptions, ScaleOptions};
+use crate::backend::pixel::YuvOptions;
+use crate::backend::record::{Recorder, Recording};
+use crate::backend::stream::{FrameQueue, QueueOptions};
+use crate::backend::synthetic::SyntheticBackend;
+#[cfg(target_os = "windows")]
+use crate::backend::windows::WindowsBackend;
+#[cfg(target_os = "linux")]
+use crate::backend::x11::X11Backend;
+use crate::backend::xcap::XCapBackend;
+use crate::backend::{
+  display_not_found, window_not_found, CaptureBackendImpl, CaptureOptions, FrameCallback,
+  FrameSink, SharedRegion, SharedSettings,
+};
+use crate::error::{Error, Result, Status};
+
+pub use crate::backend::clip::{ClipFormat, ClipOptions};
+pub use crate::backend::cursor::CursorInternal as Cursor;
+pub use crate::backend::delta::{DeltaOptions, TileCompression};
+pub use crate::backend::encode::{EncodeOptions, ImageFormat};
+pub use crate::backend::events::{
+  CaptureErrorInternal as CaptureError, CaptureState, DisplayChangeInternal as DisplayChange,
+  DisplayChangeKind, ErrorCode,
+};
+pub use crate::backend::output::ScaleFilter;
+pub use crate::backend::pixel::{ColorMatrix, ColorRange, PixelFormat};
+pub use crate::backend::record::{RecordingFormat, RecordingOptions, RecordingSummary};
+pub use crate::backend::replay::ReplayOptions;
+pub use crate::backend::stream::{Backpressure, FrameStream};
+pub use crate::backend::synthetic::SyntheticOptions;
+pub use crate::backend::{
+  CaptureStatsInternal as CaptureStats, DisplayInfoInternal as DisplayInfo,
+  FrameDataInternal as Frame, RegionInternal as Region, WindowInfoInternal as WindowInfo,
+};
+
+/// Capture backend of a session. Without one the platform default is used: ScreenCaptureKit
+/// on macOS, DXGI on Windows and X11 on Linux X11 sessions, falling back to XCap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+  /// XCap on other platforms.
+  ScreenCaptureKit,
+  XCap,
+  /// Generated frames for tests, configured with `CapturerBuilder::synthetic`.
+  Synthetic,
+  /// XCap on other platforms and for window captures.
+  X11,
+}
+
+/// How long `Capturer::export_clip` waits for the first frame beyond the clip length.
+const CLIP_START_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Settings of a `Capturer`. They are checked by `build`.
+pub struct CapturerBuilder {
+  backend: Option<BackendKind>,
+  synthetic: Option<SyntheticOptions>,
+  fps: u32,
+  display_id: Option<u32>,
+  window_id: Option<u32>,
+  display_ids: Option<Vec<u32>>,
+  stitch: bool,
+  region: Option<Region>,
+  output_width: Option<u32>,
+  output_height: Option<u32>,
+  max_width: Option<u32>,
+  scale_filter: ScaleFilter,
+  pixel_format: Option<PixelFormat>,
+  color_matrix: ColorMatrix,
+  color_range: ColorRange,
+  encode: Option<EncodeOptions>,
+  backpressure: Backpressure,
+  max_queued_frames: usize,
+  replay: Option<ReplayOptions>,
+  dirty_rects: bool,
+  emit_only_on_change: bool,
+  keepalive: Option<Duration>,
+  delta: Option<DeltaOptions>,
+  show_cursor: bool,
+  cursor_metadata: bool,
+  on_frame: Option<Box<dyn FnMut(Frame) + Send>>,
+}
+
+impl Default for CapturerBuilder {
+  fn default() -> Self {
+    Self {
+      backend: None,
+      synthetic: None,
+      fps: SharedSettings::DEFAULT_FPS,
+      display_id: None,
+      window_id: None,
+      display_ids: None,
+      stitch: false,
+      region: None,
+      output_width: None,
+      output_height: None,
+      max_width: None,
+      scale_filter: ScaleFilter::default(),
+      pixel_format: None,
+      color_matrix: ColorMatrix::default(),
+      color_range: ColorRange::default(),
+      encode: None,
+      backpressure: Backpressure::default(),
+      max_queued_frames: QueueOptions::DEFAULT_MAX_QUEUED_FRAMES,
+      replay: None,
+      dirty_rects: false,
+      emit_only_on_change: false,
+      keepalive: None,
+      delta: None,
+      show_cursor: true,
+      cursor_metadata: false,
+      on_frame: None,
+    }
+  }
+}
+
+impl CapturerBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn backend(mut self, backend: BackendKind) -> Self {
+    self.backend = Some(backend);
+    self
+  }
+
+  /// What the `Synthetic` backend generates.
+  pub fn synthetic(mut self, options: SyntheticOptions) -> Self {
+    self.synthetic = Some(options);
+    self
+  }
+
+  pub fn fps(mut self, fps: u32) -> Self {
+    self.fps = fps;
+    self
+  }
+
+  /// Display to capture, as returned by `list_displays`. Defaults to the primary display.
+  pub fn display(mut self, id: u32) -> Self {
+    self.display_id = Some(id);
+    self
+  }
+
+  /// Window to capture instead of a display, as returned by `list_windows`.
+  pub fn window(mut self, id: u32) -> Self {
+    self.window_id = Some(id);
+    self
+  }
+
+  /// Capture several displays in one session. Each frame shows one display, named by
+  /// `Frame::display_id`, unless `stitch` is set.
+  pub fn displays(mut self, ids: impl IntoIterator<Item = u32>) -> Self {
+    self.display_ids = Some(ids.into_iter().collect());
+    self
+  }
+
+  /// Deliver the displays of `displays` as one frame laid out like the virtual desktop.
+  pub fn stitch(mut self, stitch: bool) -> Self {
+    self.stitch = stitch;
+    self
+  }
+
+  /// Only capture this part of the display or window.
+  pub fn region(mut self, region: Region) -> Self {
+    self.region = Some(region);
+    self
+  }
+
+  /// Scale frames to this width. When only one of the output sizes is set the other follows
+  /// the aspect ratio.
+  pub fn output_width(mut self, width: u32) -> Self {
+    self.output_width = Some(width);
+    self
+  }
+
+  pub fn output_height(mut self, height: u32) -> Self {
+    self.output_height = Some(height);
+    self
+  }
+
+  /// Scale frames down to at most this width, keeping the aspect ratio.
+  pub fn max_width(mut self, width: u32) -> Self {
+    self.max_width = Some(width);
+    self
+  }
+
+  pub fn scale_filter(mut self, filter: ScaleFilter) -> Self {
+    self.scale_filter = filter;
+    self
+  }
+
+  /// Layout of delivered frames. Cannot be combined with `encode`.
+  pub fn pixel_format(mut self, format: PixelFormat) -> Self {
+    self.pixel_format = Some(format);
+    self
+  }
+
+  pub fn color_matrix(mut self, matrix: ColorMatrix) -> Self {
+    self.color_matrix = matrix;
+    self
+  }
+
+  pub fn color_range(mut self, range: ColorRange) -> Self {
+    self.color_range = range;
+    self
+  }
+
+  /// Deliver compressed images instead of raw pixels.
+  pub fn encode(mut self, options: EncodeOptions) -> Self {
+    self.encode = Some(options);
+    self
+  }
+
+  /// What to do with new frames while `frames()` streams are behind.
+  pub fn backpressure(mut self, backpressure: Backpressure) -> Self {
+    self.backpressure = backpressure;
+    self
+  }
+
+  /// Frames that may wait for each consumer before `backpressure` applies.
+  pub fn max_queued_frames(mut self, frames: usize) -> Self {
+    self.max_queued_frames = frames;
+    self
+  }
+
+  /// Keep the last frames of the session in memory, see `Capturer::replay_frames`.
+  pub fn replay(mut self, options: ReplayOptions) -> Self {
+    self.replay = Some(options);
+    self
+  }
+
+  /// Report the parts of each frame that changed in `Frame::dirty_rects`.
+  pub fn dirty_rects(mut self, enabled: bool) -> Self {
+    self.dirty_rects = enabled;
+    self
+  }
+
+  /// Only deliver frames whose content changed.
+  pub fn emit_only_on_change(mut self, enabled: bool) -> Self {
+    self.emit_only_on_change = enabled;
+    self
+  }
+
+  /// With `emit_only_on_change`, repeat the last frame when none was delivered for this long.
+  pub fn keepalive(mut self, interval: Duration) -> Self {
+    self.keepalive = Some(interval);
+    self
+  }
+
+  /// Deliver delta packets of the changed tiles instead of frames. Cannot be combined with
+  /// `pixel_format` or `encode`.
+  pub fn delta(mut self, options: DeltaOptions) -> Self {
+    self.delta = Some(options);
+    self
+  }
+
+  /// Include the pointer in the frames (default true).
+  pub fn show_cursor(mut self, show: bool) -> Self {
+    self.show_cursor = show;
+    self
+  }
+
+  /// Report the pointer position and shape in `Frame::cursor`.
+  pub fn cursor_metadata(mut self, enabled: bool) -> Self {
+    self.cursor_metadata = enabled;
+    self
+  }
+
+  /// Calls `on_frame` with every frame, on the capture thread: a slow callback slows the
+  /// capture down instead of queueing frames.
+  pub fn on_frame(mut self, on_frame: impl FnMut(Frame) + Send + 'static) -> Self {
+    self.on_frame = Some(Box::new(on_frame));
+    self
+  }
+
+  /// Checks the settings and sets up the backend without starting it.
+  pub fn build(self) -> Result<Capturer> {
+    let options = self.options()?;
+    let backend = self.create_backend(options.clone())?;
+    let callback = self.on_frame.map(|on_frame| {
+      let queue = FrameQueue::new(options.queue);
+      let pending = queue.clone();
+      let on_frame = StdMutex::new(on_frame);
+      let notify = move || {
+        if let Some(frame) = pending.pop() {
+          (on_frame.lock().unwrap())(frame);
+        }
+        Status::Ok
+      };
+      FrameCallback {
+        notify: Box::new(notify),
+        queue,
+      }
+    });
+    Ok(Capturer::new(backend, options, callback))
+  }
+
+  /// Lists the displays available to the configured backend.
+  pub fn list_displays(&self) -> impl Future<Output = Result<Vec<DisplayInfo>>> + Send + 'static {
+    let backend = self
+      .options()
+      .and_then(|options| self.create_backend(options));
+    async move { backend?.list_displays().await }
+  }
+
+  /// Lists the windows that can be captured with `window`.
+  pub fn list_windows(&self) -> impl Future<Output = Result<Vec<WindowInfo>>> + Send + 'static {
+    let backend = self
+      .options()
+      .and_then(|options| self.create_backend(options));
+    async move { backend?.list_windows().await }
+  }
+
+  pub(crate) fn options(&self) -> Result<CaptureOptions> {
+    let (display_id, window_id) = (self.display_id, self.window_id);
+    if display_id.is_some() && window_id.is_some() {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "displayId and windowId cannot be used together".to_string(),
+      ));
+    }
+    let (display_id, displays) = match &self.display_ids {
+      None if self.stitch => {
+        return Err(Error::new(
+          Status::InvalidArg,
+          "stitch can only be used with displayIds".to_string(),
+        ))
+      }
+      None => (display_id, Vec::new()),
+      Some(_) if display_id.is_some() || window_id.is_some() => {
+        return Err(Error::new(
+          Status::InvalidArg,
+          "displayIds cannot be used with displayId or windowId".to_string(),
+        ))
+      }
+      Some(ids) => {
+        if ids.is_empty() {
+          return Err(Error::new(
+            Status::InvalidArg,
+            "displayIds must not be empty".to_string(),
+          ));
+        }
+        if let Some(id) = ids
+          .iter()
+          .enumerate()
+          .find_map(|(i, id)| ids[..i].contains(id).then_some(id))
+        {
+          return Err(Error::new(
+            Status::InvalidArg,
+            format!("displayIds lists display {} twice", id),
+          ));
+        }
+        match ids[..] {
+          [id] => (Some(id), Vec::new()),
+          _ => (None, ids.clone()),
+        }
+      }
+    };
+    let stitch = self.stitch && !displays.is_empty();
+
+    let dimension = |name: &str, value: Option<u32>| value.map(|v| positive(name, v)).transpose();
+    let scale = ScaleOptions {
+      width: dimension("outputWidth", self.output_width)?,
+      height: dimension("outputHeight", self.output_height)?,
+      max_width: dimension("maxWidth", self.max_width)?,
+      filter: self.scale_filter,
+    };
+
+    if self.pixel_format.is_some() && self.encode.is_some() {
+      return Err(pixel_format_and_encode());
+    }
+    if let Some(delta) = &self.delta {
+      delta.validate()?;
+    }
+    if self.delta.is_some() && (self.pixel_format.is_some() || self.encode.is_some()) {
+      return Err(delta_and_format());
+    }
+    if let Some(encode) = &self.encode {
+      encode.validate()?;
+    }
+
+    let output = OutputOptions {
+      scale,
+      pixel_format: self.pixel_format.unwrap_or_default(),
+      yuv: YuvOptions {
+        matrix: self.color_matrix,
+        range: self.color_range,
+      },
+      encode: self.encode,
+    };
+
+    if self.max_queued_frames == 0 {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "maxQueuedFrames must be greater than 0".to_string(),
+      ));
+    }
+
+    if self.keepalive.is_some_and(|keepalive| keepalive.is_zero()) {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "keepaliveMs must be greater than 0".to_string(),
+      ));
+    }
+    let emit_only_on_change = if self.emit_only_on_change {
+      Some(ChangeOptions {
+        keepalive: self.keepalive,
+      })
+    } else if self.keepalive.is_some() {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "keepaliveMs can only be used with emitOnlyOnChange".to_string(),
+      ));
+    } else {
+      None
+    };
+
+    if displays.len() > 1 && !stitch {
+      let needs_stitch = [
+        ("region", self.region.is_some()),
+        ("dirtyRects", self.dirty_rects),
+        ("delta", self.delta.is_some()),
+      ];
+      if let Some((name, _)) = needs_stitch.iter().find(|(_, set)| *set) {
+        return Err(separate_displays(name));
+      }
+    }
+
+    if let Some(replay) = &self.replay {
+      replay.validate()?;
+    }
+
+    Ok(CaptureOptions {
+      display_id,
+      displays,
+      stitch,
+      window_id,
+      region: SharedRegion::new(self.region)?,
+      settings: SharedSettings::new(self.fps, output)?,
+      queue: QueueOptions {
+        max_queued_frames: self.max_queued_frames,
+        backpressure: self.backpressure,
+      },
+      events: EventSink::default(),
+      replay: self.replay,
+      dirty_rects: self.dirty_rects,
+      emit_only_on_change,
+      delta: self.delta,
+      hide_cursor: !self.show_cursor,
+      cursor_metadata: self.cursor_metadata,
+    })
+  }
+
+  pub(crate) fn create_backend(
+    &self,
+    options: CaptureOptions,
+  ) -> Result<Box<dyn CaptureBackendImpl>> {
+    // One backend per display, each capturing the whole display.
+    if options.displays.len() > 1 {
+      let backends = options
+        .displays
+        .iter()
+        .map(|&id| {
+          let display = CaptureOptions {
+            display_id: Some(id),
+            displays: Vec::new(),
+            region: SharedRegion::default(),
+            ..options.clone()
+          };
+          self.create_backend(display)
+        })
+        .collect::<Result<Vec<_>>>()?;
+      return Ok(Box::new(MultiDisplayBackend::new(backends, options)));
+    }
+
+    let backend: Box<dyn CaptureBackendImpl> = match self.backend {
+      Some(BackendKind::ScreenCaptureKit) => {
+        #[cfg(target_os = "macos")]
+        {
+          Box::new(SCKBackend::new(options))
+        }
+        #[cfg(not(target_os = "macos"))]
+        {
+          Box::new(XCapBackend::new(options))
+        }
+      }
+      Some(BackendKind::XCap) => Box::new(XCapBackend::new(options)),
+      Some(BackendKind::Synthetic) => {
+        let synthetic_options = self.synthetic.clone().unwrap_or_default();
+        Box::new(SyntheticBackend::new(synthetic_options, options))
+      }
+      // Window targets are captured through XCap's `Window`.
+      Some(BackendKind::X11) if options.window_id.is_some() => Box::new(XCapBackend::new(options)),
+      Some(BackendKind::X11) => {
+        #[cfg(target_os = "linux")]
+        {
+          let x11 = X11Backend::new(options).map_err(|e| {
+            Error::new(
+              Status::GenericFailure,
+              format!("X11 capture init failed: {:#}", e),
+            )
+          })?;
+          Box::new(x11)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+          Box::new(XCapBackend::new(options))
+        }
+      }
+      None => {
+        #[cfg(target_os = "macos")]
+        {
+          Box::new(SCKBackend::new(options))
+        }
+        #[cfg(target_os = "windows")]
+        {
+          Box::new(WindowsBackend::new(options))
+        }
+        #[cfg(target_os = "linux")]
+        {
+          Box::new(LinuxBackend::new(options))
+        }
+        #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+        {
+          Box::new(XCapBackend::new(options))
+        }
+      }
+    };
+
+    Ok(backend)
+  }
+}
+
+/// A capture session. Frames go to the `on_frame` callback of the builder and to every stream
+/// opened with `frames()`. Dropping it stops the capture threads and finishes a recording.
+pub struct Capturer {
+  backend: StdMutex<Option<Box<dyn CaptureBackendImpl>>>,
+  pub(crate) sink: FrameSink,
+  pub(crate) options: CaptureOptions,
+  recording: StdMutex<Option<Recording>>,
+}
+
+/// Settings `Capturer::update_config` changes. `None` keeps a setting; `Some(None)` clears the
+/// optional ones.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigUpdate {
+  pub fps: Option<u32>,
+  pub region: Option<Option<Region>>,
+  pub output_width: Option<Option<u32>>,
+  pub output_height: Option<Option<u32>>,
+  pub max_width: Option<Option<u32>>,
+  pub scale_filter: Option<ScaleFilter>,
+  /// Switches back from compressed to raw frames. Cannot be combined with `encode`.
+  pub pixel_format: Option<PixelFormat>,
+  pub color_matrix: Option<ColorMatrix>,
+  pub color_range: Option<ColorRange>,
+  pub encode: Option<Option<EncodeOptions>>,
+}
+
+impl Capturer {
+  pub fn builder() -> CapturerBuilder {
+    CapturerBuilder::new()
+  }
+
+  pub(crate) fn new(
+    backend: Box<dyn CaptureBackendImpl>,
+    options: CaptureOptions,
+    callback: Option<FrameCallback>,
+  ) -> Self {
+    Self {
+      backend: StdMutex::new(Some(backend)),
+      sink: FrameSink::new(callback, &options),
+      options,
+      recording: StdMutex::new(None),
+    }
+  }
+
+  pub async fn start(&self) -> Result<()> {
+    let backend_opt = {
+      let mut backend_guard = self.backend.lock().unwrap();
+      backend_guard.take()
+    };
+
+    if let Some(mut backend) = backend_opt {
+      let events = &self.options.events;
+      if events.state() != CaptureState::Running {
+        events.set_state(CaptureState::Starting);
+      }
+      self.options.settings.set_paused(false);
+      self.sink.reopen();
+      let result = match bind_region(backend.as_mut(), &self.options).await {
+        Ok(()) => backend.start(Some(self.sink.clone())).await,
+        Err(e) => Err(e),
+      };
+      match result {
+        Ok(()) => events.started(),
+        Err(_) => events.set_state(CaptureState::Stopped),
+      }
+
+      let mut backend_guard = self.backend.lock().unwrap();
+      *backend_guard = Some(backend);
+
+      result
+    } else {
+      Err(Error::new(
+        Status::GenericFailure,
+        "Backend is missing".to_string(),
+      ))
+    }
+  }
+
+  /// Stops capturing. Open `frames()` streams finish after their queued frames.
+  pub fn stop(&self) -> Result<()> {
+    self.sink.close();
+    let mut backend_guard = self.backend.lock().unwrap();
+    let result = match backend_guard.as_mut() {
+      Some(backend) => backend.stop(),
+      None => Ok(()),
+    };
+    self.options.settings.set_paused(false);
+    self.options.events.set_state(CaptureState::Stopped);
+    result
+  }
+
+  /// Stops delivering frames while keeping the stream open, so `resume()` continues right away.
+  pub fn pause(&self) -> Result<()> {
+    let (events, settings) = (&self.options.events, &self.options.settings);
+    settings.set_paused(true);
+    if events.state() == CaptureState::Paused
+      || events.transition_from(CaptureState::Running, CaptureState::Paused)
+    {
+      return Ok(());
+    }
+    settings.set_paused(false);
+    Err(not_running())
+  }
+
+  /// Delivers frames again after `pause()`.
+  pub fn resume(&self) -> Result<()> {
+    let events = &self.options.events;
+    if events.state() == CaptureState::Running {
+      return Ok(());
+    }
+    if !events.transition_from(CaptureState::Paused, CaptureState::Running) {
+      return Err(not_running());
+    }
+    self.options.settings.set_paused(false);
+    Ok(())
+  }
+
+  /// Changes the frame rate, region or output settings. A running session applies them from its
+  /// next frame without restarting the stream.
+  pub fn update_config(&self, update: ConfigUpdate) -> Result<()> {
+    let settings = &self.options.settings;
+    if let Some(fps) = update.fps {
+      positive("fps", fps)?;
+    }
+
+    let mut output = settings.output();
+    let dimension = |name: &str, value: Option<Option<u32>>, field: &mut Option<u32>| {
+      if let Some(value) = value {
+        *field = value.map(|v| positive(name, v)).transpose()?;
+      }
+      Ok::<_, Error>(())
+    };
+    dimension("outputWidth", update.output_width, &mut output.scale.width)?;
+    dimension(
+      "outputHeight",
+      update.output_height,
+      &mut output.scale.height,
+    )?;
+    dimension("maxWidth", update.max_width, &mut output.scale.max_width)?;
+    if let Some(filter) = update.scale_filter {
+      output.scale.filter = filter;
+    }
+
+    let encode = update.encode.is_some_and(|encode| encode.is_some());
+    if update.pixel_format.is_some() && encode {
+      return Err(pixel_format_and_encode());
+    }
+    if self.options.delta.is_some() && (update.pixel_format.is_some() || encode) {
+      return Err(delta_and_format());
+    }
+    // A pixel format switches back from compressed to raw frames.
+    if let Some(format) = update.pixel_format {
+      output.pixel_format = format;
+      output.encode = None;
+    }
+    if let Some(encode) = update.encode {
+      if let Some(encode) = &encode {
+        encode.validate()?;
+      }
+      output.encode = encode;
+    }
+    if let Some(matrix) = update.color_matrix {
+      output.yuv.matrix = matrix;
+    }
+    if let Some(range) = update.color_range {
+      output.yuv.range = range;
+    }
+
+    if let Some(region) = update.region {
+      self.set_region(region)?;
+    }
+    if let Some(fps) = update.fps {
+      settings.set_fps(fps)?;
+    }
+    settings.set_output(output);
+    // A still screen would otherwise keep the new settings from showing.
+    self.sink.refresh();
+    Ok(())
+  }
+
+  pub fn state(&self) -> CaptureState {
+    self.options.events.state()
+  }
+
+  /// Adds a listener for errors on the capture threads.
+  pub fn on_error(&self, listener: impl Fn(&CaptureError) + Send + Sync + 'static) {
+    self.options.events.on_error(listener);
+  }
+
+  /// Adds a listener called whenever the session moves to another `CaptureState`.
+  pub fn on_state_change(&self, listener: impl Fn(CaptureState) + Send + Sync + 'static) {
+    self.options.events.on_state_change(listener);
+  }
+
+  /// Adds a listener for display changes the session adapted to.
+  pub fn on_display_change(&self, listener: impl Fn(&DisplayChange) + Send + Sync + 'static) {
+    self.options.events.on_display_change(listener);
+  }
+
+  /// Opens a stream of the frames captured from now on. Each stream queues up to
+  /// `max_queued_frames` frames and applies `backpressure` when it falls behind.
+  pub fn frames(&self) -> FrameStream {
+    self.sink.subscribe()
+  }
+
+  /// Frame counters, including frames dropped by the backpressure policy.
+  pub fn stats(&self) -> CaptureStats {
+    self.sink.stats()
+  }
+
+  /// Frames in the replay buffer, oldest first, or `None` without the `replay` option.
+  pub fn replay_frames(&self) -> Option<Vec<Arc<Frame>>> {
+    self.sink.replay().map(|replay| replay.frames())
+  }
+
+  /// Writes the frames of the session to `path` until `stop_recording()` or `stop()`. Frames are
+  /// recorded after scaling but before `pixel_format` and `encode`, and timed by their
+  /// timestamps. `format` defaults to the extension of `path` and `fps` to the capture rate.
+  pub fn start_recording(
+    &self,
+    path: impl AsRef<Path>,
+    format: Option<RecordingFormat>,
+    fps: Option<u32>,
+  ) -> Result<()> {
+    if self.options.separate_displays() {
+      return Err(separate_displays("startRecording"));
+    }
+    let mut recording = self.recording.lock().unwrap();
+    if recording.is_some() {
+      return Err(Error::new(
+        Status::GenericFailure,
+        "A recording is already in progress".to_string(),
+      ));
+    }
+    let path = path.as_ref();
+    let options = RecordingOptions {
+      format: format.unwrap_or_else(|| RecordingFormat::from_path(path)),
+      fps: match fps {
+        Some(fps) => positive("fps", fps)?,
+        None => self.options.settings.fps(),
+      },
+    };
+
+    let recorder = Recorder::create(path, options)?;
+    *recording = Some(Recording::start(
+      self.sink.subscribe_unconverted(),
+      recorder,
+      self.options.events.clone(),
+    ));
+    Ok(())
+  }
+
+  /// Finishes the file of `start_recording()`, including the frames still queued for it.
+  /// Blocks until they are written.
+  pub fn stop_recording(&self) -> Result<RecordingSummary> {
+    let recording = self.recording.lock().unwrap().take().ok_or_else(|| {
+      Error::new(
+        Status::GenericFailure,
+        "No recording in progress".to_string(),
+      )
+    })?;
+    recording.stop()
+  }
+
+  /// Captures the next `options.duration` of the session, or takes the last one from the replay
+  /// buffer with `from_replay`, as a looping GIF or APNG. Frames are taken after scaling but
+  /// before `pixel_format` and `encode`. Blocks until the clip is encoded.
+  pub fn export_clip(&self, options: ClipOptions) -> Result<Vec<u8>> {
+    options.validate()?;
+    if self.options.separate_displays() {
+      return Err(separate_displays("exportClip"));
+    }
+    let clip = if options.from_replay {
+      self.replay_clip(options)?
+    } else {
+      self.live_clip(options)?
+    };
+    clip.encode()
+  }
+
+  fn live_clip(&self, options: ClipOptions) -> Result<Clip> {
+    if self.state() == CaptureState::Stopped {
+      return Err(not_running());
+    }
+
+    let stream = self.sink.subscribe_unconverted();
+    let mut clip = Clip::new(options);
+    // The clip starts with the first frame, which a starting session may deliver a bit later.
+    let deadline = Instant::now() + options.duration + CLIP_START_TIMEOUT;
+    while let Some(frame) = stream.blocking_next_until(deadline) {
+      if !clip.push(&frame) {
+        break;
+      }
+    }
+    Ok(clip)
+  }
+
+  /// Clip of the frames in the replay buffer that are less than `options.duration` older than
+  /// the newest one.
+  fn replay_clip(&self, options: ClipOptions) -> Result<Clip> {
+    let frames = self.buffered_replay()?;
+    let newest = frames[frames.len() - 1].timestamp;
+    let frames: Vec<_> = frames
+      .iter()
+      .filter(|frame| frame.timestamp + options.duration > newest)
+      .collect();
+    // A buffer shorter than the clip makes a shorter clip, ending one frame after the newest.
+    let span = newest - frames[0].timestamp + Duration::from_secs(1) / options.fps;
+    let mut clip = Clip::new(ClipOptions {
+      duration: options.duration.min(span),
+      ..options
+    });
+    for frame in frames {
+      if frame.encoding.is_some() {
+        clip.push(&encode::decode(frame)?);
+      } else {
+        clip.push(frame);
+      }
+    }
+    Ok(clip)
+  }
+
+  /// Writes the frames in the replay buffer to `path` like `start_recording()`. The buffer keeps
+  /// its frames.
+  pub fn save_replay(&self, path: impl AsRef<Path>) -> Result<RecordingSummary> {
+    let frames = self.buffered_replay()?;
+    if self.options.separate_displays() {
+      return Err(separate_displays("Saving a replay to a file"));
+    }
+
+    let path = path.as_ref();
+    let options = RecordingOptions {
+      format: RecordingFormat::from_path(path),
+      fps: self.options.settings.fps(),
+    };
+    let mut recorder = Recorder::create(path, options)?;
+    for frame in &frames {
+      if frame.encoding.is_some() {
+        recorder.write(&encode::decode(frame)?)?;
+      } else {
+        recorder.write(frame)?;
+      }
+    }
+    recorder.finish()
+  }
+
+  /// Frames in the replay buffer, or an error when it is disabled or empty.
+  pub(crate) fn buffered_replay(&self) -> Result<Vec<Arc<Frame>>> {
+    let frames = self.replay_frames().ok_or_else(|| {
+      Error::new(
+        Status::GenericFailure,
+        "Replay is not enabled, set the replay option".to_string(),
+      )
+    })?;
+    if frames.is_empty() {
+      return Err(Error::new(
+        Status::GenericFailure,
+        "The replay buffer is empty".to_string(),
+      ));
+    }
+    Ok(frames)
+  }
+
+  /// Makes the next packet of the `delta` option a keyframe.
+  pub fn request_keyframe(&self) -> Result<()> {
+    if !self.sink.request_keyframe() {
+      return Err(Error::new(
+        Status::GenericFailure,
+        "Delta output is not enabled, set the delta option".to_string(),
+      ));
+    }
+    Ok(())
+  }
+
+  /// Moves the capture region, or captures the whole display again with `None`.
+  pub fn set_region(&self, region: Option<Region>) -> Result<()> {
+    if region.is_some() && self.options.separate_displays() {
+      return Err(separate_displays("region"));
+    }
+    self.options.region.set(region)
+  }
+
+  /// Captures a single frame with the output settings of the session applied.
+  pub async fn screenshot(&self) -> Result<Frame> {
+    let frame = self.capture_frame().await?;
+    self.options.settings.output().process(frame)
+  }
+
+  /// Captures a single frame as the backend delivers it.
+  pub(crate) async fn capture_frame(&self) -> Result<Frame> {
+    let backend_opt = {
+      let mut backend_guard = self.backend.lock().unwrap();
+      backend_guard.take()
+    };
+
+    if let Some(mut backend) = backend_opt {
+      let result = match bind_region(backend.as_mut(), &self.options).await {
+        Ok(()) => backend.screenshot().await,
+        Err(e) => Err(e),
+      };
+
+      let mut backend_guard = self.backend.lock().unwrap();
+      *backend_guard = Some(backend);
+
+      Ok(Frame {
+        dirty_rects: None,
+        cursor: None,
+        ..result?
+      })
+    } else {
+      Err(Error::new(
+        Status::GenericFailure,
+        "Backend is busy or missing".to_string(),
+      ))
+    }
+  }
+}
+
+impl Drop for Capturer {
+  fn drop(&mut self) {
+    self.sink.close();
+    if let Ok(Some(backend)) = self.backend.get_mut() {
+      let _ = backend.stop();
+    }
+    if let Ok(recording) = self.recording.get_mut() {
+      if let Some(recording) = recording.take() {
+        let _ = recording.stop();
+      }
+    }
+  }
+}
+
+pub(crate) fn not_running() -> Error {
+  Error::new(Status::GenericFailure, "Capture is not running".to_string())
+}
+
+/// Error for `what`, which cannot handle the separate frames of several displays.
+pub(crate) fn separate_displays(what: &str) -> Error {
+  Error::new(
+    Status::InvalidArg,
+    format!("{} needs stitch when capturing several displays", what),
+  )
+}
+
+pub(crate) fn pixel_format_and_encode() -> Error {
+  Error::new(
+    Status::InvalidArg,
+    "pixelFormat and encode cannot be used together".to_string(),
+  )
+}
+
+pub(crate) fn delta_and_format() -> Error {
+  Error::new(
+    Status::InvalidArg,
+    "delta cannot be used with pixelFormat or encode".to_string(),
+  )
+}
+
+/// `value`, or an error naming the setting when it is 0.
+pub(crate) fn positive(name: &str, value: u32) -> Result<u32> {
+  if value == 0 {
+    return Err(Error::new(
+      Status::InvalidArg,
+      format!("{} must be greater than 0", name),
+    ));
+  }
+  Ok(value)
+}
+
+/// Checks that the selected display or window exists, and the capture region against its size or
+/// the size of the stitched displays.
+async fn bind_region(backend: &mut dyn CaptureBackendImpl, options: &CaptureOptions) -> Result<()> {
+  let has_region = options.region.get().is_some();
+  if !has_region
+    && options.window_id.is_none()
+    && options.display_id.is_none()
+    && options.displays.is_empty()
+  {
+    return Ok(());
+  }
+
+  if let Some(id) = options.window_id {
+    let windows = backend.list_windows().await?;
+    let window = windows
+      .iter()
+      .find(|w| w.id == id)
+      .ok_or_else(|| window_not_found(id))?;
+    if has_region {
+      options.region.bind(window.width, window.height)?;
+    }
+    return Ok(());
+  }
+
+  let displays = backend.list_displays().await?;
+  if !options.displays.is_empty() {
+    let layout = Layout::new(&displays, &options.displays)?;
+    if has_region {
+      options.region.bind(layout.width, layout.height)?;
+    }
+    return Ok(());
+  }
+  let display = match options.display_id {
+    Some(id) => displays
+      .iter()
+      .find(|d| d.id == id)
+      .ok_or_else(|| display_not_found(id))?,
+    None => displays
+      .iter()
+      .find(|d| d.is_primary)
+      .or(displays.first())
+      .ok_or_else(|| Error::new(Status::GenericFailure, "No display found".to_string()))?,
+  };
+  if has_region {
+    options.region.bind(display.width, display.height)?;
+  }
+  Ok(())
+}
@@ -0,0 +1,75 @@
+use std::fmt;
+
+/// Kind of failure, mirroring the N-API statuses the bindings report to JavaScript.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+  Ok,
+  /// A setting or argument was rejected.
+  InvalidArg,
+  /// The platform or the capture failed.
+  GenericFailure,
+  /// The consumer of the frames went away, e.g. JavaScript is shutting down.
+  Closing,
+}
+
+impl fmt::Display for Status {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(self, f)
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Error {
+  pub status: Status,
+  pub reason: String,
+}
+
+impl Error {
+  pub fn new(status: Status, reason: impl Into<String>) -> Self {
+    Self {
+      status,
+      reason: reason.into(),
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}, {}", self.status, self.reason)
+  }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(feature = "napi")]
+impl From<Status> for napi::Status {
+  fn from(status: Status) -> Self {
+    match status {
+      Status::Ok => Self::Ok,
+      Status::InvalidArg => Self::InvalidArg,
+      Status::GenericFailure => Self::GenericFailure,
+      Status::Closing => Self::Closing,
+    }
+  }
+}
+
+#[cfg(feature = "napi")]
+impl From<napi::Status> for Status {
+  fn from(status: napi::Status) -> Self {
+    match status {
+      napi::Status::Ok => Self::Ok,
+      napi::Status::InvalidArg => Self::InvalidArg,
+      napi::Status::Closing => Self::Closing,
+      _ => Self::GenericFailure,
+    }
+  }
+}
+
+#[cfg(feature = "napi")]
+impl From<Error> for napi::Error {
+  fn from(error: Error) -> Self {
+    napi::Error::new(error.status.into(), error.reason)
+  }
+}
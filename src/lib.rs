@@ -1,4 +1,12 @@
 #![deny(clippy::all)]
 
 pub mod backend;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod capturer;
+pub mod error;
+#[cfg(feature = "napi")]
 pub mod scap;
+
+pub use capturer::{Capturer, CapturerBuilder};
+pub use error::{Error, Result, Status};
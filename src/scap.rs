@@ -1,21 +1,228 @@
-use std::sync::{Arc, Mutex as StdMutex};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
 
 use napi::bindgen_prelude::*;
+use napi::threadsafe_function::ThreadsafeFunctionCallMode;
 use napi_derive::napi;
 
-#[cfg(target_os = "macos")]
-use crate::backend::macos::SCKBackend;
-#[cfg(target_os = "windows")]
-use crate::backend::windows::WindowsBackend;
-use crate::backend::xcap::XCapBackend;
-use crate::backend::{CaptureBackendImpl, FrameDataInternal, FrameTsfnType};
+use crate::backend::clip::{ClipFormat as ClipFormatInternal, ClipOptions};
+use crate::backend::cursor::CursorInternal;
+use crate::backend::delta::{
+  DeltaDecoder as DeltaDecoderInternal, DeltaEncoder as DeltaEncoderInternal, DeltaOptions,
+  TileCompression as TileCompressionInternal,
+};
+use crate::backend::encode::{self, EncodeOptions, ImageFormat as ImageFormatInternal};
+use crate::backend::events::{
+  CaptureErrorInternal, CaptureState as CaptureStateInternal, DisplayChangeInternal,
+  DisplayChangeKind as DisplayChangeKindInternal, ErrorCode as ErrorCodeInternal,
+};
+use crate::backend::output::ScaleFilter as ScaleFilterInternal;
+use crate::backend::pixel::{
+  ColorMatrix as ColorMatrixInternal, ColorRange as ColorRangeInternal,
+  PixelFormat as PixelFormatInternal,
+};
+use crate::backend::record::{
+  RecordingFormat as RecordingFormatInternal, RecordingSummary as RecordingSummaryInternal,
+};
+use crate::backend::replay::ReplayOptions;
+use crate::backend::stream::{Backpressure as BackpressureInternal, FrameQueue, FrameStream};
+use crate::backend::synthetic::SyntheticOptions;
+use crate::backend::{
+  host_time, CaptureStatsInternal, DisplayInfoInternal, FrameCallback, FrameDataInternal,
+  RegionInternal, WindowInfoInternal,
+};
+use crate::capturer::{BackendKind, Capturer, CapturerBuilder, ConfigUpdate};
 
 #[napi(object)]
 pub struct FrameData {
   pub width: u32,
   pub height: u32,
+  /// Bytes per row of packed formats, or of the Y plane of planar ones.
   pub stride: u32,
+  pub format: PixelFormat,
+  /// Set when the `encode` option is used. `rgba` then holds the compressed image and `stride` is 0.
+  pub encoding: Option<ImageFormat>,
+  /// Pixel data laid out as described by `format`. Only RGBA by default.
   pub rgba: Buffer,
+  /// Capture time in milliseconds on a monotonic clock shared by all captures of the process.
+  pub timestamp: f64,
+  /// Position of the frame in the capture session, starting at 0 on every `start()`.
+  pub sequence: i64,
+  /// Display the frame shows. Unset for window captures and with `stitch`.
+  pub display_id: Option<u32>,
+  /// Frames discarded by the backpressure policy since the previous frame this consumer received.
+  pub dropped_since_last: i64,
+  /// Milliseconds between capture and delivery to JavaScript.
+  pub latency: f64,
+  /// Parts of the frame that changed since the previous frame this consumer received, when the
+  /// `dirtyRects` option is set. Rectangles may overlap.
+  pub dirty_rects: Option<Vec<Region>>,
+  /// Set with the `delta` option, where `rgba` holds a packet for `DeltaDecoder` and `stride` is
+  /// 0: whether the packet is a keyframe. Frames returned by `DeltaDecoder` set it too.
+  pub keyframe: Option<bool>,
+  /// Pointer position and shape, when the `cursorMetadata` option is set and the backend reports them.
+  pub cursor: Option<CursorInfo>,
+}
+
+impl From<FrameDataInternal> for FrameData {
+  fn from(frame: FrameDataInternal) -> Self {
+    let latency = host_time().saturating_sub(frame.timestamp);
+    Self {
+      width: frame.width,
+      height: frame.height,
+      stride: frame.stride,
+      format: frame.format.into(),
+      encoding: frame.encoding.map(ImageFormat::from),
+      rgba: frame.data.into(),
+      timestamp: frame.timestamp.as_secs_f64() * 1000.0,
+      sequence: frame.sequence as i64,
+      display_id: frame.display_id,
+      dropped_since_last: frame.dropped_since_last as i64,
+      latency: latency.as_secs_f64() * 1000.0,
+      dirty_rects: frame
+        .dirty_rects
+        .map(|rects| rects.into_iter().map(Region::from).collect()),
+      keyframe: frame.keyframe,
+      cursor: frame.cursor.map(CursorInfo::from),
+    }
+  }
+}
+
+/// Pointer of the captured display or window at the time of a frame.
+#[napi(object)]
+pub struct CursorInfo {
+  /// Pointer position in frame pixels. Outside the frame while the pointer is elsewhere.
+  pub x: i32,
+  pub y: i32,
+  /// False while the system hides the pointer.
+  pub visible: bool,
+  /// Pixel of the shape that sits at the pointer position.
+  pub hotspot_x: u32,
+  pub hotspot_y: u32,
+  /// Set on the first frame a consumer receives and whenever the shape changes. Keep the last
+  /// one to draw the pointer.
+  pub shape: Option<CursorShape>,
+}
+
+/// Pointer image, at the resolution of the display.
+#[napi(object)]
+pub struct CursorShape {
+  pub width: u32,
+  pub height: u32,
+  /// Straight (not premultiplied) RGBA pixels, `width * 4` bytes per row.
+  pub rgba: Buffer,
+}
+
+impl From<CursorInternal> for CursorInfo {
+  fn from(cursor: CursorInternal) -> Self {
+    let shape = cursor.shape.as_deref();
+    Self {
+      x: cursor.x,
+      y: cursor.y,
+      visible: cursor.visible,
+      hotspot_x: shape.map_or(0, |shape| shape.hotspot_x),
+      hotspot_y: shape.map_or(0, |shape| shape.hotspot_y),
+      shape: shape
+        .filter(|_| cursor.shape_changed)
+        .map(|shape| CursorShape {
+          width: shape.width,
+          height: shape.height,
+          rgba: shape.data.clone().into(),
+        }),
+    }
+  }
+}
+
+#[napi(object)]
+pub struct DisplayInfo {
+  pub id: u32,
+  pub name: String,
+  pub x: i32,
+  pub y: i32,
+  pub width: u32,
+  pub height: u32,
+  pub scale_factor: f64,
+  pub rotation: f64,
+  pub is_primary: bool,
+}
+
+impl From<DisplayInfoInternal> for DisplayInfo {
+  fn from(info: DisplayInfoInternal) -> Self {
+    Self {
+      id: info.id,
+      name: info.name,
+      x: info.x,
+      y: info.y,
+      width: info.width,
+      height: info.height,
+      scale_factor: info.scale_factor,
+      rotation: info.rotation,
+      is_primary: info.is_primary,
+    }
+  }
+}
+
+#[napi(object)]
+pub struct WindowInfo {
+  pub id: u32,
+  pub title: String,
+  pub app_name: String,
+  pub pid: u32,
+  pub x: i32,
+  pub y: i32,
+  pub width: u32,
+  pub height: u32,
+  pub is_minimized: bool,
+  pub is_on_screen: bool,
+}
+
+impl From<WindowInfoInternal> for WindowInfo {
+  fn from(info: WindowInfoInternal) -> Self {
+    Self {
+      id: info.id,
+      title: info.title,
+      app_name: info.app_name,
+      pid: info.pid,
+      x: info.x,
+      y: info.y,
+      width: info.width,
+      height: info.height,
+      is_minimized: info.is_minimized,
+      is_on_screen: info.is_on_screen,
+    }
+  }
+}
+
+/// Rectangle relative to the top-left corner of the captured display or window.
+#[napi(object)]
+pub struct Region {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+}
+
+impl From<RegionInternal> for Region {
+  fn from(region: RegionInternal) -> Self {
+    Self {
+      x: region.x,
+      y: region.y,
+      width: region.width,
+      height: region.height,
+    }
+  }
+}
+
+impl From<&Region> for RegionInternal {
+  fn from(region: &Region) -> Self {
+    Self {
+      x: region.x,
+      y: region.y,
+      width: region.width,
+      height: region.height,
+    }
+  }
 }
 
 #[napi(string_enum)]
@@ -23,19 +230,678 @@ pub struct FrameData {
 pub enum CaptureBackend {
   ScreenCaptureKit,
   XCap,
+  Synthetic,
+  X11,
+}
+
+impl From<CaptureBackend> for BackendKind {
+  fn from(backend: CaptureBackend) -> Self {
+    match backend {
+      CaptureBackend::ScreenCaptureKit => Self::ScreenCaptureKit,
+      CaptureBackend::XCap => Self::XCap,
+      CaptureBackend::Synthetic => Self::Synthetic,
+      CaptureBackend::X11 => Self::X11,
+    }
+  }
+}
+
+/// Resampling filter used when frames are scaled on the CPU.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum ScaleFilter {
+  Nearest,
+  Bilinear,
+  Lanczos,
+}
+
+impl From<ScaleFilter> for ScaleFilterInternal {
+  fn from(filter: ScaleFilter) -> Self {
+    match filter {
+      ScaleFilter::Nearest => Self::Nearest,
+      ScaleFilter::Bilinear => Self::Bilinear,
+      ScaleFilter::Lanczos => Self::Lanczos,
+    }
+  }
+}
+
+/// Layout of `FrameData.rgba`.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum PixelFormat {
+  #[napi(value = "RGBA")]
+  Rgba,
+  #[napi(value = "BGRA")]
+  Bgra,
+  #[napi(value = "RGB")]
+  Rgb,
+  /// Y plane, then U and V planes at half the width and height.
+  #[napi(value = "I420")]
+  I420,
+  /// Y plane, then one interleaved UV plane at half the width and height.
+  #[napi(value = "NV12")]
+  Nv12,
+}
+
+impl From<PixelFormat> for PixelFormatInternal {
+  fn from(format: PixelFormat) -> Self {
+    match format {
+      PixelFormat::Rgba => Self::Rgba,
+      PixelFormat::Bgra => Self::Bgra,
+      PixelFormat::Rgb => Self::Rgb,
+      PixelFormat::I420 => Self::I420,
+      PixelFormat::Nv12 => Self::Nv12,
+    }
+  }
+}
+
+impl From<PixelFormatInternal> for PixelFormat {
+  fn from(format: PixelFormatInternal) -> Self {
+    match format {
+      PixelFormatInternal::Rgba => Self::Rgba,
+      PixelFormatInternal::Bgra => Self::Bgra,
+      PixelFormatInternal::Rgb => Self::Rgb,
+      PixelFormatInternal::I420 => Self::I420,
+      PixelFormatInternal::Nv12 => Self::Nv12,
+    }
+  }
+}
+
+/// Compressed image format produced by `encode` and `screenshot(options)`.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum ImageFormat {
+  #[napi(value = "jpeg")]
+  Jpeg,
+  #[napi(value = "png")]
+  Png,
+  /// Lossless WebP.
+  #[napi(value = "webp")]
+  Webp,
+}
+
+impl From<ImageFormat> for ImageFormatInternal {
+  fn from(format: ImageFormat) -> Self {
+    match format {
+      ImageFormat::Jpeg => Self::Jpeg,
+      ImageFormat::Png => Self::Png,
+      ImageFormat::Webp => Self::Webp,
+    }
+  }
+}
+
+impl From<ImageFormatInternal> for ImageFormat {
+  fn from(format: ImageFormatInternal) -> Self {
+    match format {
+      ImageFormatInternal::Jpeg => Self::Jpeg,
+      ImageFormatInternal::Png => Self::Png,
+      ImageFormatInternal::Webp => Self::Webp,
+    }
+  }
+}
+
+#[napi(object)]
+pub struct EncodeConfig {
+  pub format: ImageFormat,
+  /// JPEG quality from 1 to 100 (default 80). PNG and WebP are lossless and ignore it.
+  pub quality: Option<u32>,
+}
+
+impl TryFrom<&EncodeConfig> for EncodeOptions {
+  type Error = Error;
+
+  fn try_from(cfg: &EncodeConfig) -> Result<Self> {
+    let options = Self {
+      format: cfg.format.into(),
+      quality: cfg.quality.map_or(Self::DEFAULT_QUALITY, |quality| {
+        quality.min(u8::MAX as u32) as u8
+      }),
+    };
+    options.validate()?;
+    Ok(options)
+  }
+}
+
+/// YUV matrix used for `I420` and `NV12` frames.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum ColorMatrix {
+  #[napi(value = "BT601")]
+  Bt601,
+  #[napi(value = "BT709")]
+  Bt709,
+}
+
+impl From<ColorMatrix> for ColorMatrixInternal {
+  fn from(matrix: ColorMatrix) -> Self {
+    match matrix {
+      ColorMatrix::Bt601 => Self::Bt601,
+      ColorMatrix::Bt709 => Self::Bt709,
+    }
+  }
+}
+
+/// Value range of `I420` and `NV12` frames.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum ColorRange {
+  /// Y in 16..235, U and V in 16..240.
+  Limited,
+  /// All channels in 0..255.
+  Full,
+}
+
+impl From<ColorRange> for ColorRangeInternal {
+  fn from(range: ColorRange) -> Self {
+    match range {
+      ColorRange::Limited => Self::Limited,
+      ColorRange::Full => Self::Full,
+    }
+  }
+}
+
+/// What happens to new frames while `maxQueuedFrames` are waiting for a consumer.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum Backpressure {
+  /// Drop the oldest waiting frame, so consumers always get the latest ones.
+  #[napi(value = "dropOldest")]
+  DropOldest,
+  /// Drop the new frame.
+  #[napi(value = "dropNewest")]
+  DropNewest,
+  /// Pause the capture loop until the consumer catches up.
+  #[napi(value = "block")]
+  Block,
+}
+
+impl From<Backpressure> for BackpressureInternal {
+  fn from(backpressure: Backpressure) -> Self {
+    match backpressure {
+      Backpressure::DropOldest => Self::DropOldest,
+      Backpressure::DropNewest => Self::DropNewest,
+      Backpressure::Block => Self::Block,
+    }
+  }
+}
+
+/// Frame counters of a `ScreenCapture`, across all of its sessions.
+#[napi(object)]
+pub struct CaptureStats {
+  /// Frames captured while a callback or `frames()` iterator was listening.
+  pub frames_captured: i64,
+  /// Frames discarded by the backpressure policy, summed over the callback and all iterators.
+  pub frames_dropped: i64,
+}
+
+impl From<CaptureStatsInternal> for CaptureStats {
+  fn from(stats: CaptureStatsInternal) -> Self {
+    Self {
+      frames_captured: stats.frames_captured as i64,
+      frames_dropped: stats.frames_dropped as i64,
+    }
+  }
+}
+
+/// Stable identifier of a capture error. Codes are never renamed or reused.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum ErrorCode {
+  /// Screen recording was refused by the user or the OS.
+  #[napi(value = "permissionDenied")]
+  PermissionDenied,
+  /// The captured display went away or can no longer be read.
+  #[napi(value = "displayLost")]
+  DisplayLost,
+  /// The captured window was closed.
+  #[napi(value = "windowClosed")]
+  WindowClosed,
+  /// The preferred backend is unavailable and another one is used instead.
+  #[napi(value = "backendFallback")]
+  BackendFallback,
+  /// Reading a frame from the backend failed.
+  #[napi(value = "captureFailed")]
+  CaptureFailed,
+  /// Scaling, converting or encoding a frame failed.
+  #[napi(value = "processingFailed")]
+  ProcessingFailed,
+  /// The frame callback can no longer be called.
+  #[napi(value = "stoppedByConsumer")]
+  StoppedByConsumer,
+  /// Writing a recording failed; the recording ended but capture goes on.
+  #[napi(value = "recordingFailed")]
+  RecordingFailed,
+}
+
+impl From<ErrorCodeInternal> for ErrorCode {
+  fn from(code: ErrorCodeInternal) -> Self {
+    match code {
+      ErrorCodeInternal::PermissionDenied => Self::PermissionDenied,
+      ErrorCodeInternal::DisplayLost => Self::DisplayLost,
+      ErrorCodeInternal::WindowClosed => Self::WindowClosed,
+      ErrorCodeInternal::BackendFallback => Self::BackendFallback,
+      ErrorCodeInternal::CaptureFailed => Self::CaptureFailed,
+      ErrorCodeInternal::ProcessingFailed => Self::ProcessingFailed,
+      ErrorCodeInternal::StoppedByConsumer => Self::StoppedByConsumer,
+      ErrorCodeInternal::RecordingFailed => Self::RecordingFailed,
+    }
+  }
+}
+
+/// Error reported to `onError` listeners.
+#[napi(object)]
+pub struct CaptureError {
+  pub code: ErrorCode,
+  pub message: String,
+  /// The session ended because of this error.
+  pub fatal: bool,
+}
+
+impl From<&CaptureErrorInternal> for CaptureError {
+  fn from(error: &CaptureErrorInternal) -> Self {
+    Self {
+      code: error.code.into(),
+      message: error.message.clone(),
+      fatal: error.fatal,
+    }
+  }
+}
+
+/// Lifecycle of a capture session, reported to `onStateChange` listeners.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum CaptureState {
+  /// `start()` was called and the backend is being set up.
+  #[napi(value = "starting")]
+  Starting,
+  #[napi(value = "running")]
+  Running,
+  /// `pause()` was called: the stream stays open but delivers no frames until `resume()`.
+  #[napi(value = "paused")]
+  Paused,
+  /// Not capturing: never started, stopped, or ended by a fatal error.
+  #[napi(value = "stopped")]
+  Stopped,
+}
+
+impl From<CaptureStateInternal> for CaptureState {
+  fn from(state: CaptureStateInternal) -> Self {
+    match state {
+      CaptureStateInternal::Starting => Self::Starting,
+      CaptureStateInternal::Running => Self::Running,
+      CaptureStateInternal::Paused => Self::Paused,
+      CaptureStateInternal::Stopped => Self::Stopped,
+    }
+  }
+}
+
+/// What changed in the display setup of a running session, reported to `onDisplayChange`.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum DisplayChangeKind {
+  /// Displays were connected, disconnected, moved or resized, other than the captured one.
+  #[napi(value = "displaysChanged")]
+  DisplaysChanged,
+  /// The size of the captured display changed; frames have the new size from now on.
+  #[napi(value = "resolutionChanged")]
+  ResolutionChanged,
+}
+
+impl From<DisplayChangeKindInternal> for DisplayChangeKind {
+  fn from(kind: DisplayChangeKindInternal) -> Self {
+    match kind {
+      DisplayChangeKindInternal::DisplaysChanged => Self::DisplaysChanged,
+      DisplayChangeKindInternal::ResolutionChanged => Self::ResolutionChanged,
+    }
+  }
+}
+
+/// Display change reported to `onDisplayChange` listeners, once the session adapted to it.
+#[napi(object)]
+pub struct DisplayChange {
+  pub kind: DisplayChangeKind,
+  /// Captured display after the change. Its id may differ from the one capture started with
+  /// when the platform renumbered it.
+  pub display_id: u32,
+  /// Size of the captured display after the change, as `listDisplays()` reports it.
+  pub width: u32,
+  pub height: u32,
+  /// Every display after the change.
+  pub displays: Vec<DisplayInfo>,
+}
+
+impl From<&DisplayChangeInternal> for DisplayChange {
+  fn from(change: &DisplayChangeInternal) -> Self {
+    Self {
+      kind: change.kind.into(),
+      display_id: change.display_id,
+      width: change.width,
+      height: change.height,
+      displays: change.displays.iter().cloned().map(Into::into).collect(),
+    }
+  }
+}
+
+/// Animation format of `exportClip()`.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum ClipFormat {
+  /// One palette of up to 255 colors for the whole clip.
+  #[napi(value = "gif")]
+  Gif,
+  /// Lossless.
+  #[napi(value = "apng")]
+  Apng,
+}
+
+impl From<ClipFormat> for ClipFormatInternal {
+  fn from(format: ClipFormat) -> Self {
+    match format {
+      ClipFormat::Gif => Self::Gif,
+      ClipFormat::Apng => Self::Apng,
+    }
+  }
+}
+
+#[napi(object)]
+pub struct ClipConfig {
+  /// Length of the clip in milliseconds, from its first frame.
+  pub duration_ms: u32,
+  /// Defaults to `gif`.
+  pub format: Option<ClipFormat>,
+  /// Frames per second kept from the capture, from 1 to 50. Defaults to 10.
+  pub fps: Option<u32>,
+  /// Size relative to the captured frames, greater than 0 and at most 1. Defaults to 1.
+  pub scale: Option<f64>,
+  /// Takes the last `durationMs` of the replay buffer instead of capturing the next ones.
+  pub from_replay: Option<bool>,
+}
+
+/// File format of `startRecording()`.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum RecordingFormat {
+  /// YUV4MPEG2 video with 4:2:0 frames at a constant frame rate.
+  #[napi(value = "y4m")]
+  Y4m,
+  /// Frames as captured, plus a `.idx` text file with their timing and layout.
+  #[napi(value = "raw")]
+  Raw,
+}
+
+impl From<RecordingFormat> for RecordingFormatInternal {
+  fn from(format: RecordingFormat) -> Self {
+    match format {
+      RecordingFormat::Y4m => Self::Y4m,
+      RecordingFormat::Raw => Self::Raw,
+    }
+  }
+}
+
+#[napi(object)]
+pub struct RecordingConfig {
+  /// Defaults to `y4m` for `.y4m` paths and `raw` otherwise.
+  pub format: Option<RecordingFormat>,
+  /// Frame rate of Y4M files. Defaults to the capture frame rate.
+  pub fps: Option<u32>,
+}
+
+/// What `stopRecording()` wrote.
+#[napi(object)]
+pub struct RecordingSummary {
+  /// Frames in the file, repeated frames included.
+  pub frames: i64,
+  /// Frames written again to keep Y4M timing while the screen did not deliver new ones.
+  pub repeated_frames: i64,
+  /// Frames left out because they arrived faster than the Y4M frame rate.
+  pub skipped_frames: i64,
+  /// Play time of Y4M files, or time from the first to the last frame of raw files, in milliseconds.
+  pub duration: f64,
+}
+
+impl From<RecordingSummaryInternal> for RecordingSummary {
+  fn from(summary: RecordingSummaryInternal) -> Self {
+    Self {
+      frames: summary.frames as i64,
+      repeated_frames: summary.repeated_frames as i64,
+      skipped_frames: summary.skipped_frames as i64,
+      duration: summary.duration.as_secs_f64() * 1000.0,
+    }
+  }
+}
+
+/// In-memory buffer of the last frames of a session, saved with `saveReplay()`.
+#[napi(object)]
+pub struct ReplayConfig {
+  /// How much of the session to keep, in milliseconds.
+  pub duration_ms: u32,
+  /// Memory the buffered frames may use, in bytes (default 256 MiB). The oldest frames are
+  /// evicted first.
+  pub max_bytes: Option<i64>,
+  /// Keep frames as JPEG at this quality, from 1 to 100, to hold more of them. Unset keeps raw
+  /// pixels.
+  pub jpeg_quality: Option<u32>,
+}
+
+impl TryFrom<&ReplayConfig> for ReplayOptions {
+  type Error = Error;
+
+  fn try_from(cfg: &ReplayConfig) -> Result<Self> {
+    let options = Self {
+      duration: Duration::from_millis(cfg.duration_ms as u64),
+      max_bytes: cfg.max_bytes.map_or(Self::DEFAULT_MAX_BYTES, |max| {
+        usize::try_from(max).unwrap_or(0)
+      }),
+      jpeg_quality: cfg
+        .jpeg_quality
+        .map(|quality| quality.min(u8::MAX as u32) as u8),
+    };
+    options.validate()?;
+    Ok(options)
+  }
+}
+
+/// How `delta` packets compress their tiles.
+#[napi(string_enum)]
+#[derive(Clone, Copy)]
+pub enum TileCompression {
+  #[napi(value = "raw")]
+  Raw,
+  /// Tiles zlib does not shrink are sent raw.
+  #[napi(value = "zlib")]
+  Zlib,
+  /// Lossy and without alpha, for photos and video on screen.
+  #[napi(value = "jpeg")]
+  Jpeg,
+}
+
+impl From<TileCompression> for TileCompressionInternal {
+  fn from(compression: TileCompression) -> Self {
+    match compression {
+      TileCompression::Raw => Self::Raw,
+      TileCompression::Zlib => Self::Zlib,
+      TileCompression::Jpeg => Self::Jpeg,
+    }
+  }
+}
+
+/// Settings of delta packets, for the `delta` option and `DeltaEncoder`.
+#[napi(object)]
+pub struct DeltaConfig {
+  /// Side of the square tiles in pixels, from 8 to 1024 (default 64).
+  pub tile_size: Option<u32>,
+  /// Defaults to `zlib`.
+  pub compression: Option<TileCompression>,
+  /// Quality of `jpeg` tiles from 1 to 100 (default 80).
+  pub jpeg_quality: Option<u32>,
+}
+
+impl TryFrom<&DeltaConfig> for DeltaOptions {
+  type Error = Error;
+
+  fn try_from(cfg: &DeltaConfig) -> Result<Self> {
+    let options = Self {
+      tile_size: cfg.tile_size.unwrap_or(Self::DEFAULT_TILE_SIZE),
+      compression: cfg.compression.map(Into::into).unwrap_or_default(),
+      jpeg_quality: cfg
+        .jpeg_quality
+        .map_or(Self::DEFAULT_JPEG_QUALITY, |quality| {
+          quality.min(u8::MAX as u32) as u8
+        }),
+    };
+    options.validate()?;
+    Ok(options)
+  }
+}
+
+/// Packed pixels for `DeltaEncoder.encode()`. Frames delivered without `pixelFormat` or `encode`
+/// can be passed as they are.
+#[napi(object)]
+pub struct RawFrame {
+  pub width: u32,
+  pub height: u32,
+  /// Bytes per row (default `width * 4`).
+  pub stride: Option<u32>,
+  /// `RGBA` or `BGRA` (default `RGBA`).
+  pub format: Option<PixelFormat>,
+  pub rgba: Buffer,
+  /// Carried to the decoded frame (default 0).
+  pub timestamp: Option<f64>,
+  /// Carried to the decoded frame (default 0).
+  pub sequence: Option<i64>,
+}
+
+impl From<&RawFrame> for FrameDataInternal {
+  fn from(frame: &RawFrame) -> Self {
+    Self {
+      width: frame.width,
+      height: frame.height,
+      stride: frame.stride.unwrap_or(frame.width * 4),
+      format: frame.format.map(Into::into).unwrap_or_default(),
+      data: frame.rgba.to_vec(),
+      timestamp: Duration::from_secs_f64(frame.timestamp.unwrap_or(0.0).max(0.0) / 1000.0),
+      sequence: frame.sequence.unwrap_or(0).max(0) as u64,
+      ..Default::default()
+    }
+  }
+}
+
+/// Test pattern settings used by the `Synthetic` backend.
+#[napi(object)]
+pub struct SyntheticConfig {
+  pub width: Option<u32>,
+  pub height: Option<u32>,
+  /// Number of synthetic displays reported by `listDisplays` (default 1).
+  pub displays: Option<u32>,
+  /// Number of synthetic windows reported by `listWindows` (default 0).
+  pub windows: Option<u32>,
+  pub fail_on_start: Option<bool>,
+  pub fail_screenshot: Option<bool>,
+  pub fail_after_frames: Option<u32>,
+  /// Show every image for this many frames (default 1), like a mostly still screen.
+  pub hold_frames: Option<u32>,
+  /// Simulate a pointer moving over the test pattern (default false).
+  pub cursor: Option<bool>,
+  /// Swap the width and height of the displays from this frame on, as if they were rotated.
+  pub rotate_after_frames: Option<u32>,
+  /// Report one more display from this frame on, as if it was connected.
+  pub connect_after_frames: Option<u32>,
+}
+
+impl From<&SyntheticConfig> for SyntheticOptions {
+  fn from(cfg: &SyntheticConfig) -> Self {
+    let defaults = SyntheticOptions::default();
+    Self {
+      width: cfg.width.unwrap_or(defaults.width),
+      height: cfg.height.unwrap_or(defaults.height),
+      displays: cfg.displays.unwrap_or(defaults.displays),
+      windows: cfg.windows.unwrap_or(defaults.windows),
+      fail_on_start: cfg.fail_on_start.unwrap_or(false),
+      fail_screenshot: cfg.fail_screenshot.unwrap_or(false),
+      fail_after_frames: cfg.fail_after_frames.map(u64::from),
+      hold_frames: cfg.hold_frames.map_or(defaults.hold_frames, u64::from),
+      cursor: cfg.cursor.unwrap_or(defaults.cursor),
+      rotate_after_frames: cfg.rotate_after_frames.map(u64::from),
+      connect_after_frames: cfg.connect_after_frames.map(u64::from),
+    }
+  }
 }
 
 #[napi(object)]
 pub struct ScreenCaptureConfig {
-  pub backend: Option<CaptureBackend>, // "ScreenCaptureKit" | "xcap"
+  pub backend: Option<CaptureBackend>, // "ScreenCaptureKit" | "xcap" | "Synthetic" | "X11"
+  pub fps: Option<u32>,
+  /// Display to capture, as returned by `listDisplays`. Defaults to the primary display.
+  pub display_id: Option<u32>,
+  /// Window to capture instead of a display, as returned by `listWindows`.
+  pub window_id: Option<u32>,
+  /// Capture several displays in one session. Each frame shows one display, named by
+  /// `FrameData.displayId`, unless `stitch` is set.
+  pub display_ids: Option<Vec<u32>>,
+  /// Deliver the displays of `displayIds` as one frame laid out like the virtual desktop.
+  pub stitch: Option<bool>,
+  /// Only capture this part of the display or window.
+  pub region: Option<Region>,
+  /// Scale frames to this width. When only one of `outputWidth`/`outputHeight` is set the other follows the aspect ratio.
+  pub output_width: Option<u32>,
+  pub output_height: Option<u32>,
+  /// Scale frames down to at most this width, keeping the aspect ratio.
+  pub max_width: Option<u32>,
+  /// Resampling filter for CPU scaling (default `Bilinear`).
+  pub scale_filter: Option<ScaleFilter>,
+  /// Layout of delivered frames (default `RGBA`).
+  pub pixel_format: Option<PixelFormat>,
+  /// YUV matrix for `I420`/`NV12` (default `BT601`).
+  pub color_matrix: Option<ColorMatrix>,
+  /// YUV range for `I420`/`NV12` (default `Limited`).
+  pub color_range: Option<ColorRange>,
+  /// Deliver compressed images instead of raw pixels. Cannot be combined with `pixelFormat`.
+  pub encode: Option<EncodeConfig>,
+  /// What to do with new frames while the callback or an iterator is behind (default `dropOldest`).
+  pub backpressure: Option<Backpressure>,
+  /// Frames that may wait for each consumer before `backpressure` applies (default 4).
+  pub max_queued_frames: Option<u32>,
+  /// Keep the last frames of the session in memory for `saveReplay()`.
+  pub replay: Option<ReplayConfig>,
+  /// Report the parts of each frame that changed in `FrameData.dirtyRects`.
+  pub dirty_rects: Option<bool>,
+  /// Only deliver frames whose content changed.
+  pub emit_only_on_change: Option<bool>,
+  /// With `emitOnlyOnChange`, repeat the last frame when none was delivered for this many milliseconds.
+  pub keepalive_ms: Option<u32>,
+  /// Deliver delta packets of the changed tiles instead of frames, for `DeltaDecoder`. Cannot be
+  /// combined with `pixelFormat` or `encode`.
+  pub delta: Option<DeltaConfig>,
+  /// Include the pointer in the frames (default true). Ignored by the `XCap` backend and GDI
+  /// fallback, which capture whatever the system draws.
+  pub show_cursor: Option<bool>,
+  /// Report the pointer position and shape in `FrameData.cursor`, so viewers can draw it
+  /// themselves. Combine with `showCursor: false` to keep it out of the pixels.
+  pub cursor_metadata: Option<bool>,
+  pub synthetic: Option<SyntheticConfig>,
+}
+
+/// Settings `updateConfig` changes on a running session. Omitted fields keep their value.
+#[napi(object)]
+pub struct CaptureConfigUpdate {
   pub fps: Option<u32>,
+  /// `null` captures the whole display or window again.
+  pub region: Option<Option<Region>>,
+  /// `null` removes the limit, like the other output sizes.
+  pub output_width: Option<Option<u32>>,
+  pub output_height: Option<Option<u32>>,
+  pub max_width: Option<Option<u32>>,
+  pub scale_filter: Option<ScaleFilter>,
+  /// Also switches back to raw frames when `encode` was set.
+  pub pixel_format: Option<PixelFormat>,
+  pub color_matrix: Option<ColorMatrix>,
+  pub color_range: Option<ColorRange>,
+  /// `null` switches back to raw frames.
+  pub encode: Option<Option<EncodeConfig>>,
 }
 
 #[napi]
 pub struct ScreenCapture {
-  backend: Arc<StdMutex<Option<Box<dyn CaptureBackendImpl>>>>,
-  tsfn: Option<FrameTsfnType>,
-  fps: u32,
+  capturer: Arc<Capturer>,
 }
 
 #[napi]
@@ -64,130 +930,450 @@ impl ScreenCapture {
       }
     }
 
-    let tsfn = if let Some(func) = callback_func {
+    let builder = capturer_builder(config_obj.as_ref())?;
+    let options = builder.options()?;
+    let backend = builder.create_backend(options.clone())?;
+
+    let callback = if let Some(func) = callback_func {
+      let queue = FrameQueue::new(options.queue);
+      let pending = queue.clone();
       let func_casted: Function<(), ()> = unsafe { std::mem::transmute(func) };
-      Some(Arc::new(
-        func_casted
-          .build_threadsafe_function::<FrameDataInternal>()
-          .build_callback(|ctx| {
-            let frame: FrameDataInternal = ctx.value;
-            let mut js_obj = Object::new(&ctx.env)?;
-
-            js_obj.set_named_property("width", frame.width)?;
-            js_obj.set_named_property("height", frame.height)?;
-            js_obj.set_named_property("stride", frame.stride)?;
-
-            let buf = Buffer::from(frame.data);
-            js_obj.set_named_property("rgba", buf)?;
-            Ok(js_obj.raw())
-          })?,
-      ))
+      let tsfn = func_casted
+        .build_threadsafe_function::<()>()
+        .build_callback(move |ctx| {
+          let frame = pending.pop().ok_or_else(|| {
+            Error::new(Status::GenericFailure, "Frame queue is empty".to_string())
+          })?;
+          unsafe { FrameData::to_napi_value(ctx.env.raw(), frame.into()) }
+        })?;
+      let notify = move || {
+        tsfn
+          .call((), ThreadsafeFunctionCallMode::NonBlocking)
+          .into()
+      };
+      Some(FrameCallback {
+        notify: Box::new(notify),
+        queue,
+      })
     } else {
       None
     };
 
-    let mut backend_enum = None;
-    let mut fps = 60;
-
-    if let Some(cfg) = &config_obj {
-      backend_enum = cfg.backend;
-      if let Some(f) = cfg.fps {
-        fps = f;
-      }
-    }
-
-    let backend: Box<dyn CaptureBackendImpl> = match backend_enum {
-      Some(CaptureBackend::ScreenCaptureKit) => {
-        #[cfg(target_os = "macos")]
-        {
-          Box::new(SCKBackend::new())
-        }
-        #[cfg(not(target_os = "macos"))]
-        {
-          Box::new(XCapBackend::new())
-        }
-      }
-      Some(CaptureBackend::XCap) => Box::new(XCapBackend::new()),
-      None => {
-        #[cfg(target_os = "macos")]
-        {
-          Box::new(SCKBackend::new())
-        }
-        #[cfg(target_os = "windows")]
-        {
-          Box::new(WindowsBackend::new())
-        }
-        #[cfg(not(any(target_os = "macos", target_os = "windows")))]
-        {
-          Box::new(XCapBackend::new())
-        }
-      }
-    };
-
     Ok(ScreenCapture {
-      backend: Arc::new(StdMutex::new(Some(backend))),
-      tsfn,
-      fps,
+      capturer: Arc::new(Capturer::new(backend, options, callback)),
     })
   }
 
+  /// Lists the displays available to the configured backend.
+  #[napi]
+  pub async fn list_displays(config: Option<ScreenCaptureConfig>) -> Result<Vec<DisplayInfo>> {
+    let displays = capturer_builder(config.as_ref())?.list_displays().await?;
+    Ok(displays.into_iter().map(DisplayInfo::from).collect())
+  }
+
+  /// Lists the windows that can be captured with `windowId`.
+  #[napi]
+  pub async fn list_windows(config: Option<ScreenCaptureConfig>) -> Result<Vec<WindowInfo>> {
+    let windows = capturer_builder(config.as_ref())?.list_windows().await?;
+    Ok(windows.into_iter().map(WindowInfo::from).collect())
+  }
+
   #[napi]
   pub async fn start(&self) -> Result<()> {
-    let backend_opt = {
-      let mut backend_guard = self.backend.lock().unwrap();
-      backend_guard.take()
+    Ok(self.capturer.start().await?)
+  }
+
+  /// Stops capturing. Open `frames()` iterators finish after their queued frames.
+  #[napi]
+  pub fn stop(&self) -> Result<()> {
+    Ok(self.capturer.stop()?)
+  }
+
+  /// Stops delivering frames while keeping the stream open, so `resume()` continues right away.
+  /// Frames already waiting for the callback or an iterator are still delivered.
+  #[napi]
+  pub fn pause(&self) -> Result<()> {
+    Ok(self.capturer.pause()?)
+  }
+
+  /// Delivers frames again after `pause()`.
+  #[napi]
+  pub fn resume(&self) -> Result<()> {
+    Ok(self.capturer.resume()?)
+  }
+
+  /// Changes the frame rate, region or output settings. A running session applies them from its
+  /// next frame without restarting the stream.
+  #[napi]
+  pub fn update_config(&self, config: CaptureConfigUpdate) -> Result<()> {
+    let update = ConfigUpdate {
+      fps: config.fps,
+      region: config
+        .region
+        .map(|region| region.as_ref().map(RegionInternal::from)),
+      output_width: config.output_width,
+      output_height: config.output_height,
+      max_width: config.max_width,
+      scale_filter: config.scale_filter.map(Into::into),
+      pixel_format: config.pixel_format.map(Into::into),
+      color_matrix: config.color_matrix.map(Into::into),
+      color_range: config.color_range.map(Into::into),
+      encode: config
+        .encode
+        .map(|encode| encode.as_ref().map(EncodeOptions::try_from).transpose())
+        .transpose()?,
     };
+    Ok(self.capturer.update_config(update)?)
+  }
 
-    if let Some(mut backend) = backend_opt {
-      let result = backend.start(self.tsfn.clone(), self.fps).await;
+  /// Current state of the capture session.
+  #[napi(getter)]
+  pub fn state(&self) -> CaptureState {
+    self.capturer.state().into()
+  }
 
-      let mut backend_guard = self.backend.lock().unwrap();
-      *backend_guard = Some(backend);
+  /// Adds a listener for errors on the capture threads. Errors reported before the first
+  /// listener was added, such as a backend fallback in the constructor, are delivered to it.
+  #[napi(ts_args_type = "callback: (error: CaptureError) => void")]
+  pub fn on_error(&self, callback: Function<CaptureError, ()>) -> Result<()> {
+    let tsfn = callback
+      .build_threadsafe_function()
+      .callee_handled::<false>()
+      .weak::<true>()
+      .build()?;
+    self.capturer.on_error(move |error| {
+      tsfn.call(error.into(), ThreadsafeFunctionCallMode::NonBlocking);
+    });
+    Ok(())
+  }
 
-      result
-    } else {
-      Err(Error::new(
-        Status::GenericFailure,
-        "Backend is missing".to_string(),
-      ))
-    }
+  /// Adds a listener called whenever the session moves to another `CaptureState`.
+  #[napi(ts_args_type = "callback: (state: CaptureState) => void")]
+  pub fn on_state_change(&self, callback: Function<CaptureState, ()>) -> Result<()> {
+    let tsfn = callback
+      .build_threadsafe_function()
+      .callee_handled::<false>()
+      .weak::<true>()
+      .build()?;
+    self.capturer.on_state_change(move |state| {
+      tsfn.call(state.into(), ThreadsafeFunctionCallMode::NonBlocking);
+    });
+    Ok(())
   }
 
+  /// Adds a listener for display changes the session adapted to: displays connected,
+  /// disconnected or moved, and mode changes of the captured display. Window captures report
+  /// none, and stitched frames keep the layout the session started with.
+  #[napi(ts_args_type = "callback: (change: DisplayChange) => void")]
+  pub fn on_display_change(&self, callback: Function<DisplayChange, ()>) -> Result<()> {
+    let tsfn = callback
+      .build_threadsafe_function()
+      .callee_handled::<false>()
+      .weak::<true>()
+      .build()?;
+    self.capturer.on_display_change(move |change| {
+      tsfn.call(change.into(), ThreadsafeFunctionCallMode::NonBlocking);
+    });
+    Ok(())
+  }
+
+  /// Pulls frames with `for await`. Each iterator queues up to `maxQueuedFrames` frames and
+  /// applies `backpressure` when it falls behind. Works with or without a frame callback.
   #[napi]
-  pub fn stop(&self) -> Result<()> {
-    let mut backend_guard = self.backend.lock().unwrap();
-    if let Some(backend) = backend_guard.as_mut() {
-      backend.stop()
-    } else {
-      Ok(())
+  pub fn frames(&self) -> FrameIterator {
+    FrameIterator {
+      stream: self.frame_stream(),
     }
   }
 
+  /// Rust counterpart of `frames()`.
+  pub fn frame_stream(&self) -> FrameStream {
+    self.capturer.frames()
+  }
+
+  /// Frame counters, including frames dropped by the backpressure policy.
+  #[napi]
+  pub fn stats(&self) -> CaptureStats {
+    self.capturer.stats().into()
+  }
+
+  /// Writes the frames of the session to `path` until `stopRecording()` or `stop()`. Frames are
+  /// recorded after scaling but before `pixelFormat` and `encode`, and timed by their timestamps.
+  #[napi]
+  pub fn start_recording(&self, path: String, config: Option<RecordingConfig>) -> Result<()> {
+    let format = config.as_ref().and_then(|cfg| cfg.format).map(Into::into);
+    let fps = config.as_ref().and_then(|cfg| cfg.fps);
+    Ok(self.capturer.start_recording(path, format, fps)?)
+  }
+
+  /// Finishes the file of `startRecording()`, including the frames still queued for it.
+  #[napi]
+  pub async fn stop_recording(&self) -> Result<RecordingSummary> {
+    let capturer = self.capturer.clone();
+    let summary = blocking(move || capturer.stop_recording()).await?;
+    Ok(summary.into())
+  }
+
+  /// Captures the next `durationMs` of the session, or takes the last ones from the replay buffer
+  /// with `fromReplay`, and resolves with a looping GIF or APNG. Frames are taken after scaling
+  /// but before `pixelFormat` and `encode`.
   #[napi]
-  pub async fn screenshot(&self) -> Result<FrameData> {
-    let backend_opt = {
-      let mut backend_guard = self.backend.lock().unwrap();
-      backend_guard.take()
+  pub async fn export_clip(&self, config: ClipConfig) -> Result<Buffer> {
+    let options = ClipOptions {
+      format: config.format.map(Into::into).unwrap_or_default(),
+      duration: Duration::from_millis(config.duration_ms as u64),
+      fps: config.fps.unwrap_or(ClipOptions::DEFAULT_FPS),
+      scale: config.scale.unwrap_or(1.0),
+      from_replay: config.from_replay.unwrap_or(false),
     };
+    let capturer = self.capturer.clone();
+    let image = blocking(move || capturer.export_clip(options)).await?;
+    Ok(image.into())
+  }
+
+  /// Returns the frames in the replay buffer, oldest first, or writes them to `path` like
+  /// `startRecording()` and returns what was written. The buffer keeps its frames.
+  #[napi]
+  pub async fn save_replay(
+    &self,
+    path: Option<String>,
+  ) -> Result<Either<Vec<FrameData>, RecordingSummary>> {
+    let Some(path) = path else {
+      let frames = self.capturer.buffered_replay()?.into_iter();
+      return Ok(Either::A(
+        frames
+          .map(Arc::unwrap_or_clone)
+          .map(FrameData::from)
+          .collect(),
+      ));
+    };
+    let capturer = self.capturer.clone();
+    let summary = blocking(move || capturer.save_replay(path)).await?;
+    Ok(Either::B(summary.into()))
+  }
 
-    if let Some(mut backend) = backend_opt {
-      let result = backend.screenshot().await;
+  /// Makes the next packet of the `delta` option a keyframe, for example when a viewer joins or
+  /// lost a packet. Packets lost to `backpressure` trigger one on their own.
+  #[napi]
+  pub fn request_keyframe(&self) -> Result<()> {
+    Ok(self.capturer.request_keyframe()?)
+  }
 
-      let mut backend_guard = self.backend.lock().unwrap();
-      *backend_guard = Some(backend);
+  /// Moves the capture region, or captures the whole display again when `region` is omitted.
+  /// Takes effect on the next frame of a running session.
+  #[napi]
+  pub fn set_region(&self, region: Option<Region>) -> Result<()> {
+    let region = region.as_ref().map(RegionInternal::from);
+    Ok(self.capturer.set_region(region)?)
+  }
 
-      let frame = result?;
-      Ok(FrameData {
-        width: frame.width,
-        height: frame.height,
-        stride: frame.stride,
-        rgba: frame.data.into(),
-      })
-    } else {
-      Err(Error::new(
-        Status::GenericFailure,
-        "Backend is busy or missing".to_string(),
-      ))
+  /// Captures a single frame. With `options` the frame is compressed and only the image is returned.
+  #[napi]
+  pub async fn screenshot(
+    &self,
+    options: Option<EncodeConfig>,
+  ) -> Result<Either<FrameData, Buffer>> {
+    let encode_options = options.as_ref().map(EncodeOptions::try_from).transpose()?;
+    let frame = self.capturer.capture_frame().await?;
+    let output = self.capturer.options.settings.output();
+    if let Some(encode_options) = encode_options {
+      let image = encode::encode(output.resize(frame), encode_options)?;
+      return Ok(Either::B(image.data.into()));
+    }
+
+    let frame = output.process(frame)?;
+    Ok(Either::A(frame.into()))
+  }
+}
+
+/// Frames of a capture session, returned by `ScreenCapture.frames()`.
+#[napi(async_iterator)]
+pub struct FrameIterator {
+  stream: FrameStream,
+}
+
+#[napi]
+impl AsyncGenerator for FrameIterator {
+  type Yield = FrameData;
+  type Next = ();
+  type Return = ();
+
+  fn next(
+    &mut self,
+    _value: Option<()>,
+  ) -> impl Future<Output = Result<Option<FrameData>>> + Send + 'static {
+    let frame = self.stream.next_frame();
+    async move { Ok(frame.await.map(FrameData::from)) }
+  }
+
+  /// Called when a `for await` loop exits early.
+  fn complete(
+    &mut self,
+    _value: Option<()>,
+  ) -> impl Future<Output = Result<Option<FrameData>>> + Send + 'static {
+    self.stream.close();
+    async { Ok(None) }
+  }
+}
+
+/// Turns frames into delta packets without capturing, for example to stream frames from
+/// another source.
+#[napi]
+pub struct DeltaEncoder {
+  encoder: DeltaEncoderInternal,
+}
+
+#[napi]
+impl DeltaEncoder {
+  #[napi(constructor)]
+  pub fn new(config: Option<DeltaConfig>) -> Result<Self> {
+    let options = match &config {
+      Some(cfg) => DeltaOptions::try_from(cfg)?,
+      None => DeltaOptions::default(),
+    };
+    Ok(Self {
+      encoder: DeltaEncoderInternal::new(options),
+    })
+  }
+
+  /// Encodes the tiles that changed since the previous frame. The first packet, packets after a
+  /// size change and packets with `keyframe` set hold every tile.
+  #[napi]
+  pub fn encode(&mut self, frame: RawFrame, keyframe: Option<bool>) -> Result<Buffer> {
+    if keyframe == Some(true) {
+      self.encoder.request_keyframe();
     }
+    let packet = self.encoder.encode(FrameDataInternal::from(&frame))?;
+    Ok(packet.data.into())
   }
+
+  /// Makes the next packet a keyframe.
+  #[napi]
+  pub fn request_keyframe(&mut self) {
+    self.encoder.request_keyframe();
+  }
+}
+
+/// Rebuilds frames from delta packets of `DeltaEncoder` or the `delta` option.
+#[napi]
+pub struct DeltaDecoder {
+  decoder: DeltaDecoderInternal,
+}
+
+#[napi]
+impl DeltaDecoder {
+  #[napi(constructor)]
+  #[allow(clippy::new_without_default)]
+  pub fn new() -> Self {
+    Self {
+      decoder: DeltaDecoderInternal::default(),
+    }
+  }
+
+  /// Applies a packet and returns the whole frame as RGBA, with the tiles it updated in
+  /// `dirtyRects`. Throws for a packet that does not follow the previous one; after that only
+  /// a keyframe is accepted.
+  #[napi]
+  pub fn decode(&mut self, packet: Buffer) -> Result<FrameData> {
+    Ok(self.decoder.decode(&packet)?.into())
+  }
+
+  /// Forgets the current frame, so the next packet must be a keyframe.
+  #[napi]
+  pub fn reset(&mut self) {
+    self.decoder.reset();
+  }
+}
+
+fn capturer_builder(config: Option<&ScreenCaptureConfig>) -> Result<CapturerBuilder> {
+  let mut builder = CapturerBuilder::new();
+  let Some(cfg) = config else {
+    return Ok(builder);
+  };
+  if let Some(backend) = cfg.backend {
+    builder = builder.backend(backend.into());
+  }
+  if let Some(synthetic) = &cfg.synthetic {
+    builder = builder.synthetic(synthetic.into());
+  }
+  if let Some(fps) = cfg.fps {
+    builder = builder.fps(fps);
+  }
+  if let Some(id) = cfg.display_id {
+    builder = builder.display(id);
+  }
+  if let Some(id) = cfg.window_id {
+    builder = builder.window(id);
+  }
+  if let Some(ids) = &cfg.display_ids {
+    builder = builder.displays(ids.iter().copied());
+  }
+  if let Some(stitch) = cfg.stitch {
+    builder = builder.stitch(stitch);
+  }
+  if let Some(region) = &cfg.region {
+    builder = builder.region(region.into());
+  }
+  if let Some(width) = cfg.output_width {
+    builder = builder.output_width(width);
+  }
+  if let Some(height) = cfg.output_height {
+    builder = builder.output_height(height);
+  }
+  if let Some(width) = cfg.max_width {
+    builder = builder.max_width(width);
+  }
+  if let Some(filter) = cfg.scale_filter {
+    builder = builder.scale_filter(filter.into());
+  }
+  if let Some(format) = cfg.pixel_format {
+    builder = builder.pixel_format(format.into());
+  }
+  if let Some(matrix) = cfg.color_matrix {
+    builder = builder.color_matrix(matrix.into());
+  }
+  if let Some(range) = cfg.color_range {
+    builder = builder.color_range(range.into());
+  }
+  if let Some(encode) = &cfg.encode {
+    builder = builder.encode(encode.try_into()?);
+  }
+  if let Some(backpressure) = cfg.backpressure {
+    builder = builder.backpressure(backpressure.into());
+  }
+  if let Some(frames) = cfg.max_queued_frames {
+    builder = builder.max_queued_frames(frames as usize);
+  }
+  if let Some(replay) = &cfg.replay {
+    builder = builder.replay(replay.try_into()?);
+  }
+  if let Some(dirty_rects) = cfg.dirty_rects {
+    builder = builder.dirty_rects(dirty_rects);
+  }
+  if let Some(enabled) = cfg.emit_only_on_change {
+    builder = builder.emit_only_on_change(enabled);
+  }
+  if let Some(ms) = cfg.keepalive_ms {
+    builder = builder.keepalive(Duration::from_millis(ms as u64));
+  }
+  if let Some(delta) = &cfg.delta {
+    builder = builder.delta(delta.try_into()?);
+  }
+  if let Some(show) = cfg.show_cursor {
+    builder = builder.show_cursor(show);
+  }
+  if let Some(enabled) = cfg.cursor_metadata {
+    builder = builder.cursor_metadata(enabled);
+  }
+  Ok(builder)
+}
+
+/// Runs a blocking `Capturer` call on the blocking threads of the runtime.
+async fn blocking<T: Send + 'static>(
+  call: impl FnOnce() -> crate::error::Result<T> + Send + 'static,
+) -> Result<T> {
+  let result = tokio::task::spawn_blocking(call)
+    .await
+    .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+  Ok(result?)
 }